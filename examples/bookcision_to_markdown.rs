@@ -13,7 +13,7 @@ pub fn main() {
 
     let markdown_file =
         File::create("target/default_markdown.md").expect("cannot create output file");
-    let mut renderer = MarkdownRenderer::default();
+    let mut renderer = MarkdownRenderer;
     renderer
         .render(&book, markdown_file)
         .expect("cannot render markdown document")
@@ -1,6 +1,7 @@
 //! Common errors.
 use std::error::Error;
 use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
 
 /// Common error for highlight converter.
 #[derive(Debug)]
@@ -11,6 +12,22 @@ pub enum HighlightError {
     IOError(String, std::io::Error),
     /// Broken format of the input highlights.
     InvalidFormat(String, std::io::Error),
+    /// Broken input at the known position.
+    Parse(ParseError),
+}
+
+/// Position and context of the broken input.
+///
+/// Readers know the line and column of the problem,
+/// the source path and the offending line are attached when the input is read from the file.
+#[derive(Debug)]
+pub struct ParseError {
+    message: String,
+    reason: String,
+    path: Option<PathBuf>,
+    line: usize,
+    column: usize,
+    snippet: Option<String>,
 }
 
 impl HighlightError {
@@ -23,6 +40,103 @@ impl HighlightError {
     pub fn format(message: impl Into<String>, io_error: std::io::Error) -> Self {
         HighlightError::InvalidFormat(message.into(), io_error)
     }
+
+    /// Convenience constructor for the parse error at the 1-based line and column.
+    pub fn parse(
+        message: impl Into<String>,
+        reason: impl Into<String>,
+        line: usize,
+        column: usize,
+    ) -> Self {
+        HighlightError::Parse(ParseError {
+            message: message.into(),
+            reason: reason.into(),
+            path: None,
+            line: line.max(1),
+            column: column.max(1),
+            snippet: None,
+        })
+    }
+
+    /// Attaches the source path and the offending line to the parse error.
+    ///
+    /// Other errors are returned unchanged.
+    pub fn with_source(self, path: Option<&Path>, content: &[u8]) -> Self {
+        match self {
+            HighlightError::Parse(mut error) => {
+                if error.path.is_none() {
+                    error.path = path.map(Path::to_path_buf);
+                }
+                if error.snippet.is_none() {
+                    error.snippet = String::from_utf8_lossy(content)
+                        .lines()
+                        .nth(error.line.saturating_sub(1))
+                        .map(|line| line.trim_end().to_owned());
+                }
+                HighlightError::Parse(error)
+            }
+            error => error,
+        }
+    }
+}
+
+impl ParseError {
+    /// What was being read, e.g. `invalid bookcision json file`.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// What is wrong at the position.
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    /// Path of the input file, if known.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Line of the problem, starting from 1.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Column of the problem, starting from 1.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// The offending line of the input, if known.
+    pub fn snippet(&self) -> Option<&str> {
+        self.snippet.as_deref()
+    }
+}
+
+impl Display for ParseError {
+    /// Formats the error like a compiler diagnostic with the caret under the problem.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let path = match &self.path {
+            Some(path) => path.display().to_string(),
+            None => "<input>".to_owned(),
+        };
+        let gutter = " ".repeat(self.line.to_string().len());
+        writeln!(f, "{}", self.message)?;
+        writeln!(f, "{}--> {}:{}:{}", gutter, path, self.line, self.column)?;
+        match &self.snippet {
+            Some(snippet) => {
+                // Keep tabs in the padding, so the caret lines up with the snippet.
+                let padding: String = snippet
+                    .chars()
+                    .take(self.column.saturating_sub(1))
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect();
+                writeln!(f, "{} |", gutter)?;
+                writeln!(f, "{} | {}", self.line, snippet)?;
+                write!(f, "{} | {}^ {}", gutter, padding, self.reason)
+            }
+            None => write!(f, "{} = {}", gutter, self.reason),
+        }
+    }
 }
 
 impl Display for HighlightError {
@@ -35,6 +149,7 @@ impl Display for HighlightError {
             HighlightError::InvalidFormat(message, err) => {
                 f.write_fmt(format_args!("{}\n\t{}", message, err))
             }
+            HighlightError::Parse(err) => err.fmt(f),
         }
     }
 }
@@ -44,7 +159,7 @@ impl Error for HighlightError {
         match self {
             HighlightError::IOError(_, err) => Some(err),
             HighlightError::InvalidFormat(_, err) => Some(err),
-            HighlightError::General(_) => None,
+            HighlightError::General(_) | HighlightError::Parse(_) => None,
         }
     }
 }
@@ -54,3 +169,34 @@ impl Default for HighlightError {
         HighlightError::General("unknown error".to_owned())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_parse_diagnostic() {
+        let content = b"{\n  \"text\": null,\n}";
+        let error = HighlightError::parse("invalid json file", "expected a string", 2, 11)
+            .with_source(Some(Path::new("notes.json")), content);
+
+        assert_eq!(
+            "invalid json file\n \
+             --> notes.json:2:11\n  \
+             |\n\
+             2 |   \"text\": null,\n  \
+             |           ^ expected a string",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn render_parse_diagnostic_without_source() {
+        let error = HighlightError::parse("invalid json file", "expected a string", 12, 1);
+
+        assert_eq!(
+            "invalid json file\n  --> <input>:12:1\n   = expected a string",
+            error.to_string()
+        );
+    }
+}
@@ -0,0 +1,473 @@
+//! Merging of duplicate and overlapping highlights.
+//!
+//! Re-highlighting a passage on Kindle produces near-duplicate entries,
+//! and clippings files keep every version of the highlight.
+use std::fmt::{Display, Formatter};
+
+use crate::highlights::filter::Kind;
+use crate::highlights::{Book, Highlight};
+
+/// Highlights at most this number of locations apart are adjacent.
+pub const ADJACENT_LOCATIONS: usize = 2;
+
+/// Number of words of the highlight text shown in the merge report.
+const EXCERPT_WORDS: usize = 8;
+
+/// How the highlight was merged.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MergeKind {
+    /// Exact duplicate of another highlight was removed.
+    Duplicate,
+    /// Quote starting or ending the quote at an adjacent location was merged into the longer one.
+    Overlap,
+    /// Note without a quote was attached to the quote at an adjacent location.
+    Note,
+}
+
+/// Highlight merged into another one by [`dedup`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Merge {
+    kind: MergeKind,
+    location: usize,
+    excerpt: String,
+}
+
+impl Merge {
+    fn new(kind: MergeKind, highlight: &Highlight) -> Self {
+        let words: Vec<&str> = texts(highlight)
+            .flat_map(|text| text.split_whitespace())
+            .collect();
+        let excerpt = if words.len() > EXCERPT_WORDS {
+            format!("{}…", words[..EXCERPT_WORDS].join(" "))
+        } else {
+            words.join(" ")
+        };
+        Merge {
+            kind,
+            location: highlight.location().value(),
+            excerpt,
+        }
+    }
+
+    /// How the highlight was merged.
+    pub fn kind(&self) -> MergeKind {
+        self.kind
+    }
+
+    /// Location value of the merged highlight.
+    pub fn location(&self) -> usize {
+        self.location
+    }
+
+    /// First words of the merged highlight.
+    pub fn excerpt(&self) -> &str {
+        &self.excerpt
+    }
+}
+
+impl Display for Merge {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "location {}: ", self.location)?;
+        match self.kind {
+            MergeKind::Duplicate => write!(f, "removed duplicate \"{}\"", self.excerpt),
+            MergeKind::Overlap => write!(
+                f,
+                "merged overlapping quote \"{}\" into the longer one",
+                self.excerpt
+            ),
+            MergeKind::Note => write!(f, "attached note \"{}\" to the quote", self.excerpt),
+        }
+    }
+}
+
+/// Merges duplicate and overlapping highlights of the book.
+///
+/// - Highlights of the same kind with the same text and location are collapsed into one.
+/// - A quote starting or ending the quote at an adjacent location is merged into the longer quote,
+///   keeping the notes of both.
+/// - A note at an adjacent location to a quote without a note becomes the comment on the quote.
+///
+/// Highlights with location 0, the unknown one, are only merged when they are exact duplicates.
+/// Merged highlights keep the tags of both and the earliest creation time.
+/// Returns the book with the merged highlights and the report of every merge.
+///
+/// ## Example:
+///
+/// ```
+/// # use highlights::highlights::dedup::dedup;
+/// # use highlights::highlights::{Book, Highlight, Location};
+/// let book = Book::new(
+///     "Title",
+///     "Author",
+///     [
+///         Highlight::quote("Chess is", Location::new(157, "")),
+///         Highlight::quote("Chess is life", Location::new(157, "")),
+///         Highlight::note("Fischer", Location::new(158, "")),
+///     ],
+/// );
+///
+/// let (book, merges) = dedup(book);
+/// assert_eq!(
+///     vec![Highlight::comment("Chess is life", "Fischer", Location::new(157, ""))],
+///     book.highlights()
+/// );
+/// assert_eq!(2, merges.len());
+/// ```
+pub fn dedup(mut book: Book) -> (Book, Vec<Merge>) {
+    let mut merges = vec![];
+    let mut kept: Vec<Highlight> = Vec::with_capacity(book.highlights.len());
+    for highlight in std::mem::take(&mut book.highlights) {
+        if let Some(index) = kept
+            .iter()
+            .position(|known| is_duplicate(known, &highlight))
+        {
+            merges.push(Merge::new(MergeKind::Duplicate, &highlight));
+            let known = kept.remove(index);
+            kept.insert(index, with_details_of(known, &highlight));
+        } else if let Some(index) = kept.iter().position(|known| overlaps(known, &highlight)) {
+            let known = kept.remove(index);
+            kept.insert(index, merge_quotes(known, highlight, &mut merges));
+        } else {
+            kept.push(highlight);
+        }
+    }
+
+    let mut index = 0;
+    while index < kept.len() {
+        match nearest_quote(&kept, index) {
+            Some(quote_index) => {
+                let note = kept.remove(index);
+                let quote_index = if quote_index > index {
+                    quote_index - 1
+                } else {
+                    quote_index
+                };
+                merges.push(Merge::new(MergeKind::Note, &note));
+                let quote = kept.remove(quote_index);
+                kept.insert(quote_index, attach_note(quote, &note));
+            }
+            None => index += 1,
+        }
+    }
+
+    book.highlights = kept;
+    (book, merges)
+}
+
+fn is_duplicate(known: &Highlight, highlight: &Highlight) -> bool {
+    Kind::of(known) == Kind::of(highlight)
+        && known.location().value() == highlight.location().value()
+        && texts(known)
+            .map(normalize)
+            .eq(texts(highlight).map(normalize))
+}
+
+/// Checks if one quote starts or ends the other one at an adjacent location, word by word.
+///
+/// Re-highlighting extends the passage at either end, while a short quote
+/// somewhere inside a longer one is a separate highlight.
+fn overlaps(known: &Highlight, highlight: &Highlight) -> bool {
+    match (quote(known), quote(highlight)) {
+        (Some(known_quote), Some(quote)) if adjacent(known, highlight) => {
+            let known_words: Vec<&str> = known_quote.split_whitespace().collect();
+            let words: Vec<&str> = quote.split_whitespace().collect();
+            let (shorter, longer) = if words.len() < known_words.len() {
+                (&words, &known_words)
+            } else {
+                (&known_words, &words)
+            };
+            !shorter.is_empty()
+                && (longer.starts_with(shorter.as_slice()) || longer.ends_with(shorter.as_slice()))
+        }
+        _ => false,
+    }
+}
+
+/// Quote without a note at the adjacent location to the note, the closest one in the book first.
+fn nearest_quote(highlights: &[Highlight], note_index: usize) -> Option<usize> {
+    let note = &highlights[note_index];
+    if !matches!(note, Highlight::Note { .. }) {
+        return None;
+    }
+    highlights
+        .iter()
+        .enumerate()
+        .filter(|(_, highlight)| {
+            matches!(highlight, Highlight::Quote { .. }) && adjacent(highlight, note)
+        })
+        .min_by_key(|(index, highlight)| {
+            (
+                highlight
+                    .location()
+                    .value()
+                    .abs_diff(note.location().value()),
+                index.abs_diff(note_index),
+            )
+        })
+        .map(|(index, _)| index)
+}
+
+fn adjacent(first: &Highlight, second: &Highlight) -> bool {
+    let (first, second) = (first.location().value(), second.location().value());
+    first != 0 && second != 0 && first.abs_diff(second) <= ADJACENT_LOCATIONS
+}
+
+/// Keeps the longer quote with the notes of both highlights in the input order.
+fn merge_quotes(known: Highlight, highlight: Highlight, merges: &mut Vec<Merge>) -> Highlight {
+    let note = match (note(&known), note(&highlight)) {
+        (Some(note), Some(other)) if normalize(note) != normalize(other) => {
+            Some(format!("{}\n\n{}", note, other))
+        }
+        (Some(note), _) | (None, Some(note)) => Some(note.to_owned()),
+        (None, None) => None,
+    };
+    let (longer, shorter) = if quote_length(&highlight) > quote_length(&known) {
+        (highlight, known)
+    } else {
+        (known, highlight)
+    };
+    merges.push(Merge::new(MergeKind::Overlap, &shorter));
+    let merged = match (longer, note) {
+        (
+            Highlight::Quote {
+                quote,
+                location,
+                details,
+            }
+            | Highlight::Comment {
+                quote,
+                location,
+                details,
+                ..
+            },
+            Some(note),
+        ) => Highlight::Comment {
+            quote,
+            note,
+            location,
+            details,
+        },
+        (highlight, _) => highlight,
+    };
+    with_details_of(merged, &shorter)
+}
+
+fn attach_note(quote: Highlight, note: &Highlight) -> Highlight {
+    let commented = match (quote, self::note(note)) {
+        (
+            Highlight::Quote {
+                quote,
+                location,
+                details,
+            },
+            Some(note),
+        ) => Highlight::Comment {
+            quote,
+            note: note.to_owned(),
+            location,
+            details,
+        },
+        (highlight, _) => highlight,
+    };
+    with_details_of(commented, note)
+}
+
+/// Adds the tags, the earliest creation time and the latest modification time of the other highlight.
+fn with_details_of(mut highlight: Highlight, other: &Highlight) -> Highlight {
+    let details = highlight.details_mut();
+    let other_details = other.details();
+    details.created = match (details.created, other_details.created) {
+        (Some(created), Some(other)) => Some(created.min(other)),
+        (created, other) => created.or(other),
+    };
+    details.modified = details.modified.max(other_details.modified);
+    details.color = details.color.or(other_details.color);
+    highlight.with_tags(other.tags().to_vec())
+}
+
+fn quote(highlight: &Highlight) -> Option<&str> {
+    match highlight {
+        Highlight::Quote { quote, .. } | Highlight::Comment { quote, .. } => Some(quote),
+        Highlight::Note { .. } => None,
+    }
+}
+
+fn note(highlight: &Highlight) -> Option<&str> {
+    match highlight {
+        Highlight::Note { note, .. } | Highlight::Comment { note, .. } => Some(note),
+        Highlight::Quote { .. } => None,
+    }
+}
+
+fn quote_length(highlight: &Highlight) -> usize {
+    quote(highlight).map_or(0, |quote| normalize(quote).chars().count())
+}
+
+fn texts(highlight: &Highlight) -> impl Iterator<Item = &str> {
+    quote(highlight).into_iter().chain(note(highlight))
+}
+
+/// Text with the whitespace collapsed, so re-highlighted passages compare equal.
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::highlights::details::Timestamp;
+    use crate::highlights::Location;
+
+    fn dedup_highlights(highlights: Vec<Highlight>) -> (Vec<Highlight>, Vec<MergeKind>) {
+        let (book, merges) = dedup(Book::new("Title", "Author", highlights));
+        (book.highlights(), merges.iter().map(Merge::kind).collect())
+    }
+
+    #[test]
+    fn collapse_exact_duplicates() {
+        let created = Timestamp::new(2022, 10, 4, 10, 2, 44).unwrap();
+        let (highlights, merges) = dedup_highlights(vec![
+            Highlight::quote("Chess  is life", Location::new(10, "")).with_tags(["chess"]),
+            Highlight::quote("Other", Location::new(20, "")),
+            Highlight::quote("Chess is life", Location::new(10, "")).with_created(created),
+        ]);
+
+        assert_eq!(vec![MergeKind::Duplicate], merges);
+        assert_eq!(2, highlights.len());
+        assert_eq!(Some(created), highlights[0].created());
+        assert_eq!(["chess"], highlights[0].tags());
+    }
+
+    #[test]
+    fn keep_same_text_at_other_location() {
+        let (highlights, merges) = dedup_highlights(vec![
+            Highlight::quote("Check", Location::new(10, "")),
+            Highlight::quote("Check", Location::new(500, "")),
+        ]);
+
+        assert!(merges.is_empty());
+        assert_eq!(2, highlights.len());
+    }
+
+    #[test]
+    fn merge_overlapping_quotes_into_longest() {
+        let (highlights, merges) = dedup_highlights(vec![
+            Highlight::quote("Chess is", Location::new(10, "")),
+            Highlight::quote("Chess is life", Location::new(12, "")),
+            Highlight::comment("is life", "Indeed", Location::new(11, "")),
+            Highlight::quote("Chess is a game", Location::new(13, "")),
+        ]);
+
+        assert_eq!(vec![MergeKind::Overlap, MergeKind::Overlap], merges);
+        assert_eq!(
+            vec![
+                Highlight::comment("Chess is life", "Indeed", Location::new(12, "")),
+                Highlight::quote("Chess is a game", Location::new(13, "")),
+            ],
+            highlights
+        );
+    }
+
+    #[test]
+    fn keep_quotes_inside_longer_ones() {
+        let (highlights, merges) = dedup_highlights(vec![
+            Highlight::quote("we discard the reality of decisions", Location::new(10, "")),
+            Highlight::quote("the", Location::new(11, "")),
+            Highlight::quote("we disc", Location::new(11, "")),
+            Highlight::quote("we discard", Location::new(12, "")),
+        ]);
+
+        assert_eq!(vec![MergeKind::Overlap], merges);
+        assert_eq!(
+            vec![
+                Highlight::quote("we discard the reality of decisions", Location::new(10, "")),
+                Highlight::quote("the", Location::new(11, "")),
+                Highlight::quote("we disc", Location::new(11, "")),
+            ],
+            highlights
+        );
+    }
+
+    #[test]
+    fn keep_empty_quotes_apart() {
+        let (highlights, merges) = dedup_highlights(vec![
+            Highlight::quote("Chess is life", Location::new(10, "")),
+            Highlight::comment(" ", "Note", Location::new(10, "")),
+        ]);
+
+        assert!(merges.is_empty());
+        assert_eq!(2, highlights.len());
+    }
+
+    #[test]
+    fn keep_notes_of_both_quotes() {
+        let (highlights, _) = dedup_highlights(vec![
+            Highlight::comment("Chess", "First", Location::new(10, "")),
+            Highlight::comment("Chess is life", "Second", Location::new(10, "")),
+        ]);
+
+        assert_eq!(
+            vec![Highlight::comment(
+                "Chess is life",
+                "First\n\nSecond",
+                Location::new(10, "")
+            )],
+            highlights
+        );
+    }
+
+    #[test]
+    fn attach_orphan_note_to_nearest_quote() {
+        let (highlights, merges) = dedup_highlights(vec![
+            Highlight::note("Remember this", Location::new(21, "")),
+            Highlight::quote("Far", Location::new(19, "")),
+            Highlight::quote("Near", Location::new(20, "")),
+            Highlight::note("Lonely", Location::new(40, "")),
+        ]);
+
+        assert_eq!(vec![MergeKind::Note], merges);
+        assert_eq!(
+            vec![
+                Highlight::quote("Far", Location::new(19, "")),
+                Highlight::comment("Near", "Remember this", Location::new(20, "")),
+                Highlight::note("Lonely", Location::new(40, "")),
+            ],
+            highlights
+        );
+    }
+
+    #[test]
+    fn skip_unknown_locations() {
+        let (highlights, merges) = dedup_highlights(vec![
+            Highlight::quote("Chess is life", Location::new(0, "")),
+            Highlight::quote("Chess", Location::new(0, "")),
+            Highlight::note("Note", Location::new(0, "")),
+        ]);
+
+        assert!(merges.is_empty());
+        assert_eq!(3, highlights.len());
+    }
+
+    #[test]
+    fn report_merges() {
+        let (_, merges) = dedup(Book::new(
+            "Title",
+            "Author",
+            [
+                Highlight::quote(
+                    "One two three four five six seven eight nine",
+                    Location::new(5, ""),
+                ),
+                Highlight::quote(
+                    "One two three four five six seven eight nine",
+                    Location::new(5, ""),
+                ),
+            ],
+        ));
+
+        assert_eq!(
+            "location 5: removed duplicate \"One two three four five six seven eight…\"",
+            merges[0].to_string()
+        );
+    }
+}
@@ -0,0 +1,391 @@
+//! Optional details of highlights: when they were made, their color and tags.
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Seconds between the Unix epoch and 2001-01-01, the epoch of Apple timestamps.
+pub const APPLE_EPOCH: i64 = 978_307_200;
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Details of the highlight the source may or may not provide.
+///
+/// Serialized details are flattened into the highlight and skipped when empty.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Details {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) created: Option<Timestamp>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) modified: Option<Timestamp>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) color: Option<Color>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) tags: Vec<String>,
+}
+
+/// Highlight color.
+///
+/// Readers use different names for similar colors, so names are mapped to the closest color.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Color {
+    Yellow,
+    Orange,
+    Red,
+    Pink,
+    Purple,
+    Blue,
+    Green,
+    Gray,
+}
+
+/// Date and time of the highlight.
+///
+/// Timestamps with the UTC offset are converted to UTC, timestamps without one are kept as written.
+/// Serialized as `YYYY-MM-DDTHH:MM:SS`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Timestamp {
+    year: i32,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+}
+
+impl Color {
+    /// Finds the color by its name ignoring the case.
+    ///
+    /// ## Example:
+    ///
+    /// ```
+    /// # use highlights::highlights::details::Color;
+    /// assert_eq!(Some(Color::Gray), Color::parse("Grey"));
+    /// assert_eq!(None, Color::parse("underline"));
+    /// ```
+    pub fn parse(name: &str) -> Option<Self> {
+        let color = match name.trim().to_lowercase().as_str() {
+            "yellow" => Color::Yellow,
+            "orange" => Color::Orange,
+            "red" => Color::Red,
+            "pink" | "magenta" => Color::Pink,
+            "purple" | "violet" => Color::Purple,
+            "blue" | "cyan" => Color::Blue,
+            "green" | "olive" => Color::Green,
+            "gray" | "grey" => Color::Gray,
+            _ => return None,
+        };
+        Some(color)
+    }
+
+    /// Lowercase color name.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Color::Yellow => "yellow",
+            Color::Orange => "orange",
+            Color::Red => "red",
+            Color::Pink => "pink",
+            Color::Purple => "purple",
+            Color::Blue => "blue",
+            Color::Green => "green",
+            Color::Gray => "gray",
+        }
+    }
+}
+
+impl Display for Color {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl Timestamp {
+    /// Creates the timestamp checking the ranges of its parts.
+    pub fn new(
+        year: i32,
+        month: u32,
+        day: u32,
+        hour: u32,
+        minute: u32,
+        second: u32,
+    ) -> Option<Self> {
+        let valid = (1..=12).contains(&month)
+            && (1..=days_in_month(year, month)).contains(&day)
+            && hour < 24
+            && minute < 60
+            && second < 60;
+        valid.then_some(Timestamp {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        })
+    }
+
+    /// Creates the UTC timestamp from seconds since the Unix epoch.
+    pub fn from_unix(seconds: i64) -> Self {
+        let days = seconds.div_euclid(SECONDS_PER_DAY);
+        let time = seconds.rem_euclid(SECONDS_PER_DAY) as u32;
+        let (year, month, day) = civil_from_days(days);
+        Timestamp {
+            year,
+            month,
+            day,
+            hour: time / 3600,
+            minute: time / 60 % 60,
+            second: time % 60,
+        }
+    }
+
+    /// Seconds since the Unix epoch, treating timestamps without the offset as UTC.
+    pub fn unix(&self) -> i64 {
+        days_from_civil(self.year, self.month, self.day) * SECONDS_PER_DAY
+            + i64::from(self.hour * 3600 + self.minute * 60 + self.second)
+    }
+
+    /// Parses `YYYY-MM-DD`, `YYYY-MM-DD HH:MM[:SS]` and RFC 3339 timestamps.
+    ///
+    /// Fractions of a second are dropped.
+    ///
+    /// ## Example:
+    ///
+    /// ```
+    /// # use highlights::highlights::details::Timestamp;
+    /// let timestamp = Timestamp::parse("2022-10-04T12:12:01.250+02:00").unwrap();
+    ///
+    /// assert_eq!("2022-10-04T10:12:01", timestamp.to_string());
+    /// ```
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        let (date, time) = match text.find(['T', 't', ' ']) {
+            Some(index) => (&text[..index], text[index + 1..].trim_start()),
+            None => (text, ""),
+        };
+        let mut date_parts = date.splitn(3, '-');
+        let year = date_parts.next()?.parse().ok()?;
+        let month = number(date_parts.next()?)?;
+        let day = number(date_parts.next()?)?;
+        if time.is_empty() {
+            return Timestamp::new(year, month, day, 0, 0, 0);
+        }
+
+        let (time, offset) = match time.find(['Z', 'z', '+', '-']) {
+            Some(index) => (&time[..index], Some(offset(&time[index..])?)),
+            None => (time, None),
+        };
+        let time = time.trim().split('.').next()?;
+        let mut time_parts = time.splitn(3, ':');
+        let hour = number(time_parts.next()?)?;
+        let minute = number(time_parts.next()?)?;
+        let second = time_parts.next().map_or(Some(0), number)?;
+        let timestamp = Timestamp::new(year, month, day, hour, minute, second)?;
+        Some(match offset {
+            Some(offset) => Timestamp::from_unix(timestamp.unix() - offset),
+            None => timestamp,
+        })
+    }
+
+    /// Date part formatted as `YYYY-MM-DD`.
+    pub fn date(&self) -> String {
+        format!("{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}T{:02}:{:02}:{:02}",
+            self.date(),
+            self.hour,
+            self.minute,
+            self.second
+        )
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Timestamp::parse(text).ok_or_else(|| format!("invalid timestamp '{}'", text))
+    }
+}
+
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Splits the note into the leading `.tag` words and the rest of the note.
+///
+/// Kindle has no tags, so readers write them as `.tag1 .tag2` notes the way Readwise does.
+///
+/// ## Example:
+///
+/// ```
+/// # use highlights::highlights::details::note_tags;
+/// let (tags, note) = note_tags(".chess .strategy Plan every move");
+///
+/// assert_eq!(vec!["chess", "strategy"], tags);
+/// assert_eq!("Plan every move", note);
+/// ```
+pub fn note_tags(note: &str) -> (Vec<String>, &str) {
+    let mut tags = vec![];
+    let mut rest = note.trim_start();
+    while let Some(word) = rest.split_whitespace().next() {
+        let tag = match word.strip_prefix('.') {
+            Some(tag) if tag.starts_with(|c: char| c.is_alphanumeric()) => tag,
+            _ => break,
+        };
+        tags.push(tag.to_owned());
+        rest = rest[word.len()..].trim_start();
+    }
+    (tags, rest)
+}
+
+fn number(text: &str) -> Option<u32> {
+    let text = text.trim();
+    if text.is_empty() || !text.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    text.parse().ok()
+}
+
+/// Offset of `Z`, `+HH:MM` or `-HHMM` in seconds.
+fn offset(text: &str) -> Option<i64> {
+    let sign = match text.chars().next()? {
+        'Z' | 'z' => return text[1..].trim().is_empty().then_some(0),
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits: String = text[1..].chars().filter(|c| *c != ':').collect();
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let hours = i64::from(number(&digits[..2])?);
+    let minutes = i64::from(number(&digits[2..])?);
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+fn is_leap_year(year: i32) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since the Unix epoch of the proleptic Gregorian date.
+fn days_from_civil(year: i32, month: u32, day: u32) -> i64 {
+    let year = i64::from(year) - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = i64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Proleptic Gregorian date of the days since the Unix epoch.
+fn civil_from_days(days: i64) -> (i32, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year as i32, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_timestamps() {
+        let expected = Timestamp::new(2022, 10, 4, 10, 12, 1);
+
+        assert_eq!(expected, Timestamp::parse("2022-10-04 10:12:01"));
+        assert_eq!(expected, Timestamp::parse("2022-10-04T10:12:01.000"));
+        assert_eq!(expected, Timestamp::parse("2022-10-04T10:12:01Z"));
+        assert_eq!(expected, Timestamp::parse("2022-10-04 05:12:01-05:00"));
+        assert_eq!(
+            Timestamp::new(2022, 10, 4, 0, 0, 0),
+            Timestamp::parse("2022-10-04")
+        );
+    }
+
+    #[test]
+    fn reject_invalid_timestamps() {
+        assert_eq!(None, Timestamp::parse("2022-02-29 10:00"));
+        assert_eq!(None, Timestamp::parse("2022-10-04 25:00"));
+        assert_eq!(None, Timestamp::parse("yesterday"));
+        assert_eq!(None, Timestamp::parse("2022-10-04T10:00+2"));
+        assert_eq!(None, Timestamp::parse("2022-10-04T10:00+1é1"));
+        assert_eq!(None, Timestamp::parse("2022-10-04T10:00-０１:００"));
+    }
+
+    #[test]
+    fn convert_unix_seconds() {
+        let timestamp = Timestamp::from_unix(APPLE_EPOCH + 686_571_121);
+
+        assert_eq!("2022-10-04T10:12:01", timestamp.to_string());
+        assert_eq!(APPLE_EPOCH + 686_571_121, timestamp.unix());
+        assert_eq!("1969-12-31T23:59:59", Timestamp::from_unix(-1).to_string());
+    }
+
+    #[test]
+    fn order_timestamps() {
+        let earlier = Timestamp::parse("2022-10-04 10:12:01").unwrap();
+        let later = Timestamp::parse("2022-10-04 10:12:02").unwrap();
+
+        assert!(earlier < later);
+    }
+
+    #[test]
+    fn split_note_tags() {
+        assert_eq!((vec!["todo".to_owned()], ""), note_tags(".todo"));
+        assert_eq!((vec![], "... and then"), note_tags("... and then"));
+        assert_eq!((vec![], "Plain note .tag"), note_tags("Plain note .tag"));
+    }
+
+    #[test]
+    fn keep_text_and_tags_of_tag_only_notes() {
+        use crate::highlights::{Highlight, Location};
+
+        let highlight = Highlight::note(".todo .chess", Location::new(1, "")).with_note_tags();
+
+        match &highlight {
+            Highlight::Note { note, .. } => assert_eq!(".todo .chess", note),
+            other => panic!("expected note, got {:?}", other),
+        }
+        assert_eq!(["todo", "chess"], highlight.tags());
+    }
+}
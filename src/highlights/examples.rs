@@ -22,4 +22,5 @@ pub fn chess_book() -> Book {
             ),
         ],
     )
+    .with_asin("B0049U443Q")
 }
@@ -0,0 +1,304 @@
+//! Predicates selecting highlights of a book.
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use regex::Regex;
+
+use crate::highlights::{Book, Highlight};
+
+/// Kind of the highlight, the variant of [`Highlight`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Kind {
+    Quote,
+    Note,
+    Comment,
+}
+
+/// Range of location values including both ends, either end may be open.
+///
+/// Parsed from `100..400`, `100..`, `..400` or a single location `100`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Locations {
+    start: Option<usize>,
+    end: Option<usize>,
+}
+
+/// Selects highlights matching all configured conditions.
+///
+/// A filter without conditions matches every highlight.
+///
+/// ## Example:
+///
+/// ```
+/// # use highlights::highlights::examples;
+/// # use highlights::highlights::filter::{Filter, Kind};
+/// let filter = Filter::new()
+///     .only([Kind::Comment])
+///     .grep("chess".parse().unwrap());
+///
+/// let book = filter.apply(examples::chess_book());
+/// assert!(book.highlights().iter().all(|highlight| filter.matches(highlight)));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    kinds: Vec<Kind>,
+    locations: Option<Locations>,
+    pattern: Option<Regex>,
+    min_length: Option<usize>,
+}
+
+impl Kind {
+    /// All highlight kinds.
+    pub const ALL: [Kind; 3] = [Kind::Quote, Kind::Note, Kind::Comment];
+
+    /// Kind name used on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kind::Quote => "quotes",
+            Kind::Note => "notes",
+            Kind::Comment => "comments",
+        }
+    }
+
+    /// Kind of the highlight.
+    pub fn of(highlight: &Highlight) -> Self {
+        match highlight {
+            Highlight::Quote { .. } => Kind::Quote,
+            Highlight::Note { .. } => Kind::Note,
+            Highlight::Comment { .. } => Kind::Comment,
+        }
+    }
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Kind {
+    type Err = String;
+
+    /// Parses the plural kind name, the singular one is accepted as well.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let name = name.trim().to_lowercase();
+        Kind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == name || kind.name().trim_end_matches('s') == name)
+            .ok_or_else(|| {
+                let supported: Vec<&str> = Kind::ALL.iter().map(Kind::name).collect();
+                format!(
+                    "unknown highlight kind, supported kinds: {}",
+                    supported.join(", ")
+                )
+            })
+    }
+}
+
+impl Locations {
+    /// Range of locations from the start to the end, both included.
+    pub fn new(start: Option<usize>, end: Option<usize>) -> Self {
+        Locations { start, end }
+    }
+
+    /// Checks if the location value is in the range.
+    pub fn contains(&self, value: usize) -> bool {
+        self.start.is_none_or(|start| start <= value) && self.end.is_none_or(|end| value <= end)
+    }
+}
+
+impl FromStr for Locations {
+    type Err = String;
+
+    fn from_str(range: &str) -> Result<Self, Self::Err> {
+        let bound = |value: &str| match value.trim() {
+            "" => Ok(None),
+            value => value
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("invalid location: {}", value)),
+        };
+        let locations = match range.split_once("..") {
+            Some((start, end)) => Locations::new(bound(start)?, bound(end)?),
+            None => {
+                let value = bound(range)?.ok_or("empty location range")?;
+                Locations::new(Some(value), Some(value))
+            }
+        };
+        match (locations.start, locations.end) {
+            (Some(start), Some(end)) if start > end => Err(format!(
+                "location range starts after it ends: {}..{}",
+                start, end
+            )),
+            _ => Ok(locations),
+        }
+    }
+}
+
+impl Filter {
+    /// Creates the filter matching every highlight.
+    pub fn new() -> Self {
+        Filter::default()
+    }
+
+    /// Keeps highlights of the given kinds, adding to the kinds set before.
+    pub fn only(mut self, kinds: impl IntoIterator<Item = Kind>) -> Self {
+        for kind in kinds {
+            if !self.kinds.contains(&kind) {
+                self.kinds.push(kind);
+            }
+        }
+        self
+    }
+
+    /// Keeps highlights with the location in the range.
+    pub fn locations(mut self, locations: Locations) -> Self {
+        self.locations = Some(locations);
+        self
+    }
+
+    /// Keeps highlights with the quote or the note matching the pattern.
+    pub fn grep(mut self, pattern: Regex) -> Self {
+        self.pattern = Some(pattern);
+        self
+    }
+
+    /// Keeps highlights with at least the number of characters in the quote and the note.
+    pub fn min_length(mut self, length: usize) -> Self {
+        self.min_length = Some(length);
+        self
+    }
+
+    /// Checks if the filter has no conditions.
+    pub fn is_empty(&self) -> bool {
+        self.kinds.is_empty()
+            && self.locations.is_none()
+            && self.pattern.is_none()
+            && self.min_length.is_none()
+    }
+
+    /// Checks if the highlight matches all conditions.
+    pub fn matches(&self, highlight: &Highlight) -> bool {
+        (self.kinds.is_empty() || self.kinds.contains(&Kind::of(highlight)))
+            && self
+                .locations
+                .is_none_or(|locations| locations.contains(highlight.location().value()))
+            && self
+                .pattern
+                .as_ref()
+                .is_none_or(|pattern| texts(highlight).any(|text| pattern.is_match(text)))
+            && self
+                .min_length
+                .is_none_or(|length| highlight.length() >= length)
+    }
+
+    /// Removes highlights not matching the filter from the book.
+    pub fn apply(&self, mut book: Book) -> Book {
+        book.highlights.retain(|highlight| self.matches(highlight));
+        book
+    }
+}
+
+/// Quote and note of the highlight.
+fn texts(highlight: &Highlight) -> impl Iterator<Item = &str> {
+    let (quote, note) = match highlight {
+        Highlight::Quote { quote, .. } => (Some(quote), None),
+        Highlight::Note { note, .. } => (None, Some(note)),
+        Highlight::Comment { quote, note, .. } => (Some(quote), Some(note)),
+    };
+    quote.into_iter().chain(note).map(String::as_str)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::highlights::Location;
+
+    fn highlights() -> Vec<Highlight> {
+        vec![
+            Highlight::quote("Chess is life", Location::new(10, "")),
+            Highlight::note("Remember the opening", Location::new(120, "")),
+            Highlight::comment("Play the board", "Not the man", Location::new(400, "")),
+        ]
+    }
+
+    fn matching(filter: &Filter) -> Vec<usize> {
+        highlights()
+            .iter()
+            .filter(|highlight| filter.matches(highlight))
+            .map(|highlight| highlight.location().value())
+            .collect()
+    }
+
+    #[test]
+    fn match_everything_without_conditions() {
+        let filter = Filter::new();
+
+        assert!(filter.is_empty());
+        assert_eq!(vec![10, 120, 400], matching(&filter));
+    }
+
+    #[test]
+    fn filter_by_kind() {
+        let filter = Filter::new().only([Kind::Quote, Kind::Comment]);
+
+        assert_eq!(vec![10, 400], matching(&filter));
+    }
+
+    #[test]
+    fn parse_kinds() {
+        assert_eq!(Ok(Kind::Comment), "comments".parse());
+        assert_eq!(Ok(Kind::Note), "Note".parse());
+        assert!("bookmarks".parse::<Kind>().is_err());
+    }
+
+    #[test]
+    fn filter_by_locations() {
+        let filter = Filter::new().locations("100..400".parse().unwrap());
+
+        assert_eq!(vec![120, 400], matching(&filter));
+    }
+
+    #[test]
+    fn parse_location_ranges() {
+        assert_eq!(Ok(Locations::new(Some(100), None)), "100..".parse());
+        assert_eq!(Ok(Locations::new(None, Some(400))), "..400".parse());
+        assert_eq!(Ok(Locations::new(Some(42), Some(42))), "42".parse());
+        assert!("400..100".parse::<Locations>().is_err());
+        assert!("a..b".parse::<Locations>().is_err());
+        assert!("".parse::<Locations>().is_err());
+    }
+
+    #[test]
+    fn filter_by_pattern_in_quote_or_note() {
+        let filter = Filter::new().grep(Regex::new("(?i)^(chess|not)").unwrap());
+
+        assert_eq!(vec![10, 400], matching(&filter));
+    }
+
+    #[test]
+    fn filter_by_length_of_quote_and_note() {
+        let filter = Filter::new().min_length(20);
+
+        assert_eq!(vec![120, 400], matching(&filter));
+    }
+
+    #[test]
+    fn combine_conditions() {
+        let filter = Filter::new().only([Kind::Note]).min_length(100);
+
+        assert!(matching(&filter).is_empty());
+    }
+
+    #[test]
+    fn apply_to_book() {
+        let book = Book::new("Title", "Author", highlights());
+
+        let book = Filter::new()
+            .locations("..100".parse().unwrap())
+            .apply(book);
+
+        assert_eq!(1, book.highlights().len());
+        assert_eq!("Title", book.title());
+    }
+}
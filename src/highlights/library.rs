@@ -0,0 +1,297 @@
+//! Collection of books read from one or many inputs.
+use std::slice::Iter;
+use std::vec::IntoIter;
+
+use crate::highlights::Book;
+
+/// Longest book detail kept in the generated file name, in characters.
+const MAX_NAME_PART: usize = 100;
+
+/// Books read from one or many inputs.
+///
+/// Books with the same ASIN, or with the same title and authors when either has no ASIN,
+/// are the same book, so highlights read from several inputs end up in one book.
+///
+/// ## Example:
+///
+/// ```
+/// # use highlights::highlights::examples;
+/// # use highlights::highlights::library::Library;
+/// let library: Library = [examples::chess_book()].into_iter().collect();
+///
+/// let book = library.find("B0049U443Q").unwrap();
+/// assert_eq!("Garry Kasparov", book.authors());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Library {
+    books: Vec<Book>,
+}
+
+impl Library {
+    /// Creates an empty library.
+    pub fn new() -> Self {
+        Library::default()
+    }
+
+    /// Adds the book, appending its highlights when the library already has the same book.
+    ///
+    /// Highlights the library already has are skipped,
+    /// so reading the same input twice, or an older copy of it, adds nothing.
+    pub fn add(&mut self, book: Book) {
+        match self.books.iter_mut().find(|known| known.is_same(&book)) {
+            Some(known) => {
+                if known.asin.is_none() {
+                    known.asin = book.asin;
+                }
+                let present = known.highlights.len();
+                for highlight in book.highlights {
+                    if !known.highlights[..present].contains(&highlight) {
+                        known.highlights.push(highlight);
+                    }
+                }
+            }
+            None => self.books.push(book),
+        }
+    }
+
+    /// Books in the order they were first added.
+    pub fn books(&self) -> &[Book] {
+        &self.books
+    }
+
+    /// Number of books.
+    pub fn len(&self) -> usize {
+        self.books.len()
+    }
+
+    /// Checks if the library has no books.
+    pub fn is_empty(&self) -> bool {
+        self.books.is_empty()
+    }
+
+    /// Iterates over the books.
+    pub fn iter(&self) -> Iter<'_, Book> {
+        self.books.iter()
+    }
+
+    /// Finds the book by the title ignoring the case.
+    pub fn by_title(&self, title: &str) -> Option<&Book> {
+        let title = title.trim().to_lowercase();
+        self.books
+            .iter()
+            .find(|book| book.title().to_lowercase() == title)
+    }
+
+    /// Finds the book by the Amazon identifier.
+    pub fn by_asin(&self, asin: &str) -> Option<&Book> {
+        let asin = asin.trim();
+        self.books.iter().find(|book| book.asin() == Some(asin))
+    }
+
+    /// Finds the book by the ASIN or by the title.
+    pub fn find(&self, title_or_asin: &str) -> Option<&Book> {
+        self.by_asin(title_or_asin)
+            .or_else(|| self.by_title(title_or_asin))
+    }
+}
+
+impl Book {
+    fn is_same(&self, other: &Book) -> bool {
+        match (self.asin(), other.asin()) {
+            (Some(asin), Some(other_asin)) => asin == other_asin,
+            _ => self.title == other.title && self.authors == other.authors,
+        }
+    }
+}
+
+impl FromIterator<Book> for Library {
+    fn from_iter<I: IntoIterator<Item = Book>>(books: I) -> Self {
+        let mut library = Library::new();
+        library.extend(books);
+        library
+    }
+}
+
+impl Extend<Book> for Library {
+    fn extend<I: IntoIterator<Item = Book>>(&mut self, books: I) {
+        for book in books {
+            self.add(book);
+        }
+    }
+}
+
+impl From<Vec<Book>> for Library {
+    fn from(books: Vec<Book>) -> Self {
+        books.into_iter().collect()
+    }
+}
+
+impl From<Library> for Vec<Book> {
+    fn from(library: Library) -> Self {
+        library.books
+    }
+}
+
+impl IntoIterator for Library {
+    type Item = Book;
+    type IntoIter = IntoIter<Book>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.books.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Library {
+    type Item = &'a Book;
+    type IntoIter = Iter<'a, Book>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.books.iter()
+    }
+}
+
+/// Generates the file name of the book from the pattern.
+///
+/// The pattern replaces `{author}`, `{title}` and `{asin}` with the book details.
+/// Characters not allowed in file names are replaced with `_`.
+///
+/// ## Example:
+///
+/// ```
+/// # use highlights::highlights::examples;
+/// # use highlights::highlights::library::file_name;
+/// let name = file_name(&examples::chess_book(), "{author} - {title}.md");
+///
+/// assert!(name.starts_with("Garry Kasparov - How Life Imitates Chess_ Making"));
+/// ```
+pub fn file_name(book: &Book, pattern: &str) -> String {
+    let author = match book.authors().trim() {
+        "" => "Unknown",
+        authors => authors,
+    };
+    let title = match book.title().trim() {
+        "" => "Untitled",
+        title => title,
+    };
+    pattern
+        .replace("{author}", &name_part(author))
+        .replace("{title}", &name_part(title))
+        .replace("{asin}", &name_part(book.asin().unwrap_or_default()))
+}
+
+fn name_part(value: &str) -> String {
+    let value: String = value
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .chars()
+        .map(|c| {
+            if c.is_control() || "/\\:*?\"<>|".contains(c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .take(MAX_NAME_PART)
+        .collect();
+    value.trim_end_matches(['.', ' ']).to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::highlights::{examples, Highlight, Location};
+
+    fn book(title: &str, authors: &str, location: usize) -> Book {
+        Book::new(
+            title,
+            authors,
+            [Highlight::quote("Quote", Location::new(location, ""))],
+        )
+    }
+
+    #[test]
+    fn merge_same_book() {
+        let library: Library = [
+            book("Title", "Author", 1),
+            book("Other", "Author", 2),
+            book("Title", "Author", 3),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(2, library.len());
+        assert_eq!(2, library.books()[0].highlights().len());
+    }
+
+    #[test]
+    fn skip_highlights_already_present() {
+        let library: Library = [
+            book("Title", "Author", 1),
+            Book::new(
+                "Title",
+                "Author",
+                [
+                    Highlight::quote("Quote", Location::new(1, "")),
+                    Highlight::quote("Quote", Location::new(2, "")),
+                    Highlight::quote("Quote", Location::new(2, "")),
+                ],
+            ),
+        ]
+        .into_iter()
+        .collect();
+
+        let locations: Vec<usize> = library.books()[0]
+            .highlights()
+            .iter()
+            .map(|highlight| highlight.location().value())
+            .collect();
+        assert_eq!(vec![1, 2, 2], locations);
+    }
+
+    #[test]
+    fn merge_by_asin() {
+        let library: Library = [
+            book("Title", "Author", 1).with_asin("B0049U443Q"),
+            book("Title: Subtitle", "A. Author", 2).with_asin("B0049U443Q"),
+            book("Title", "Author", 3).with_asin("B000000000"),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(2, library.len());
+        assert_eq!("Title", library.books()[0].title());
+    }
+
+    #[test]
+    fn find_by_title_or_asin() {
+        let library = Library::from(vec![examples::chess_book(), book("Title", "Author", 1)]);
+
+        assert_eq!(Some("Title"), library.find("title").map(Book::title));
+        assert_eq!(
+            Some("Garry Kasparov"),
+            library.find("B0049U443Q").map(Book::authors)
+        );
+        assert!(library.find("Missing").is_none());
+    }
+
+    #[test]
+    fn generate_file_name() {
+        let book = book("What? A/B: <Test>.", "Some  Author", 1).with_asin("B01");
+
+        assert_eq!(
+            "Some Author - What_ A_B_ _Test_ (B01).md",
+            file_name(&book, "{author} - {title} ({asin}).md")
+        );
+    }
+
+    #[test]
+    fn name_books_without_details() {
+        let book = book("", "", 1);
+
+        assert_eq!(
+            "Unknown - Untitled.md",
+            file_name(&book, "{author} - {title}.md")
+        );
+    }
+}
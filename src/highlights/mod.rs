@@ -1,13 +1,24 @@
 //! Data model for book highlights.
+use serde::{Deserialize, Serialize};
+
+use crate::highlights::details::{note_tags, Color, Details, Timestamp};
+
+pub mod dedup;
+pub mod details;
 pub mod examples;
+pub mod filter;
+pub mod library;
+pub mod order;
 
 /// Book with highlighted passages.
 ///
 /// Holds basic data on the book and a list of highlights.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Book {
     title: String,
     authors: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    asin: Option<String>,
     highlights: Vec<Highlight>,
 }
 
@@ -15,29 +26,50 @@ pub struct Book {
 ///
 /// Each passage is always related to some location in the book.
 /// The highlight can have the quote from the original text and readers comment.
-#[derive(Clone, Debug)]
+/// Optional [`Details`] keep when the highlight was made, its color and tags.
+/// Serialized highlights carry the variant name in the `type` field.
+///
+/// Build highlights with the [`Highlight::quote`], [`Highlight::note`] and [`Highlight::comment`]
+/// constructors and match them with `..`, so new fields of the variants do not break the code.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
 pub enum Highlight {
     /// Word-by-word quote from the original text.
-    Quote { quote: String, location: Location },
+    Quote {
+        quote: String,
+        location: Location,
+        #[serde(default, flatten)]
+        details: Details,
+    },
 
     /// Margin note for the particular book location.
-    Note { note: String, location: Location },
+    Note {
+        note: String,
+        location: Location,
+        #[serde(default, flatten)]
+        details: Details,
+    },
 
     /// Quote from the book and readers comment on the quote.
     Comment {
         quote: String,
         note: String,
         location: Location,
+        #[serde(default, flatten)]
+        details: Details,
     },
 }
 
 /// Location of highlighted passage.
 ///
 /// Contains the numeric value of the passage as well as a link to reach the highlight.
-#[derive(Clone, Debug)]
+/// Readers that know the structure of the book also keep the chapter of the passage.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Location {
     value: usize,
     link: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    chapter: Option<String>,
 }
 
 impl Book {
@@ -49,10 +81,10 @@ impl Book {
     /// let hhgttg = Book::new(
     ///     "The Hitchhikers Guide",
     ///     "Douglas Adams",
-    ///     [Highlight::Quote{
-    ///         quote: "An ultimate answer is 42".to_owned(),
-    ///         location: Location::new(42, "https://ultimate.answers.org/42")
-    ///     }]);
+    ///     [Highlight::quote(
+    ///         "An ultimate answer is 42",
+    ///         Location::new(42, "https://ultimate.answers.org/42")
+    ///     )]);
     /// ```
     pub fn new<S, I>(title: S, authors: S, highlights: I) -> Self
     where
@@ -62,10 +94,17 @@ impl Book {
         Book {
             title: title.into(),
             authors: authors.into(),
+            asin: None,
             highlights: Vec::from_iter(highlights),
         }
     }
 
+    /// Sets the Amazon identifier of the book.
+    pub fn with_asin(mut self, asin: impl Into<String>) -> Self {
+        self.asin = Some(asin.into());
+        self
+    }
+
     /// Book title.
     pub fn title(&self) -> &str {
         &self.title
@@ -76,6 +115,11 @@ impl Book {
         &self.authors
     }
 
+    /// Amazon identifier of the book when the source provides it.
+    pub fn asin(&self) -> Option<&str> {
+        self.asin.as_deref()
+    }
+
     /// Book highlights.
     ///
     /// Returns a clone of the highlights vector.
@@ -93,6 +137,7 @@ impl Highlight {
         Highlight::Note {
             note: note.into(),
             location,
+            details: Details::default(),
         }
     }
 
@@ -104,6 +149,7 @@ impl Highlight {
         Highlight::Quote {
             quote: quote.into(),
             location,
+            details: Details::default(),
         }
     }
 
@@ -116,27 +162,148 @@ impl Highlight {
             quote: quote.into(),
             note: note.into(),
             location,
+            details: Details::default(),
         }
     }
 
     pub fn location(&self) -> Location {
         let location = match self {
-            Highlight::Note {
-                note: _,
-                location: l,
-            } => l,
-            Highlight::Quote {
-                quote: _,
-                location: l,
-            } => l,
-            Highlight::Comment {
-                quote: _,
-                note: _,
-                location: l,
-            } => l,
+            Highlight::Note { location, .. } => location,
+            Highlight::Quote { location, .. } => location,
+            Highlight::Comment { location, .. } => location,
         };
         location.clone()
     }
+
+    /// Number of characters in the quote and the note.
+    pub fn length(&self) -> usize {
+        match self {
+            Highlight::Quote { quote, .. } => quote.chars().count(),
+            Highlight::Note { note, .. } => note.chars().count(),
+            Highlight::Comment { quote, note, .. } => quote.chars().count() + note.chars().count(),
+        }
+    }
+
+    /// When the highlight was made, if known.
+    pub fn created(&self) -> Option<Timestamp> {
+        self.details().created
+    }
+
+    /// When the highlight was last changed, if known.
+    pub fn modified(&self) -> Option<Timestamp> {
+        self.details().modified
+    }
+
+    /// Highlight color, if known.
+    pub fn color(&self) -> Option<Color> {
+        self.details().color
+    }
+
+    /// User tags of the highlight.
+    pub fn tags(&self) -> &[String] {
+        &self.details().tags
+    }
+
+    /// Sets the creation time.
+    pub fn with_created(mut self, created: Timestamp) -> Self {
+        self.details_mut().created = Some(created);
+        self
+    }
+
+    /// Sets the modification time.
+    pub fn with_modified(mut self, modified: Timestamp) -> Self {
+        self.details_mut().modified = Some(modified);
+        self
+    }
+
+    /// Sets the highlight color.
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.details_mut().color = Some(color);
+        self
+    }
+
+    /// Adds the tags skipping blank and repeated ones.
+    pub fn with_tags<I, S>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let known = &mut self.details_mut().tags;
+        for tag in tags {
+            let tag = tag.into().trim().to_owned();
+            if !tag.is_empty() && !known.contains(&tag) {
+                known.push(tag);
+            }
+        }
+        self
+    }
+
+    /// Moves the leading `.tag` words of the note to the highlight tags.
+    ///
+    /// A comment with only tags in the note becomes a quote.
+    /// A note with only tags keeps its text as written, so the highlight does not lose it,
+    /// and still gets the tags.
+    pub fn with_note_tags(self) -> Self {
+        let note = match &self {
+            Highlight::Note { note, .. } | Highlight::Comment { note, .. } => note,
+            Highlight::Quote { .. } => return self,
+        };
+        let (tags, rest) = note_tags(note);
+        if tags.is_empty() {
+            return self;
+        }
+        let rest = rest.to_owned();
+        match self {
+            Highlight::Comment {
+                quote,
+                location,
+                details,
+                ..
+            } if rest.is_empty() => Highlight::Quote {
+                quote,
+                location,
+                details,
+            }
+            .with_tags(tags),
+            Highlight::Comment {
+                quote,
+                location,
+                details,
+                ..
+            } => Highlight::Comment {
+                quote,
+                note: rest,
+                location,
+                details,
+            }
+            .with_tags(tags),
+            Highlight::Note {
+                location, details, ..
+            } if !rest.is_empty() => Highlight::Note {
+                note: rest,
+                location,
+                details,
+            }
+            .with_tags(tags),
+            highlight => highlight.with_tags(tags),
+        }
+    }
+
+    fn details(&self) -> &Details {
+        match self {
+            Highlight::Note { details, .. }
+            | Highlight::Quote { details, .. }
+            | Highlight::Comment { details, .. } => details,
+        }
+    }
+
+    fn details_mut(&mut self) -> &mut Details {
+        match self {
+            Highlight::Note { details, .. }
+            | Highlight::Quote { details, .. }
+            | Highlight::Comment { details, .. } => details,
+        }
+    }
 }
 
 impl Location {
@@ -148,9 +315,20 @@ impl Location {
         Location {
             value,
             link: link.into(),
+            chapter: None,
         }
     }
 
+    /// Sets the chapter or section containing the location.
+    ///
+    /// Blank chapter titles are ignored.
+    pub fn with_chapter(mut self, chapter: impl Into<String>) -> Self {
+        let chapter = chapter.into();
+        let chapter = chapter.trim();
+        self.chapter = (!chapter.is_empty()).then(|| chapter.to_owned());
+        self
+    }
+
     pub fn value(&self) -> usize {
         self.value
     }
@@ -158,4 +336,9 @@ impl Location {
     pub fn link(&self) -> &str {
         &self.link
     }
+
+    /// Chapter or section containing the location when the source provides it.
+    pub fn chapter(&self) -> Option<&str> {
+        self.chapter.as_deref()
+    }
 }
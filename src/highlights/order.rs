@@ -0,0 +1,221 @@
+//! Ordering of the book highlights.
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::highlights::{Book, Highlight};
+
+/// What the highlights are ordered by.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum SortKey {
+    /// Order the input listed the highlights in.
+    Input,
+    /// Location value in the book.
+    #[default]
+    Location,
+    /// Creation time, highlights without the time come last.
+    Created,
+    /// Number of characters in the quote and the note.
+    Length,
+}
+
+/// Order of the book highlights.
+///
+/// Sorting is stable, so highlights with the same key keep the input order,
+/// also in the reversed order, and already sorted input is left as it is.
+/// The default order is by location.
+///
+/// ## Example:
+///
+/// ```
+/// # use highlights::highlights::examples;
+/// # use highlights::highlights::order::{Order, SortKey};
+/// let book = Order::new(SortKey::Length)
+///     .reversed()
+///     .apply(examples::chess_book());
+///
+/// let highlights = book.highlights();
+/// assert!(highlights[0].length() >= highlights[1].length());
+/// ```
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Order {
+    key: SortKey,
+    reversed: bool,
+}
+
+impl SortKey {
+    /// All sort keys.
+    pub const ALL: [SortKey; 4] = [
+        SortKey::Input,
+        SortKey::Location,
+        SortKey::Created,
+        SortKey::Length,
+    ];
+
+    /// Sort key name used on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            SortKey::Input => "input",
+            SortKey::Location => "location",
+            SortKey::Created => "created",
+            SortKey::Length => "length",
+        }
+    }
+
+    /// Compares the highlights by the key.
+    pub fn compare(&self, first: &Highlight, second: &Highlight) -> Ordering {
+        match self {
+            SortKey::Input => Ordering::Equal,
+            SortKey::Location => first.location().value().cmp(&second.location().value()),
+            SortKey::Created => match (first.created(), second.created()) {
+                (Some(first), Some(second)) => first.cmp(&second),
+                (first, second) => first.is_none().cmp(&second.is_none()),
+            },
+            SortKey::Length => first.length().cmp(&second.length()),
+        }
+    }
+}
+
+impl Display for SortKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        SortKey::ALL
+            .into_iter()
+            .find(|key| key.name() == name.trim())
+            .ok_or_else(|| {
+                let supported: Vec<&str> = SortKey::ALL.iter().map(SortKey::name).collect();
+                format!(
+                    "unknown sort order, supported orders: {}",
+                    supported.join(", ")
+                )
+            })
+    }
+}
+
+impl Order {
+    /// Orders highlights by the key.
+    pub fn new(key: SortKey) -> Self {
+        Order {
+            key,
+            reversed: false,
+        }
+    }
+
+    /// Reverses the order.
+    pub fn reversed(mut self) -> Self {
+        self.reversed = !self.reversed;
+        self
+    }
+
+    /// What the highlights are ordered by.
+    pub fn key(&self) -> SortKey {
+        self.key
+    }
+
+    /// Checks if the order is reversed.
+    pub fn is_reversed(&self) -> bool {
+        self.reversed
+    }
+
+    /// Sorts the highlights in place.
+    ///
+    /// Reversed orders compare the keys the other way round, so ties still keep the input order.
+    /// Only the reversed input order turns the highlights around.
+    pub fn sort(&self, highlights: &mut [Highlight]) {
+        match (self.key, self.reversed) {
+            (SortKey::Input, true) => highlights.reverse(),
+            (_, true) => {
+                highlights.sort_by(|first, second| self.key.compare(first, second).reverse())
+            }
+            (_, false) => highlights.sort_by(|first, second| self.key.compare(first, second)),
+        }
+    }
+
+    /// Sorts the highlights of the book.
+    pub fn apply(&self, mut book: Book) -> Book {
+        self.sort(&mut book.highlights);
+        book
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::highlights::details::Timestamp;
+    use crate::highlights::Location;
+
+    fn highlights() -> Vec<Highlight> {
+        let created = |day| Timestamp::new(2022, 10, day, 12, 0, 0).unwrap();
+        vec![
+            Highlight::quote("Third", Location::new(30, "")).with_created(created(1)),
+            Highlight::note("First", Location::new(10, "")),
+            Highlight::comment("Second", "Note", Location::new(20, "")).with_created(created(3)),
+            Highlight::quote("Fourth and longest", Location::new(30, "")).with_created(created(2)),
+        ]
+    }
+
+    fn sorted(order: Order) -> Vec<String> {
+        let mut highlights = highlights();
+        order.sort(&mut highlights);
+        highlights
+            .iter()
+            .map(|highlight| match highlight {
+                Highlight::Quote { quote, .. } | Highlight::Comment { quote, .. } => quote.clone(),
+                Highlight::Note { note, .. } => note.clone(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sort_by_location_by_default() {
+        assert_eq!(
+            vec!["First", "Second", "Third", "Fourth and longest"],
+            sorted(Order::default())
+        );
+    }
+
+    #[test]
+    fn reverse_input_order() {
+        assert_eq!(
+            vec!["Fourth and longest", "Second", "First", "Third"],
+            sorted(Order::new(SortKey::Input).reversed())
+        );
+    }
+
+    #[test]
+    fn keep_input_order_of_ties_when_reversed() {
+        assert_eq!(
+            vec!["Third", "Fourth and longest", "Second", "First"],
+            sorted(Order::new(SortKey::Location).reversed())
+        );
+    }
+
+    #[test]
+    fn sort_by_creation_time_with_unknown_last() {
+        assert_eq!(
+            vec!["Third", "Fourth and longest", "Second", "First"],
+            sorted(Order::new(SortKey::Created))
+        );
+    }
+
+    #[test]
+    fn sort_by_length_reversed() {
+        assert_eq!(
+            vec!["Fourth and longest", "Second", "Third", "First"],
+            sorted(Order::new(SortKey::Length).reversed())
+        );
+    }
+
+    #[test]
+    fn parse_sort_keys() {
+        assert_eq!(Ok(SortKey::Created), "created".parse());
+        assert!("title".parse::<SortKey>().is_err());
+    }
+}
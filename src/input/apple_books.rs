@@ -0,0 +1,536 @@
+//! Import highlights from the Apple Books annotation databases.
+//!
+//! Apple Books keeps annotations in the `ZAEANNOTATION` table of the `AEAnnotation_*.sqlite` database,
+//! while book titles and authors live in the `ZBKLIBRARYASSET` table of the `BKLibrary-*.sqlite` database.
+//! Both tables refer to the book by the asset identifier.
+use std::cmp::Ordering;
+use std::io::Read;
+use std::path::Path;
+
+use rusqlite::Connection;
+
+use crate::error::HighlightError;
+use crate::highlights::details::{Color, Timestamp, APPLE_EPOCH};
+use crate::highlights::{Book, Highlight, Location};
+use crate::input::{sqlite, HighlightsRead, ReadMode, Warning};
+
+const FORMAT: &str = "apple books";
+
+/// Locations reserved for every document of the book spine.
+///
+/// The location of the highlight is `spine position * SPINE_LOCATIONS` plus the position
+/// of its block in the document, so it does not change when other highlights are added.
+const SPINE_LOCATIONS: usize = 10_000;
+
+/// Blocks counted at each of the two levels under the document body.
+const BLOCK_POSITIONS: usize = 100;
+
+const ANNOTATIONS_QUERY: &str = "
+    SELECT a.ZANNOTATIONASSETID, l.ZTITLE, l.ZAUTHOR,
+        a.ZANNOTATIONSELECTEDTEXT, a.ZANNOTATIONNOTE, a.ZANNOTATIONLOCATION,
+        a.ZANNOTATIONCREATIONDATE, a.ZANNOTATIONMODIFICATIONDATE, a.ZANNOTATIONSTYLE
+    FROM ZAEANNOTATION a
+    LEFT JOIN library.ZBKLIBRARYASSET l ON l.ZASSETID = a.ZANNOTATIONASSETID
+    WHERE a.ZANNOTATIONDELETED = 0
+    ORDER BY l.ZTITLE, a.ZANNOTATIONASSETID";
+
+const ANNOTATIONS_ONLY_QUERY: &str = "
+    SELECT ZANNOTATIONASSETID, NULL, NULL,
+        ZANNOTATIONSELECTEDTEXT, ZANNOTATIONNOTE, ZANNOTATIONLOCATION,
+        ZANNOTATIONCREATIONDATE, ZANNOTATIONMODIFICATIONDATE, ZANNOTATIONSTYLE
+    FROM ZAEANNOTATION
+    WHERE ZANNOTATIONDELETED = 0
+    ORDER BY ZANNOTATIONASSETID";
+
+/// Annotations read from the Apple Books databases.
+#[derive(Debug)]
+pub struct AppleBooksAnnotations {
+    annotations: Vec<AppleBooksAnnotation>,
+}
+
+#[derive(Debug)]
+struct AppleBooksAnnotation {
+    asset_id: String,
+    title: Option<String>,
+    authors: Option<String>,
+    text: Option<String>,
+    note: Option<String>,
+    cfi: Option<String>,
+    created: Option<f64>,
+    modified: Option<f64>,
+    style: Option<i64>,
+}
+
+impl AppleBooksAnnotations {
+    /// Reads annotations joined with the book titles from the library database.
+    pub fn open(
+        annotations: impl AsRef<Path>,
+        library: impl AsRef<Path>,
+    ) -> Result<Self, HighlightError> {
+        let connection = attach_library(annotations.as_ref(), library.as_ref())?;
+        Ok(AppleBooksAnnotations::read(&connection, ANNOTATIONS_QUERY, ReadMode::Strict)?.0)
+    }
+
+    /// Reads annotations joined with the book titles skipping the rows that cannot be decoded.
+    ///
+    /// Every skipped row is reported as the warning.
+    pub fn open_lenient(
+        annotations: impl AsRef<Path>,
+        library: impl AsRef<Path>,
+    ) -> Result<(Self, Vec<Warning>), HighlightError> {
+        let connection = attach_library(annotations.as_ref(), library.as_ref())?;
+        AppleBooksAnnotations::read(&connection, ANNOTATIONS_QUERY, ReadMode::Lenient)
+    }
+
+    fn read(
+        connection: &Connection,
+        query: &str,
+        mode: ReadMode,
+    ) -> Result<(Self, Vec<Warning>), HighlightError> {
+        let (annotations, warnings) = sqlite::query_rows(connection, query, FORMAT, mode, |row| {
+            Ok(AppleBooksAnnotation {
+                asset_id: row.get(0)?,
+                title: row.get(1)?,
+                authors: row.get(2)?,
+                text: row.get(3)?,
+                note: row.get(4)?,
+                cfi: row.get(5)?,
+                created: row.get(6)?,
+                modified: row.get(7)?,
+                style: row.get(8)?,
+            })
+        })?;
+        Ok((AppleBooksAnnotations { annotations }, warnings))
+    }
+}
+
+fn attach_library(annotations: &Path, library: &Path) -> Result<Connection, HighlightError> {
+    let connection = sqlite::open(annotations, FORMAT)?;
+    connection
+        .execute("ATTACH DATABASE ?1 AS library", [library.to_string_lossy()])
+        .map_err(|e| sqlite::invalid_database(FORMAT, e))?;
+    Ok(connection)
+}
+
+impl AppleBooksAnnotation {
+    fn has_content(&self) -> bool {
+        non_blank(&self.text).is_some() || non_blank(&self.note).is_some()
+    }
+
+    fn highlight(&self) -> Option<Highlight> {
+        let link = match &self.cfi {
+            Some(cfi) => format!("ibooks://assetid/{}#{}", self.asset_id, cfi),
+            None => format!("ibooks://assetid/{}", self.asset_id),
+        };
+        let value = self.cfi.as_deref().map_or(0, cfi_location);
+        let location = Location::new(value, link);
+        let mut highlight = match (non_blank(&self.text), non_blank(&self.note)) {
+            (Some(quote), Some(note)) => Highlight::comment(quote, note, location),
+            (Some(quote), None) => Highlight::quote(quote, location),
+            (None, Some(note)) => Highlight::note(note, location),
+            (None, None) => return None,
+        };
+        if let Some(created) = self.created {
+            highlight = highlight.with_created(apple_timestamp(created));
+        }
+        if let Some(modified) = self.modified {
+            highlight = highlight.with_modified(apple_timestamp(modified));
+        }
+        if let Some(color) = self.style.and_then(style_color) {
+            highlight = highlight.with_color(color);
+        }
+        Some(highlight)
+    }
+}
+
+/// Apple timestamps count seconds since 2001-01-01 UTC.
+fn apple_timestamp(seconds: f64) -> Timestamp {
+    Timestamp::from_unix(APPLE_EPOCH + seconds as i64)
+}
+
+/// Color of the annotation style, the underline style has no color.
+fn style_color(style: i64) -> Option<Color> {
+    match style {
+        1 => Some(Color::Green),
+        2 => Some(Color::Blue),
+        3 => Some(Color::Yellow),
+        4 => Some(Color::Pink),
+        5 => Some(Color::Purple),
+        _ => None,
+    }
+}
+
+fn non_blank(value: &Option<String>) -> Option<&str> {
+    value
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+/// Decodes the EPUB CFI into the sequence of steps comparable in the reading order.
+///
+/// `epubcfi(/6/24[chap05]!/4/2/6,/1:0,/1:120)` becomes `[6, 24, 4, 2, 6, 1, 0]`:
+/// the common path followed by the start of the range.
+/// Id assertions in brackets and side bias markers are ignored.
+fn cfi_steps(cfi: &str) -> Vec<usize> {
+    let (path, start) = cfi_start(cfi);
+    steps(path).into_iter().chain(steps(start)).collect()
+}
+
+/// Common path and the start of the range of the EPUB CFI.
+fn cfi_start(cfi: &str) -> (&str, &str) {
+    let cfi = cfi.trim();
+    let cfi = cfi
+        .strip_prefix("epubcfi(")
+        .and_then(|cfi| cfi.strip_suffix(')'))
+        .unwrap_or(cfi);
+    let mut parts = cfi.split(',');
+    let path = parts.next().unwrap_or_default();
+    let start = parts.next().unwrap_or_default();
+    (path, start)
+}
+
+/// Numbers of the CFI path, numbers too large for `usize` are dropped.
+fn steps(path: &str) -> Vec<usize> {
+    let mut steps = vec![];
+    let mut number = Some(0);
+    let mut digits = false;
+    let mut in_assertion = false;
+    for c in path.chars() {
+        match c {
+            '[' => in_assertion = true,
+            ']' => in_assertion = false,
+            _ if in_assertion => {}
+            '0'..='9' => {
+                let digit = c.to_digit(10).unwrap_or_default() as usize;
+                number = number
+                    .and_then(|number: usize| number.checked_mul(10))
+                    .and_then(|number| number.checked_add(digit));
+                digits = true;
+            }
+            _ => {
+                if std::mem::take(&mut digits) {
+                    steps.extend(number);
+                }
+                number = Some(0);
+            }
+        }
+    }
+    if digits {
+        steps.extend(number);
+    }
+    steps
+}
+
+/// Location of the highlight derived from its EPUB CFI.
+///
+/// `epubcfi(/6/24!/4/2/6,…)` is in the 12th document of the spine, `/24`,
+/// in the 1st block of the body, `/2`, and its 3rd nested block, `/6`,
+/// so its location is `12 * 10000 + 1 * 100 + 3`.
+/// Blocks past the 99th share the location of the 99th one,
+/// the highlights keep their order by the full CFI then.
+fn cfi_location(cfi: &str) -> usize {
+    let (path, start) = cfi_start(cfi);
+    let Some((package, content)) = path.split_once('!') else {
+        return 0;
+    };
+    let spine = steps(package).get(1).map_or(0, |step| step / 2);
+    let blocks: Vec<usize> = steps(content).into_iter().chain(steps(start)).collect();
+    let block = |level: usize| {
+        blocks
+            .get(level)
+            .map_or(0, |step| (step / 2).min(BLOCK_POSITIONS - 1))
+    };
+    spine
+        .saturating_mul(SPINE_LOCATIONS)
+        .saturating_add(block(1) * BLOCK_POSITIONS + block(2))
+}
+
+fn compare_cfi(left: &Option<String>, right: &Option<String>) -> Ordering {
+    let left = left.as_deref().map(cfi_steps);
+    let right = right.as_deref().map(cfi_steps);
+    left.cmp(&right)
+}
+
+impl From<AppleBooksAnnotations> for Vec<Book> {
+    /// Groups annotations by book asset.
+    ///
+    /// Highlights are ordered by their position in the book,
+    /// locations are derived from the position, see [`cfi_location`].
+    fn from(apple_books: AppleBooksAnnotations) -> Self {
+        let mut assets: Vec<Vec<AppleBooksAnnotation>> = vec![];
+        for annotation in apple_books.annotations {
+            match assets.last_mut() {
+                Some(asset) if asset[0].asset_id == annotation.asset_id => asset.push(annotation),
+                _ => assets.push(vec![annotation]),
+            }
+        }
+
+        assets
+            .into_iter()
+            .filter_map(|mut annotations| {
+                annotations.retain(AppleBooksAnnotation::has_content);
+                annotations.sort_by(|left, right| compare_cfi(&left.cfi, &right.cfi));
+                let highlights: Vec<Highlight> = annotations
+                    .iter()
+                    .filter_map(AppleBooksAnnotation::highlight)
+                    .collect();
+                if highlights.is_empty() {
+                    return None;
+                }
+                let asset = &annotations[0];
+                let title = asset
+                    .title
+                    .clone()
+                    .unwrap_or_else(|| asset.asset_id.clone());
+                let authors = asset.authors.clone().unwrap_or_default();
+                Some(Book::new(title, authors, highlights))
+            })
+            .collect()
+    }
+}
+
+impl HighlightsRead for AppleBooksAnnotations {
+    /// Reads the annotations database alone.
+    ///
+    /// Titles are not available without the library database, so books are named by the asset identifier.
+    fn from_reader(reader: impl Read) -> Result<Self, HighlightError> {
+        let connection = sqlite::from_reader(reader, FORMAT)?;
+        Ok(AppleBooksAnnotations::read(&connection, ANNOTATIONS_ONLY_QUERY, ReadMode::Strict)?.0)
+    }
+
+    /// Skips annotation rows that cannot be decoded.
+    fn from_reader_lenient(reader: impl Read) -> Result<(Self, Vec<Warning>), HighlightError> {
+        let connection = sqlite::from_reader(reader, FORMAT)?;
+        AppleBooksAnnotations::read(&connection, ANNOTATIONS_ONLY_QUERY, ReadMode::Lenient)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use assert_fs::NamedTempFile;
+
+    use super::*;
+
+    const CHESS: &str = "8D8A1F2C3B4E";
+    const RUST: &str = "A1B2C3D4E5F6";
+
+    #[test]
+    fn read_books() {
+        let books = read_fixture();
+
+        assert_eq!(2, books.len());
+        assert_eq!("How Life Imitates Chess", books[0].title());
+        assert_eq!("Garry Kasparov", books[0].authors());
+        assert_eq!("Rustonomicon", books[1].title());
+    }
+
+    #[test]
+    fn skip_deleted_annotations() {
+        let books = read_fixture();
+
+        assert_eq!(2, books[0].highlights().len());
+        assert_eq!(1, books[1].highlights().len());
+    }
+
+    #[test]
+    fn order_highlights_by_cfi() {
+        let books = read_fixture();
+        let highlights = books[0].highlights();
+
+        match &highlights[0] {
+            Highlight::Quote {
+                quote, location, ..
+            } => {
+                assert_eq!("we discard our decisions", quote);
+                assert_eq!(40_100, location.value());
+                assert_eq!(
+                    "ibooks://assetid/8D8A1F2C3B4E#epubcfi(/6/8[chap01]!/4/2,/1:0,/1:24)",
+                    location.link()
+                );
+            }
+            other => panic!("expected quote, got {:?}", other),
+        }
+        match &highlights[1] {
+            Highlight::Comment {
+                quote,
+                note,
+                location,
+                ..
+            } => {
+                assert_eq!("Why this move?", quote);
+                assert_eq!("Every move needs a purpose", note);
+                assert_eq!(60_500, location.value());
+            }
+            other => panic!("expected comment, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn read_dates_and_colors() {
+        let books = read_fixture();
+        let highlights = books[0].highlights();
+
+        assert_eq!(None, highlights[0].color());
+        assert_eq!(Some(Color::Pink), highlights[1].color());
+        assert_eq!(
+            Timestamp::parse("2022-10-04 10:12:01"),
+            highlights[1].created()
+        );
+        assert_eq!(
+            Timestamp::parse("2022-10-04 10:13:20"),
+            highlights[1].modified()
+        );
+    }
+
+    #[test]
+    fn read_annotations_without_library() {
+        let (annotations, _library) = fixture_databases();
+        let file = File::open(annotations.path()).unwrap();
+
+        let books: Vec<Book> = AppleBooksAnnotations::from_reader(file).unwrap().into();
+
+        assert_eq!(2, books.len());
+        assert_eq!(CHESS, books[0].title());
+    }
+
+    #[test]
+    fn skip_broken_annotations_leniently() {
+        let (annotations, library) = fixture_databases();
+        Connection::open(annotations.path())
+            .unwrap()
+            .execute_batch(&format!(
+                "INSERT INTO ZAEANNOTATION VALUES
+                    (6, '{}', X'FF', NULL, 'epubcfi(/6/16!/4/2/1:0)', 0, NULL, NULL, 3);",
+                CHESS
+            ))
+            .unwrap();
+
+        assert!(AppleBooksAnnotations::open(annotations.path(), library.path()).is_err());
+        let (annotations, warnings) =
+            AppleBooksAnnotations::open_lenient(annotations.path(), library.path()).unwrap();
+        let books: Vec<Book> = annotations.into();
+
+        assert_eq!(2, books[0].highlights().len());
+        assert_eq!(1, warnings.len());
+    }
+
+    #[test]
+    fn decode_cfi_steps() {
+        let steps = cfi_steps("epubcfi(/6/24[chap05]!/4/2[para-3]/6,/1:10,/1:120)");
+
+        assert_eq!(vec![6, 24, 4, 2, 6, 1, 10], steps);
+    }
+
+    #[test]
+    fn skip_steps_too_large() {
+        let steps = cfi_steps("epubcfi(/6/99999999999999999999999!/4/2,/1:0)");
+
+        assert_eq!(vec![6, 4, 2, 1, 0], steps);
+    }
+
+    #[test]
+    fn derive_location_from_cfi() {
+        assert_eq!(
+            120_103,
+            cfi_location("epubcfi(/6/24[chap05]!/4/2[para-3]/6,/1:10,/1:120)")
+        );
+        assert_eq!(40_100, cfi_location("epubcfi(/6/8!/4/2/1:0)"));
+        assert_eq!(49_900, cfi_location("epubcfi(/6/8!/4/400/1:0)"));
+        assert_eq!(0, cfi_location("not a cfi"));
+    }
+
+    #[test]
+    fn keep_locations_when_highlights_are_added() {
+        let (annotations, library) = fixture_databases();
+        let before = AppleBooksAnnotations::open(annotations.path(), library.path()).unwrap();
+        Connection::open(annotations.path())
+            .unwrap()
+            .execute_batch(&format!(
+                "INSERT INTO ZAEANNOTATION VALUES
+                    (6, '{}', 'Earlier', NULL, 'epubcfi(/6/4!/4/2,/1:0,/1:7)', 0, NULL, NULL, 3);",
+                CHESS
+            ))
+            .unwrap();
+        let after = AppleBooksAnnotations::open(annotations.path(), library.path()).unwrap();
+
+        let before: Vec<Book> = before.into();
+        let after: Vec<Book> = after.into();
+        let locations = |book: &Book| -> Vec<usize> {
+            book.highlights()
+                .iter()
+                .map(|highlight| highlight.location().value())
+                .collect()
+        };
+        assert_eq!(locations(&before[0])[..], locations(&after[0])[1..]);
+    }
+
+    #[test]
+    fn compare_cfi_numerically() {
+        let earlier = Some("epubcfi(/6/8!/4/2,/1:9,/1:20)".to_owned());
+        let later = Some("epubcfi(/6/8!/4/2,/1:10,/1:20)".to_owned());
+
+        assert_eq!(Ordering::Less, compare_cfi(&earlier, &later));
+    }
+
+    fn read_fixture() -> Vec<Book> {
+        let (annotations, library) = fixture_databases();
+        AppleBooksAnnotations::open(annotations.path(), library.path())
+            .unwrap()
+            .into()
+    }
+
+    fn fixture_databases() -> (NamedTempFile, NamedTempFile) {
+        let annotations = NamedTempFile::new("AEAnnotation.sqlite").unwrap();
+        Connection::open(annotations.path())
+            .unwrap()
+            .execute_batch(&format!(
+                "CREATE TABLE ZAEANNOTATION (
+                    Z_PK INTEGER PRIMARY KEY,
+                    ZANNOTATIONASSETID TEXT,
+                    ZANNOTATIONSELECTEDTEXT TEXT,
+                    ZANNOTATIONNOTE TEXT,
+                    ZANNOTATIONLOCATION TEXT,
+                    ZANNOTATIONDELETED INTEGER,
+                    ZANNOTATIONCREATIONDATE TIMESTAMP,
+                    ZANNOTATIONMODIFICATIONDATE TIMESTAMP,
+                    ZANNOTATIONSTYLE INTEGER
+                );
+                INSERT INTO ZAEANNOTATION VALUES
+                    (1, '{chess}', 'Why this move?', 'Every move needs a purpose',
+                        'epubcfi(/6/12[chap03]!/4/10,/1:0,/1:14)', 0, 686571121.5, 686571200.0, 4),
+                    (2, '{chess}', 'we discard our decisions', NULL,
+                        'epubcfi(/6/8[chap01]!/4/2,/1:0,/1:24)', 0, NULL, NULL, 0),
+                    (3, '{chess}', 'removed highlight', NULL,
+                        'epubcfi(/6/10[chap02]!/4/2,/1:0,/1:17)', 1, NULL, NULL, 3),
+                    (4, '{chess}', NULL, NULL, 'epubcfi(/6/14!/4/2/1:0)', 0, NULL, NULL, 3),
+                    (5, '{rust}', 'Unsafe Rust is dangerous', NULL,
+                        'epubcfi(/6/4[intro]!/4/6,/1:0,/1:24)', 0, NULL, NULL, 3);",
+                chess = CHESS,
+                rust = RUST,
+            ))
+            .unwrap();
+
+        let library = NamedTempFile::new("BKLibrary.sqlite").unwrap();
+        Connection::open(library.path())
+            .unwrap()
+            .execute_batch(&format!(
+                "CREATE TABLE ZBKLIBRARYASSET (
+                    Z_PK INTEGER PRIMARY KEY,
+                    ZASSETID TEXT,
+                    ZTITLE TEXT,
+                    ZAUTHOR TEXT
+                );
+                INSERT INTO ZBKLIBRARYASSET VALUES
+                    (1, '{chess}', 'How Life Imitates Chess', 'Garry Kasparov'),
+                    (2, '{rust}', 'Rustonomicon', 'The Rust Community');",
+                chess = CHESS,
+                rust = RUST,
+            ))
+            .unwrap();
+
+        (annotations, library)
+    }
+}
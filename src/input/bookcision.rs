@@ -3,10 +3,11 @@
 use std::io::Read;
 
 use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
 
 use crate::error::HighlightError;
 use crate::highlights::{Book, Highlight, Location};
-use crate::input::HighlightsRead;
+use crate::input::{json_error, json_reason, position, HighlightsRead, ReadMode, Warning};
 
 /// JSON representation of bookcision kindle highlights export.
 #[derive(Serialize, Deserialize, Debug)]
@@ -17,12 +18,27 @@ pub struct JsonBook {
     highlights: Vec<JsonHighlight>,
 }
 
+/// Bookcision export with highlights kept as raw JSON, so each one is checked on its own
+/// and problems are reported at the position of the highlight.
+#[derive(Deserialize)]
+struct RawJsonBook<'a> {
+    #[serde(default)]
+    asin: String,
+    title: String,
+    #[serde(default)]
+    authors: String,
+    #[serde(default, borrow)]
+    highlights: Vec<&'a RawValue>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 struct JsonHighlight {
-    text: String,
-    #[serde(rename = "isNoteOnly")]
+    #[serde(default)]
+    text: Option<String>,
+    #[serde(rename = "isNoteOnly", default)]
     is_note_only: bool,
-    location: JsonHighlightLocation,
+    #[serde(default)]
+    location: Option<JsonHighlightLocation>,
     note: Option<String>,
 }
 
@@ -34,34 +50,144 @@ struct JsonHighlightLocation {
 
 impl From<JsonBook> for Book {
     /// Convert json representation of highlights into a book.
+    ///
+    /// Highlights without any text are skipped.
+    /// `.tag` words at the start of notes become tags.
     fn from(json: JsonBook) -> Self {
-        let highlights = json.highlights.iter().map(Highlight::from);
-        Book::new(json.title.clone(), json.authors.clone(), highlights)
+        let highlights = json
+            .highlights
+            .iter()
+            .filter_map(|h| h.convert().0)
+            .map(Highlight::with_note_tags);
+        let book = Book::new(json.title.clone(), json.authors.clone(), highlights);
+        if json.asin.is_empty() {
+            book
+        } else {
+            book.with_asin(json.asin)
+        }
     }
 }
 
-impl From<&JsonHighlight> for Highlight {
-    fn from(h: &JsonHighlight) -> Self {
-        let location = Location::new(h.location.value as usize, h.location.url.clone());
-        let quote = h.text.clone();
-        let note = h.note.clone();
-        if h.is_note_only {
-            Highlight::note(note.unwrap(), location)
-        } else {
-            match note {
-                Some(note_text) => Highlight::comment(quote, note_text, location),
-                None => Highlight::quote(quote, location),
+impl From<JsonBook> for Vec<Book> {
+    /// Bookcision exports always contain exactly one book.
+    fn from(json: JsonBook) -> Self {
+        vec![json.into()]
+    }
+}
+
+impl JsonHighlight {
+    /// Converts the entry to the highlight repairing what can be repaired.
+    ///
+    /// Returns the problems found in the entry with the applied fixes next to the highlight.
+    /// The highlight is `None` when the entry has no text to keep.
+    fn convert(&self) -> (Option<Highlight>, Vec<(&'static str, &'static str)>) {
+        let mut problems = vec![];
+        let location = match &self.location {
+            Some(location) => Location::new(location.value as usize, location.url.clone()),
+            None => {
+                problems.push(("missing location", "used location 0"));
+                Location::new(0, "")
+            }
+        };
+        let text = non_blank(&self.text);
+        let note = non_blank(&self.note);
+        let highlight = match (self.is_note_only, text, note) {
+            (true, _, Some(note)) => Some(Highlight::note(note, location)),
+            (true, Some(text), None) => {
+                problems.push(("missing note", "used the highlight text"));
+                Some(Highlight::note(text, location))
+            }
+            (false, Some(text), Some(note)) => Some(Highlight::comment(text, note, location)),
+            (false, Some(text), None) => Some(Highlight::quote(text, location)),
+            (false, None, Some(note)) => {
+                problems.push(("empty highlight text", "kept the note"));
+                Some(Highlight::note(note, location))
+            }
+            (_, None, None) => {
+                problems.push(("empty highlight", "skipped"));
+                None
+            }
+        };
+        (highlight, problems)
+    }
+}
+
+fn non_blank(text: &Option<String>) -> Option<&str> {
+    text.as_deref().filter(|text| !text.trim().is_empty())
+}
+
+const INVALID_JSON: &str = "invalid bookcision json file";
+
+impl JsonBook {
+    /// Reads the export checking every highlight.
+    ///
+    /// Strict mode fails at the first broken highlight,
+    /// lenient mode skips or repairs broken highlights and reports them as warnings.
+    fn parse(content: &str, mode: ReadMode) -> Result<(Self, Vec<Warning>), HighlightError> {
+        let raw: RawJsonBook =
+            serde_json::from_str(content).map_err(|e| json_error(INVALID_JSON, e))?;
+        let mut warnings = vec![];
+        let mut highlights = vec![];
+        for (index, value) in raw.highlights.into_iter().enumerate() {
+            let entry = index + 1;
+            let offset = value.get().as_ptr() as usize - content.as_ptr() as usize;
+            let (line, column) = position(content, offset);
+            let problems = match serde_json::from_str::<JsonHighlight>(value.get()) {
+                Ok(highlight) => {
+                    let problems = highlight.convert().1;
+                    highlights.push(highlight);
+                    problems
+                        .into_iter()
+                        .map(|(problem, fix)| (problem.to_owned(), fix, line, column))
+                        .collect()
+                }
+                Err(e) => {
+                    // Positions inside the highlight are relative to its first line.
+                    let column = if e.line() == 1 {
+                        column + e.column() - 1
+                    } else {
+                        e.column()
+                    };
+                    vec![(json_reason(&e), "skipped", line + e.line() - 1, column)]
+                }
+            };
+            for (problem, fix, line, column) in problems {
+                match mode {
+                    ReadMode::Strict => {
+                        return Err(HighlightError::parse(INVALID_JSON, problem, line, column))
+                    }
+                    ReadMode::Lenient => {
+                        warnings.push(Warning::new(entry, format!("{}, {}", problem, fix)))
+                    }
+                }
             }
         }
+        let book = JsonBook {
+            asin: raw.asin,
+            title: raw.title,
+            authors: raw.authors,
+            highlights,
+        };
+        Ok((book, warnings))
+    }
+
+    fn read(mut reader: impl Read, mode: ReadMode) -> Result<(Self, Vec<Warning>), HighlightError> {
+        let mut content = String::new();
+        reader
+            .read_to_string(&mut content)
+            .map_err(|e| HighlightError::format(INVALID_JSON, e))?;
+        JsonBook::parse(&content, mode)
     }
 }
 
 impl HighlightsRead for JsonBook {
     fn from_reader(reader: impl Read) -> Result<Self, HighlightError> {
-        let b: JsonBook = serde_json::from_reader(reader).map_err(|e| {
-            HighlightError::format("invalid bookcision json file", std::io::Error::from(e))
-        })?;
-        Ok(b)
+        Ok(JsonBook::read(reader, ReadMode::Strict)?.0)
+    }
+
+    /// Reads highlights one by one, so a broken highlight does not fail the whole book.
+    fn from_reader_lenient(reader: impl Read) -> Result<(Self, Vec<Warning>), HighlightError> {
+        JsonBook::read(reader, ReadMode::Lenient)
     }
 }
 
@@ -115,6 +241,104 @@ mod tests {
         assert_eq!(2, book.highlights.len());
     }
 
+    #[test]
+    fn convert_note_tags() {
+        let book_json = r#"{ "asin": "", "title": "Title", "authors": "Author", "highlights": [
+            { "text": "Quote", "isNoteOnly": false, "location": { "url": "", "value": 1 }, "note": ".chess .plans" }
+        ] }"#;
+
+        let book: Book = JsonBook::from_str(book_json).into();
+
+        assert_eq!(
+            vec![Highlight::quote("Quote", Location::new(1, "")).with_tags(["chess", "plans"])],
+            book.highlights()
+        );
+    }
+
+    #[test]
+    fn convert_asin() {
+        let book_json =
+            r#"{ "asin": "B0049U443Q", "title": "Title", "authors": "Author", "highlights": [] }"#;
+
+        let book: Book = JsonBook::from_str(book_json).into();
+
+        assert_eq!(Some("B0049U443Q"), book.asin());
+    }
+
+    const BROKEN: &str = r#"{
+  "asin": "",
+  "title": "Title",
+  "authors": "Author",
+  "highlights": [
+    { "text": null, "isNoteOnly": false, "location": { "url": "", "value": 1 }, "note": null },
+    { "text": "Quote", "isNoteOnly": true, "location": { "url": "", "value": 2 }, "note": null },
+    { "text": "", "isNoteOnly": false, "location": { "url": "", "value": 3 }, "note": "Note" },
+    { "text": "Quote", "isNoteOnly": false, "note": null },
+    { "text": 42, "isNoteOnly": false, "location": { "url": "", "value": 5 }, "note": null },
+    { "text": "Fine", "isNoteOnly": false, "location": { "url": "", "value": 6 }, "note": null }
+  ]
+}"#;
+
+    #[test]
+    fn reject_note_only_without_note() {
+        let book_json = r#"{ "asin": "", "title": "Title", "authors": "Author", "highlights": [
+            { "text": "", "isNoteOnly": true, "location": { "url": "", "value": 1 }, "note": null }
+        ] }"#;
+
+        let error = JsonBook::from_reader(book_json.as_bytes()).unwrap_err();
+
+        match error {
+            HighlightError::Parse(error) => {
+                assert_eq!("empty highlight", error.reason());
+                assert_eq!((2, 13), (error.line(), error.column()));
+            }
+            other => panic!("expected parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn report_type_error_position() {
+        let book_json = r#"{ "asin": "", "title": "Title", "authors": "Author", "highlights": [
+            {
+              "text": 42, "isNoteOnly": false, "location": { "url": "", "value": 1 } }
+        ] }"#;
+
+        let error = JsonBook::from_reader(book_json.as_bytes()).unwrap_err();
+
+        match error {
+            HighlightError::Parse(error) => {
+                assert!(error.reason().starts_with("invalid type"), "{:?}", error);
+                assert_eq!((3, 24), (error.line(), error.column()));
+            }
+            other => panic!("expected parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn repair_broken_highlights() {
+        let (json, warnings) = JsonBook::from_reader_lenient(BROKEN.as_bytes()).unwrap();
+        let book: Book = json.into();
+
+        assert_eq!(
+            vec![
+                Highlight::note("Quote", Location::new(2, "")),
+                Highlight::note("Note", Location::new(3, "")),
+                Highlight::quote("Quote", Location::new(0, "")),
+                Highlight::quote("Fine", Location::new(6, "")),
+            ],
+            book.highlights()
+        );
+        let entries: Vec<usize> = warnings.iter().map(Warning::entry).collect();
+        assert_eq!(vec![1, 2, 3, 4, 5], entries);
+        assert_eq!("empty highlight, skipped", warnings[0].message());
+        assert!(warnings[4].message().ends_with("skipped"));
+    }
+
+    #[test]
+    fn fail_strictly_on_broken_highlights() {
+        assert!(JsonBook::from_reader(BROKEN.as_bytes()).is_err());
+    }
+
     impl JsonBook {
         fn from_str(value: &str) -> Self {
             serde_json::from_str(value).unwrap()
@@ -0,0 +1,608 @@
+//! Import highlights from the kindle `My Clippings.txt` file.
+//!
+//! Kindle devices append every highlight, note and bookmark to the `documents/My Clippings.txt` file.
+//! Entries are separated by the `==========` line and have the following structure:
+//!
+//! ```text
+//! How Life Imitates Chess (Garry Kasparov)
+//! - Your Highlight on page 12 | Location 157-159 | Added on Tuesday, 4 October 2022 10:02:44
+//!
+//! the reality is that we discard our decisions almost as soon as we make them
+//! ==========
+//! ```
+//!
+//! One clippings file contains highlights from many books.
+use std::io::Read;
+
+use crate::error::HighlightError;
+use crate::highlights::details::Timestamp;
+use crate::highlights::{Book, Highlight, Location};
+use crate::input::{HighlightsRead, ReadMode, Warning};
+
+const SEPARATOR: &str = "==========";
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// Parsed content of the kindle clippings file.
+#[derive(Debug)]
+pub struct Clippings {
+    clippings: Vec<Clipping>,
+}
+
+#[derive(Debug)]
+struct Clipping {
+    title: String,
+    authors: String,
+    kind: ClippingKind,
+    page: Option<usize>,
+    location: Option<(usize, usize)>,
+    added: Option<Timestamp>,
+    text: String,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+struct Metadata {
+    kind: ClippingKind,
+    page: Option<usize>,
+    location: Option<(usize, usize)>,
+    added: Option<Timestamp>,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+enum ClippingKind {
+    Highlight,
+    Note,
+    Bookmark,
+}
+
+impl Clippings {
+    /// Parses the clippings file.
+    ///
+    /// Lenient mode skips malformed and empty entries reporting them as warnings.
+    fn parse(content: &str, mode: ReadMode) -> Result<(Self, Vec<Warning>), HighlightError> {
+        let content = content.trim_start_matches('\u{feff}');
+        let mut entries = vec![vec![]];
+        for (index, line) in content.lines().enumerate() {
+            if line.trim() == SEPARATOR {
+                entries.push(vec![]);
+            } else if let Some(entry) = entries.last_mut() {
+                entry.push((index + 1, line));
+            }
+        }
+
+        let mut clippings = vec![];
+        let mut warnings = vec![];
+        for (index, entry) in entries.iter().enumerate() {
+            let warning = match (Clipping::parse(entry), mode) {
+                (Ok(None), _) => None,
+                (Ok(Some(clipping)), ReadMode::Lenient)
+                    if clipping.kind != ClippingKind::Bookmark && clipping.text.is_empty() =>
+                {
+                    Some("empty clipping, skipped".to_owned())
+                }
+                (Ok(Some(clipping)), _) => {
+                    clippings.push(clipping);
+                    None
+                }
+                (Err(error), ReadMode::Lenient) => Some(format!("{}, skipped", error.message)),
+                (Err(error), ReadMode::Strict) => return Err(error.into()),
+            };
+            warnings.extend(warning.map(|message| Warning::new(index + 1, message)));
+        }
+        Ok((Clippings { clippings }, warnings))
+    }
+}
+
+impl Clipping {
+    /// Parses the numbered lines of a single entry.
+    ///
+    /// Returns `None` for the blank entries.
+    fn parse(lines: &[(usize, &str)]) -> Result<Option<Self>, ClippingError> {
+        let mut lines = lines
+            .iter()
+            .map(|(number, line)| (*number, line.trim_start_matches('\u{feff}')))
+            .skip_while(|(_, line)| line.trim().is_empty());
+        let (heading_line, heading) = match lines.next() {
+            Some((number, heading)) => (number, heading.trim()),
+            None => return Ok(None),
+        };
+        let (metadata_line, metadata) = lines.next().ok_or_else(|| ClippingError {
+            line: heading_line,
+            column: 1,
+            message: format!("missing metadata line for '{}'", heading),
+        })?;
+        let (title, authors) = parse_heading(heading);
+        let Metadata {
+            kind,
+            page,
+            location,
+            added,
+        } = parse_metadata(metadata).map_err(|message| ClippingError {
+            line: metadata_line,
+            column: metadata.chars().take_while(|c| c.is_whitespace()).count() + 1,
+            message,
+        })?;
+        let text = lines
+            .map(|(_, line)| line)
+            .collect::<Vec<&str>>()
+            .join("\n")
+            .trim()
+            .to_owned();
+
+        Ok(Some(Clipping {
+            title,
+            authors,
+            kind,
+            page,
+            location,
+            added,
+            text,
+        }))
+    }
+
+    fn location_value(&self) -> usize {
+        match (self.location, self.page) {
+            (Some((start, _)), _) => start,
+            (None, Some(page)) => page,
+            (None, None) => 0,
+        }
+    }
+
+    fn contains(&self, other: &Clipping) -> bool {
+        match (self.location, other.location) {
+            (Some((start, end)), Some((location, _))) => start <= location && location <= end,
+            (None, None) => self.page.is_some() && self.page == other.page,
+            _ => false,
+        }
+    }
+
+    fn location(&self) -> Location {
+        let value = self.location_value();
+        Location::new(
+            value,
+            format!("kindle://book?action=open&location={}", value),
+        )
+    }
+}
+
+/// Splits the `Title (Author)` heading into the title and authors.
+fn parse_heading(heading: &str) -> (String, String) {
+    if !heading.ends_with(')') {
+        return (heading.to_owned(), String::new());
+    }
+    let mut depth = 0;
+    for (index, c) in heading.char_indices().rev() {
+        match c {
+            ')' => depth += 1,
+            '(' => {
+                depth -= 1;
+                if depth == 0 {
+                    let title = heading[..index].trim();
+                    let authors = heading[index + 1..heading.len() - 1].trim();
+                    if title.is_empty() {
+                        break;
+                    }
+                    return (title.to_owned(), authors.to_owned());
+                }
+            }
+            _ => {}
+        }
+    }
+    (heading.to_owned(), String::new())
+}
+
+/// Parses the `- Your Highlight on page 12 | Location 157-159 | Added on ...` line.
+fn parse_metadata(line: &str) -> Result<Metadata, String> {
+    let metadata = line.trim().trim_start_matches('-').trim().to_lowercase();
+    let kind = if metadata.starts_with("your highlight") {
+        ClippingKind::Highlight
+    } else if metadata.starts_with("your note") {
+        ClippingKind::Note
+    } else if metadata.starts_with("your bookmark") {
+        ClippingKind::Bookmark
+    } else {
+        return Err(format!("unrecognised clipping metadata '{}'", line.trim()));
+    };
+
+    let mut page = None;
+    let mut location = None;
+    let mut added = None;
+    for part in metadata.split('|') {
+        if let Some(value) = value_after(part, "page ") {
+            page = parse_range(value).map(|(start, _)| start);
+        }
+        if let Some(value) = value_after(part, "location ") {
+            location = parse_range(value);
+        }
+        if let Some(value) = value_after(part, "added on ") {
+            added = parse_added(value);
+        }
+    }
+    Ok(Metadata {
+        kind,
+        page,
+        location,
+        added,
+    })
+}
+
+/// Parses the English date of the clipping, e.g. `tuesday, 4 october 2022 10:02:44`
+/// or `monday, october 3, 2022 10:12:01 pm`.
+///
+/// Dates without the time start at midnight, dates in other languages are ignored.
+fn parse_added(value: &str) -> Option<Timestamp> {
+    let (mut year, mut month, mut day, mut time, mut meridiem) = (None, None, None, None, None);
+    for word in value.split(|c: char| c == ',' || c.is_whitespace()) {
+        if word.contains(':') {
+            time = Some(word);
+        } else if word == "am" || word == "pm" {
+            meridiem = Some(word);
+        } else if word.len() == 4 && word.chars().all(|c| c.is_ascii_digit()) {
+            year = word.parse().ok();
+        } else if !word.is_empty() && word.chars().all(|c| c.is_ascii_digit()) {
+            day = word.parse().ok();
+        } else if let Some(index) = MONTHS.iter().position(|name| word.starts_with(name)) {
+            month = Some(index as u32 + 1);
+        }
+    }
+    let mut time = time
+        .unwrap_or("0:0")
+        .split(':')
+        .map(|part| part.parse::<u32>().ok());
+    let mut hour = time.next()??;
+    let minute = time.next()??;
+    let second = time.next().unwrap_or(Some(0))?;
+    match meridiem {
+        Some("pm") if hour < 12 => hour += 12,
+        Some("am") if hour == 12 => hour = 0,
+        _ => {}
+    }
+    Timestamp::new(year?, month?, day?, hour, minute, second)
+}
+
+fn value_after<'a>(part: &'a str, marker: &str) -> Option<&'a str> {
+    part.find(marker)
+        .map(|index| part[index + marker.len()..].trim())
+}
+
+fn parse_range(value: &str) -> Option<(usize, usize)> {
+    let value = value.split_whitespace().next()?;
+    match value.split_once('-') {
+        Some((start, end)) => {
+            let start = start.parse().ok()?;
+            let end = end.parse().unwrap_or(start);
+            Some((start, end))
+        }
+        None => {
+            let start = value.parse().ok()?;
+            Some((start, start))
+        }
+    }
+}
+
+/// Malformed entry at the line and column of the clippings file.
+struct ClippingError {
+    line: usize,
+    column: usize,
+    message: String,
+}
+
+impl From<ClippingError> for HighlightError {
+    fn from(error: ClippingError) -> Self {
+        HighlightError::parse(
+            "invalid kindle clippings file",
+            error.message,
+            error.line,
+            error.column,
+        )
+    }
+}
+
+impl From<Clippings> for Vec<Book> {
+    /// Groups clippings by book.
+    ///
+    /// Notes are attached to the highlight at the same location and become comments.
+    /// `.tag` words at the start of notes become tags.
+    /// Bookmarks carry no text and are skipped.
+    fn from(clippings: Clippings) -> Self {
+        let mut grouped: Vec<(String, String, Vec<Clipping>)> = vec![];
+        for clipping in clippings.clippings {
+            if clipping.kind == ClippingKind::Bookmark {
+                continue;
+            }
+            let book = grouped.iter_mut().find(|(title, authors, _)| {
+                *title == clipping.title && *authors == clipping.authors
+            });
+            match book {
+                Some((_, _, entries)) => entries.push(clipping),
+                None => grouped.push((
+                    clipping.title.clone(),
+                    clipping.authors.clone(),
+                    vec![clipping],
+                )),
+            }
+        }
+
+        grouped
+            .into_iter()
+            .map(|(title, authors, entries)| Book::new(title, authors, pair_notes(entries)))
+            .collect()
+    }
+}
+
+/// Converts book clippings to highlights pairing each note with the highlight at its location.
+fn pair_notes(entries: Vec<Clipping>) -> Vec<Highlight> {
+    let mut notes: Vec<Option<String>> = entries.iter().map(|_| None).collect();
+    let mut paired = vec![false; entries.len()];
+    for (note_index, note) in entries.iter().enumerate() {
+        if note.kind != ClippingKind::Note {
+            continue;
+        }
+        let quote = entries.iter().enumerate().position(|(index, quote)| {
+            quote.kind == ClippingKind::Highlight && notes[index].is_none() && quote.contains(note)
+        });
+        if let Some(quote_index) = quote {
+            notes[quote_index] = Some(note.text.clone());
+            paired[note_index] = true;
+        }
+    }
+
+    entries
+        .iter()
+        .zip(notes)
+        .zip(paired)
+        .filter(|(_, paired)| !paired)
+        .map(|((clipping, note), _)| {
+            let highlight = match (&clipping.kind, note) {
+                (ClippingKind::Note, _) => {
+                    Highlight::note(clipping.text.clone(), clipping.location())
+                }
+                (_, Some(note)) => {
+                    Highlight::comment(clipping.text.clone(), note, clipping.location())
+                }
+                (_, None) => Highlight::quote(clipping.text.clone(), clipping.location()),
+            };
+            match clipping.added {
+                Some(added) => highlight.with_created(added),
+                None => highlight,
+            }
+            .with_note_tags()
+        })
+        .collect()
+}
+
+impl HighlightsRead for Clippings {
+    fn from_reader(mut reader: impl Read) -> Result<Self, HighlightError> {
+        let mut content = String::new();
+        reader
+            .read_to_string(&mut content)
+            .map_err(|e| HighlightError::format("invalid kindle clippings file", e))?;
+        Ok(Clippings::parse(&content, ReadMode::Strict)?.0)
+    }
+
+    fn from_reader_lenient(mut reader: impl Read) -> Result<(Self, Vec<Warning>), HighlightError> {
+        let mut content = String::new();
+        reader
+            .read_to_string(&mut content)
+            .map_err(|e| HighlightError::format("invalid kindle clippings file", e))?;
+        Clippings::parse(&content, ReadMode::Lenient)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIPPINGS: &str = "\u{feff}How Life Imitates Chess (Garry Kasparov)
+- Your Highlight on page 12 | Location 157-159 | Added on Tuesday, 4 October 2022 10:02:44
+
+the reality is that we discard our decisions almost as soon as we make them
+==========
+The Rustonomicon (The Rust Community)
+- Your Bookmark on Location 12 | Added on Tuesday, 4 October 2022 10:04:12
+
+
+==========
+How Life Imitates Chess (Garry Kasparov)
+- Your Highlight on page 25 | Location 445-447 | Added on Tuesday, 4 October 2022 10:05:01
+
+Why this move? What am I trying to achieve?
+==========
+How Life Imitates Chess (Garry Kasparov)
+- Your Note on page 25 | Location 447 | Added on Tuesday, 4 October 2022 10:05:30
+
+Each move should contribute to some strategical objective
+==========
+The Rustonomicon (The Rust Community)
+- Your Note on Location 305 | Added on Tuesday, 4 October 2022 10:07:00
+
+Check the nomicon chapter on variance
+==========
+";
+
+    #[test]
+    fn parse_books() {
+        let books = parse(CLIPPINGS);
+
+        assert_eq!(2, books.len());
+        assert_eq!("How Life Imitates Chess", books[0].title());
+        assert_eq!("Garry Kasparov", books[0].authors());
+        assert_eq!("The Rustonomicon", books[1].title());
+        assert_eq!("The Rust Community", books[1].authors());
+    }
+
+    #[test]
+    fn parse_quote() {
+        let books = parse(CLIPPINGS);
+        let highlights = books[0].highlights();
+
+        match &highlights[0] {
+            Highlight::Quote {
+                quote, location, ..
+            } => {
+                assert_eq!(
+                    "the reality is that we discard our decisions almost as soon as we make them",
+                    quote
+                );
+                assert_eq!(157, location.value());
+            }
+            other => panic!("expected quote, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn pair_note_with_highlight() {
+        let books = parse(CLIPPINGS);
+        let highlights = books[0].highlights();
+
+        assert_eq!(2, highlights.len());
+        match &highlights[1] {
+            Highlight::Comment {
+                quote,
+                note,
+                location,
+                ..
+            } => {
+                assert_eq!("Why this move? What am I trying to achieve?", quote);
+                assert_eq!(
+                    "Each move should contribute to some strategical objective",
+                    note
+                );
+                assert_eq!(445, location.value());
+            }
+            other => panic!("expected comment, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn keep_standalone_note() {
+        let books = parse(CLIPPINGS);
+        let highlights = books[1].highlights();
+
+        assert_eq!(1, highlights.len(), "bookmarks must be skipped");
+        match &highlights[0] {
+            Highlight::Note { note, location, .. } => {
+                assert_eq!("Check the nomicon chapter on variance", note);
+                assert_eq!(305, location.value());
+            }
+            other => panic!("expected note, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_heading_with_parentheses_in_title() {
+        let (title, authors) = parse_heading("Rust (2nd Edition) (Steve Klabnik; Carol Nichols)");
+
+        assert_eq!("Rust (2nd Edition)", title);
+        assert_eq!("Steve Klabnik; Carol Nichols", authors);
+    }
+
+    #[test]
+    fn parse_heading_without_authors() {
+        let (title, authors) = parse_heading("Personal document");
+
+        assert_eq!("Personal document", title);
+        assert_eq!("", authors);
+    }
+
+    #[test]
+    fn parse_location_only_metadata() {
+        let metadata = parse_metadata(
+            "- Your Highlight at location 1202-1204 | Added on Sunday, 2 October 2022",
+        )
+        .unwrap();
+
+        assert_eq!(ClippingKind::Highlight, metadata.kind);
+        assert_eq!(None, metadata.page);
+        assert_eq!(Some((1202, 1204)), metadata.location);
+        assert_eq!(Timestamp::parse("2022-10-02"), metadata.added);
+    }
+
+    #[test]
+    fn parse_added_dates() {
+        assert_eq!(
+            Timestamp::parse("2022-10-04 10:02:44"),
+            parse_added("tuesday, 4 october 2022 10:02:44")
+        );
+        assert_eq!(
+            Timestamp::parse("2022-10-03 22:12:01"),
+            parse_added("monday, october 3, 2022 10:12:01 pm")
+        );
+        assert_eq!(
+            Timestamp::parse("2022-10-03 00:05:00"),
+            parse_added("monday, october 3, 2022 12:05:00 am")
+        );
+        assert_eq!(None, parse_added("2022年10月4日星期二 10:02:44"));
+    }
+
+    #[test]
+    fn read_creation_time_and_note_tags() {
+        let content = "Title (Author)\n\
+                       - Your Highlight on Location 1 | Added on Tuesday, 4 October 2022 10:02:44\n\n\
+                       Quote\n==========\n\
+                       Title (Author)\n\
+                       - Your Note on Location 1 | Added on Tuesday, 4 October 2022 10:03:00\n\n\
+                       .idea\n==========\n";
+
+        let books: Vec<Book> = Clippings::from_reader(content.as_bytes()).unwrap().into();
+        let highlight = &books[0].highlights()[0];
+
+        assert!(matches!(highlight, Highlight::Quote { .. }));
+        assert_eq!(Timestamp::parse("2022-10-04 10:02:44"), highlight.created());
+        assert_eq!(["idea"], highlight.tags());
+    }
+
+    #[test]
+    fn reject_unknown_metadata() {
+        let result = Clippings::from_reader(
+            "Title (Author)\n- Something else\n\ntext\n==========".as_bytes(),
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn report_error_line() {
+        let content = "Title (Author)\n- Your Highlight on Location 1\n\nQuote\n==========\n\
+                       \n  Title (Author)\n  - Something else\n==========\n";
+
+        match Clippings::from_reader(content.as_bytes()) {
+            Err(HighlightError::Parse(error)) => {
+                assert_eq!((8, 3), (error.line(), error.column()));
+                assert_eq!(
+                    "unrecognised clipping metadata '- Something else'",
+                    error.reason()
+                );
+            }
+            other => panic!("expected parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn skip_broken_entries_leniently() {
+        let content = "Title (Author)\n- Something else\n\ntext\n==========\n\
+                       Title (Author)\n- Your Highlight on Location 2\n\n\n==========\n\
+                       Title (Author)\n- Your Highlight on Location 3\n\nQuote\n==========\n";
+
+        let (clippings, warnings) = Clippings::from_reader_lenient(content.as_bytes()).unwrap();
+        let books: Vec<Book> = clippings.into();
+
+        assert_eq!(1, books[0].highlights().len());
+        assert_eq!(
+            vec![
+                Warning::new(
+                    1,
+                    "unrecognised clipping metadata '- Something else', skipped"
+                ),
+                Warning::new(2, "empty clipping, skipped"),
+            ],
+            warnings
+        );
+    }
+
+    fn parse(content: &str) -> Vec<Book> {
+        Clippings::from_reader(content.as_bytes()).unwrap().into()
+    }
+}
@@ -0,0 +1,694 @@
+//! Detection of the input format.
+//!
+//! Inputs are recognised by their content first and by the file extension when the content is ambiguous.
+use std::fmt::{Display, Formatter};
+use std::fs::{self, DirEntry, File};
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::error::HighlightError;
+use crate::highlights::library::Library;
+use crate::highlights::Book;
+use crate::input::apple_books::AppleBooksAnnotations;
+use crate::input::bookcision::JsonBook;
+use crate::input::clippings::Clippings;
+use crate::input::json::JsonDocuments;
+use crate::input::kindle_html::KindleNotebook;
+use crate::input::kobo::KoboBookmarks;
+use crate::input::koreader::KoreaderSidecars;
+use crate::input::markdown::MarkdownNotes;
+use crate::input::readwise::ReadwiseCsv;
+use crate::input::{skipped_file, sqlite, HighlightsRead, IntoBooks, ReadMode, Warning};
+
+const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+/// Length of the content start used to detect text formats.
+const HEAD_LENGTH: usize = 4096;
+
+/// Supported input formats.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InputFormat {
+    /// Bookcision json export.
+    Bookcision,
+    /// Kindle `My Clippings.txt` file.
+    Clippings,
+    /// Kindle notebook HTML export.
+    KindleHtml,
+    /// Kobo `KoboReader.sqlite` database.
+    Kobo,
+    /// KOReader sidecar file or library directory.
+    Koreader,
+    /// Apple Books annotation database.
+    AppleBooks,
+    /// Readwise CSV export.
+    Readwise,
+    /// JSON schema written by the json renderer.
+    Json,
+    /// Markdown notes written by the markdown renderer.
+    Markdown,
+}
+
+impl InputFormat {
+    /// All supported formats.
+    pub const ALL: [InputFormat; 9] = [
+        InputFormat::Bookcision,
+        InputFormat::Clippings,
+        InputFormat::KindleHtml,
+        InputFormat::Kobo,
+        InputFormat::Koreader,
+        InputFormat::AppleBooks,
+        InputFormat::Readwise,
+        InputFormat::Json,
+        InputFormat::Markdown,
+    ];
+
+    /// Format name used on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            InputFormat::Bookcision => "bookcision",
+            InputFormat::Clippings => "clippings",
+            InputFormat::KindleHtml => "kindle-html",
+            InputFormat::Kobo => "kobo",
+            InputFormat::Koreader => "koreader",
+            InputFormat::AppleBooks => "apple-books",
+            InputFormat::Readwise => "readwise",
+            InputFormat::Json => "json",
+            InputFormat::Markdown => "markdown",
+        }
+    }
+
+    /// Detects the format by the input content, falling back to the file extension.
+    ///
+    /// Databases are recognised by their tables, so the content must hold the whole input.
+    pub fn detect(path: Option<&Path>, content: &[u8]) -> Option<InputFormat> {
+        InputFormat::sniff(content).or_else(|| path.and_then(InputFormat::by_extension))
+    }
+
+    fn sniff(content: &[u8]) -> Option<InputFormat> {
+        if content.starts_with(SQLITE_HEADER) {
+            return sniff_database(content);
+        }
+        let head = &content[..content.len().min(HEAD_LENGTH)];
+        let head = String::from_utf8_lossy(head);
+        let text = head.trim_start_matches('\u{feff}').trim_start();
+        let first_line = text.lines().next().unwrap_or_default();
+        if text.starts_with('{') && text.contains("\"version\"") {
+            Some(InputFormat::Json)
+        } else if text.starts_with('{') && text.contains("\"asin\"") {
+            Some(InputFormat::Bookcision)
+        } else if text.starts_with('<')
+            && (text.contains("noteHeading") || text.contains("bookTitle"))
+        {
+            Some(InputFormat::KindleHtml)
+        } else if is_markdown(text) {
+            Some(InputFormat::Markdown)
+        } else if text.starts_with("--") || text.starts_with("return") {
+            Some(InputFormat::Koreader)
+        } else if text.lines().any(|line| line.trim() == "==========") {
+            Some(InputFormat::Clippings)
+        } else if first_line.contains("Highlight") && first_line.contains("Title") {
+            Some(InputFormat::Readwise)
+        } else {
+            None
+        }
+    }
+
+    fn by_extension(path: &Path) -> Option<InputFormat> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "json" => Some(InputFormat::Bookcision),
+            "txt" => Some(InputFormat::Clippings),
+            "html" | "htm" => Some(InputFormat::KindleHtml),
+            "lua" => Some(InputFormat::Koreader),
+            "csv" => Some(InputFormat::Readwise),
+            "md" | "markdown" => Some(InputFormat::Markdown),
+            _ => None,
+        }
+    }
+
+    /// Reads books from the input content in this format.
+    ///
+    /// Warnings list the entries skipped or repaired in the lenient mode.
+    pub fn read_books(
+        &self,
+        content: &[u8],
+        mode: ReadMode,
+    ) -> Result<(Vec<Book>, Vec<Warning>), HighlightError> {
+        match self {
+            InputFormat::Bookcision => read::<JsonBook>(content, mode),
+            InputFormat::Clippings => read::<Clippings>(content, mode),
+            InputFormat::KindleHtml => read::<KindleNotebook>(content, mode),
+            InputFormat::Kobo => read::<KoboBookmarks>(content, mode),
+            InputFormat::Koreader => read::<KoreaderSidecars>(content, mode),
+            InputFormat::AppleBooks => read::<AppleBooksAnnotations>(content, mode),
+            InputFormat::Readwise => read::<ReadwiseCsv>(content, mode),
+            InputFormat::Json => read::<JsonDocuments>(content, mode),
+            InputFormat::Markdown => read::<MarkdownNotes>(content, mode),
+        }
+    }
+}
+
+fn read<R: HighlightsRead + IntoBooks>(
+    content: &[u8],
+    mode: ReadMode,
+) -> Result<(Vec<Book>, Vec<Warning>), HighlightError> {
+    match mode {
+        ReadMode::Strict => Ok((R::from_reader(content)?.into_books(), vec![])),
+        ReadMode::Lenient => {
+            let (highlights, warnings) = R::from_reader_lenient(content)?;
+            Ok((highlights.into_books(), warnings))
+        }
+    }
+}
+
+/// Checks if the text starts with the book title heading, after the YAML frontmatter if any.
+fn is_markdown(text: &str) -> bool {
+    let mut lines = text.lines();
+    if text.starts_with("---") {
+        lines.by_ref().skip(1).find(|line| line.trim() == "---");
+    }
+    lines
+        .find(|line| !line.trim().is_empty())
+        .is_some_and(|line| line.starts_with("# "))
+}
+
+fn sniff_database(content: &[u8]) -> Option<InputFormat> {
+    let connection = sqlite::from_reader(content, "sqlite").ok()?;
+    let mut statement = connection
+        .prepare("SELECT name FROM sqlite_master WHERE type = 'table'")
+        .ok()?;
+    let tables = statement
+        .query_map([], |row| row.get::<_, String>(0))
+        .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+        .ok()?;
+    let has_table = |name: &str| tables.iter().any(|table| table == name);
+    if has_table("Bookmark") && has_table("content") {
+        Some(InputFormat::Kobo)
+    } else if has_table("ZAEANNOTATION") {
+        Some(InputFormat::AppleBooks)
+    } else {
+        None
+    }
+}
+
+impl Display for InputFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        InputFormat::ALL
+            .into_iter()
+            .find(|format| format.name() == name)
+            .ok_or_else(|| format!("unknown input format, supported formats: {}", supported()))
+    }
+}
+
+fn supported() -> String {
+    InputFormat::ALL
+        .iter()
+        .map(InputFormat::name)
+        .collect::<Vec<&str>>()
+        .join(", ")
+}
+
+fn unknown_format() -> HighlightError {
+    HighlightError::format(
+        format!(
+            "cannot detect input format, use --from with one of: {}",
+            supported()
+        ),
+        std::io::Error::new(ErrorKind::InvalidData, "unknown input format"),
+    )
+}
+
+/// Reads books from the input stream in the given or detected format.
+pub fn read_input(
+    mut reader: impl Read,
+    format: Option<InputFormat>,
+    mode: ReadMode,
+) -> Result<(Vec<Book>, Vec<Warning>), HighlightError> {
+    let mut content = vec![];
+    reader
+        .read_to_end(&mut content)
+        .map_err(|e| HighlightError::io("cannot read input", e))?;
+    let format = format
+        .or_else(|| InputFormat::detect(None, &content))
+        .ok_or_else(unknown_format)?;
+    format
+        .read_books(&content, mode)
+        .map_err(|e| e.with_source(None, &content))
+}
+
+/// Reads books from the file or directory in the given or detected format.
+///
+/// Directories are read as KOReader libraries.
+/// Apple Books annotations are joined with the `BKLibrary*.sqlite` database from the same directory when present,
+/// the first by name when there are several.
+pub fn read_path(
+    path: &Path,
+    format: Option<InputFormat>,
+    mode: ReadMode,
+) -> Result<(Vec<Book>, Vec<Warning>), HighlightError> {
+    if path.is_dir() {
+        return match format {
+            None | Some(InputFormat::Koreader) => read_koreader(path, mode),
+            Some(format) => Err(HighlightError::General(format!(
+                "{} input cannot be read from the directory: {}",
+                format,
+                path.display()
+            ))),
+        };
+    }
+
+    let content = read_file(path)?;
+    let format = format
+        .or_else(|| InputFormat::detect(Some(path), &content))
+        .ok_or_else(unknown_format)?;
+    read_file_content(path, &content, format, mode)
+}
+
+fn read_koreader(path: &Path, mode: ReadMode) -> Result<(Vec<Book>, Vec<Warning>), HighlightError> {
+    match mode {
+        ReadMode::Strict => Ok((KoreaderSidecars::open(path)?.into_books(), vec![])),
+        ReadMode::Lenient => {
+            let (sidecars, warnings) = KoreaderSidecars::open_lenient(path)?;
+            Ok((sidecars.into_books(), warnings))
+        }
+    }
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, HighlightError> {
+    fs::read(path)
+        .map_err(|e| HighlightError::io(format!("cannot read input file: {}", path.display()), e))
+}
+
+fn read_file_content(
+    path: &Path,
+    content: &[u8],
+    format: InputFormat,
+    mode: ReadMode,
+) -> Result<(Vec<Book>, Vec<Warning>), HighlightError> {
+    match (format, apple_books_library(path)) {
+        (InputFormat::AppleBooks, Some(library)) => match mode {
+            ReadMode::Strict => Ok((
+                AppleBooksAnnotations::open(path, library)?.into_books(),
+                vec![],
+            )),
+            ReadMode::Lenient => {
+                let (annotations, warnings) = AppleBooksAnnotations::open_lenient(path, library)?;
+                Ok((annotations.into_books(), warnings))
+            }
+        },
+        _ => format
+            .read_books(content, mode)
+            .map_err(|e| e.with_source(Some(path), content)),
+    }
+}
+
+/// Reads books from several files and directories into one library.
+///
+/// Files are read in the given or detected format.
+/// Directories are searched recursively: KOReader `.sdr` directories are read as sidecars,
+/// other files are read when their content is recognised and skipped otherwise.
+/// With the given format only the files recognised as that format are read from directories.
+/// Symbolic links to directories are not followed inside directories.
+/// In the lenient mode files found in directories that cannot be read are skipped with a warning.
+/// Warnings carry the path of the input file.
+pub fn read_paths(
+    paths: &[PathBuf],
+    format: Option<InputFormat>,
+    mode: ReadMode,
+) -> Result<(Library, Vec<Warning>), HighlightError> {
+    let mut library = Library::new();
+    let mut warnings = vec![];
+    for path in paths {
+        if path.is_dir() {
+            read_dir(path, format, mode, &mut library, &mut warnings)?;
+        } else {
+            let (books, file_warnings) = read_path(path, format, mode)?;
+            library.extend(books);
+            warnings.extend(file_warnings.into_iter().map(|w| w.with_path(path)));
+        }
+    }
+    Ok((library, warnings))
+}
+
+fn read_dir(
+    dir: &Path,
+    format: Option<InputFormat>,
+    mode: ReadMode,
+    library: &mut Library,
+    warnings: &mut Vec<Warning>,
+) -> Result<(), HighlightError> {
+    let cannot_read =
+        |e| HighlightError::io(format!("cannot read directory: {}", dir.display()), e);
+    let mut entries = fs::read_dir(dir)
+        .map_err(cannot_read)?
+        .collect::<Result<Vec<DirEntry>, _>>()
+        .map_err(cannot_read)?;
+    entries.sort_by_key(DirEntry::path);
+    for (index, entry) in entries.iter().enumerate() {
+        let path = entry.path();
+        // Symlinked directories are skipped, they may point back up the tree.
+        let file_type = entry.file_type().map_err(cannot_read)?;
+        let result = if file_type.is_dir() {
+            let is_sdr = path.extension().is_some_and(|extension| extension == "sdr");
+            match (is_sdr, format) {
+                (true, None | Some(InputFormat::Koreader)) => {
+                    read_koreader(&path, mode).map(|(books, sidecar_warnings)| {
+                        library.extend(books);
+                        warnings.extend(sidecar_warnings);
+                    })
+                }
+                (true, _) => Ok(()),
+                (false, _) => read_dir(&path, format, mode, library, warnings),
+            }
+        } else if file_type.is_file() || (file_type.is_symlink() && path.is_file()) {
+            read_dir_file(&path, format, mode).map(|(books, file_warnings)| {
+                library.extend(books);
+                warnings.extend(file_warnings.into_iter().map(|w| w.with_path(&path)));
+            })
+        } else {
+            continue;
+        };
+        match (result, mode) {
+            (Ok(()), _) => {}
+            (Err(error), ReadMode::Lenient) => {
+                warnings.push(skipped_file(index + 1, &path, &error))
+            }
+            (Err(error), ReadMode::Strict) => return Err(error),
+        }
+    }
+    Ok(())
+}
+
+/// Reads the file found in the directory, files with unrecognised content give no books.
+///
+/// Only the start of the file is read to detect the format, except for databases,
+/// so unrelated large files are not loaded.
+fn read_dir_file(
+    path: &Path,
+    format: Option<InputFormat>,
+    mode: ReadMode,
+) -> Result<(Vec<Book>, Vec<Warning>), HighlightError> {
+    let cannot_read =
+        |e| HighlightError::io(format!("cannot read input file: {}", path.display()), e);
+    let wanted = |detected: Option<InputFormat>| {
+        detected.filter(|detected| format.is_none_or(|format| format == *detected))
+    };
+    let mut file = File::open(path).map_err(cannot_read)?;
+    let mut content = vec![];
+    file.by_ref()
+        .take(HEAD_LENGTH as u64)
+        .read_to_end(&mut content)
+        .map_err(cannot_read)?;
+    if !content.starts_with(SQLITE_HEADER) && wanted(InputFormat::sniff(&content)).is_none() {
+        return Ok((vec![], vec![]));
+    }
+    file.read_to_end(&mut content).map_err(cannot_read)?;
+    match wanted(InputFormat::detect(None, &content)) {
+        Some(detected) => read_file_content(path, &content, detected, mode),
+        None => Ok((vec![], vec![])),
+    }
+}
+
+/// Library database next to the annotations, the first by name when there are several.
+fn apple_books_library(annotations: &Path) -> Option<PathBuf> {
+    let dir = match annotations.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let name = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or("");
+            name.starts_with("BKLibrary") && name.ends_with(".sqlite")
+        })
+        .min()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_bookcision() {
+        let content = br#"{ "asin": "B0049U443Q", "title": "Title", "highlights": [] }"#;
+
+        assert_eq!(
+            Some(InputFormat::Bookcision),
+            InputFormat::detect(None, content)
+        );
+    }
+
+    #[test]
+    fn detect_json() {
+        let content = br#"{ "version": 1, "title": "Title", "highlights": [] }"#;
+
+        assert_eq!(Some(InputFormat::Json), InputFormat::detect(None, content));
+    }
+
+    #[test]
+    fn detect_clippings() {
+        let content =
+            "\u{feff}Title (Author)\n- Your Highlight on Location 1\n\nQuote\n==========\n";
+
+        assert_eq!(
+            Some(InputFormat::Clippings),
+            InputFormat::detect(None, content.as_bytes())
+        );
+    }
+
+    #[test]
+    fn detect_kindle_html() {
+        let content = b"<!DOCTYPE html><html><div class='bookTitle'>Title</div></html>";
+
+        assert_eq!(
+            Some(InputFormat::KindleHtml),
+            InputFormat::detect(None, content)
+        );
+    }
+
+    #[test]
+    fn detect_koreader() {
+        let content = b"-- we can read Lua syntax here!\nreturn {}";
+
+        assert_eq!(
+            Some(InputFormat::Koreader),
+            InputFormat::detect(None, content)
+        );
+    }
+
+    #[test]
+    fn detect_readwise() {
+        let content = b"Highlight,Book Title,Book Author\nQuote,Title,Author\n";
+
+        assert_eq!(
+            Some(InputFormat::Readwise),
+            InputFormat::detect(None, content)
+        );
+    }
+
+    #[test]
+    fn detect_markdown() {
+        let content = b"# Title\n\n*by Author*\n\n---\n> Quote\n";
+        let obsidian = b"---\ntitle: \"Title\"\ntags: []\n---\n\n# Title\n";
+
+        assert_eq!(
+            Some(InputFormat::Markdown),
+            InputFormat::detect(None, content)
+        );
+        assert_eq!(
+            Some(InputFormat::Markdown),
+            InputFormat::detect(None, obsidian)
+        );
+    }
+
+    #[test]
+    fn detect_kobo_database() {
+        let database = assert_fs::NamedTempFile::new("KoboReader.sqlite").unwrap();
+        rusqlite::Connection::open(database.path())
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE content (ContentID TEXT); CREATE TABLE Bookmark (Text TEXT);",
+            )
+            .unwrap();
+        let content = fs::read(database.path()).unwrap();
+
+        assert_eq!(Some(InputFormat::Kobo), InputFormat::detect(None, &content));
+    }
+
+    #[test]
+    fn detect_by_extension() {
+        let path = Path::new("My Clippings.txt");
+
+        assert_eq!(
+            Some(InputFormat::Clippings),
+            InputFormat::detect(Some(path), b"")
+        );
+    }
+
+    #[test]
+    fn prefer_content_over_extension() {
+        let path = Path::new("export.txt");
+        let content = br#"{ "asin": "B0049U443Q" }"#;
+
+        assert_eq!(
+            Some(InputFormat::Bookcision),
+            InputFormat::detect(Some(path), content)
+        );
+    }
+
+    #[test]
+    fn reject_unknown_content() {
+        assert_eq!(None, InputFormat::detect(None, b"plain text"));
+    }
+
+    #[test]
+    fn parse_format_name() {
+        assert_eq!(Ok(InputFormat::KindleHtml), "kindle-html".parse());
+        let error = "pdf".parse::<InputFormat>().unwrap_err();
+        assert!(error.contains("bookcision, clippings"), "{}", error);
+    }
+
+    #[test]
+    fn read_detected_format() {
+        let content = "Title (Author)\n- Your Highlight on Location 1\n\nQuote\n==========\n";
+
+        let (books, _) = read_input(content.as_bytes(), None, ReadMode::Strict).unwrap();
+
+        assert_eq!("Title", books[0].title());
+    }
+
+    #[test]
+    fn read_leniently_with_warnings() {
+        let content = "Title (Author)\n- Something else\n\ntext\n==========\n\
+                       Title (Author)\n- Your Highlight on Location 1\n\nQuote\n==========\n";
+
+        let (books, warnings) = read_input(
+            content.as_bytes(),
+            Some(InputFormat::Clippings),
+            ReadMode::Lenient,
+        )
+        .unwrap();
+
+        assert_eq!(1, books[0].highlights().len());
+        assert_eq!(1, warnings.len());
+    }
+
+    #[test]
+    fn read_library_from_directory() {
+        use assert_fs::prelude::*;
+
+        let dir = assert_fs::TempDir::new().unwrap();
+        dir.child("My Clippings.txt")
+            .write_str("Title (Author)\n- Your Highlight on Location 1\n\nQuote\n==========\n")
+            .unwrap();
+        dir.child("exports/other.txt")
+            .write_str("Other (Author)\n- Your Highlight on Location 2\n\nQuote\n==========\n")
+            .unwrap();
+        dir.child("README.txt").write_str("Not highlights").unwrap();
+        dir.child("book.sdr/metadata.epub.lua")
+            .write_str(
+                r#"return { ["doc_props"] = { ["title"] = "Sidecar" },
+                ["annotations"] = { [1] = { ["text"] = "Quote", ["pos0"] = "p", ["pageno"] = 3 } } }"#,
+            )
+            .unwrap();
+
+        let (library, _) = read_paths(&[dir.path().to_path_buf()], None, ReadMode::Strict).unwrap();
+
+        let titles: Vec<&str> = library.iter().map(Book::title).collect();
+        assert_eq!(vec!["Title", "Sidecar", "Other"], titles);
+    }
+
+    #[test]
+    fn skip_broken_files_leniently() {
+        use assert_fs::prelude::*;
+
+        let dir = assert_fs::TempDir::new().unwrap();
+        dir.child("a.html")
+            .write_str("<html><div class='noteHeading'>Highlight - Location 1</div></html>")
+            .unwrap();
+        dir.child("b.txt")
+            .write_str("Title (Author)\n- Your Highlight on Location 1\n\nQuote\n==========\n")
+            .unwrap();
+        let paths = [dir.path().to_path_buf()];
+
+        let (library, warnings) = read_paths(&paths, None, ReadMode::Lenient).unwrap();
+
+        assert_eq!(1, library.len());
+        assert_eq!(1, warnings.len());
+        assert_eq!(Some(dir.child("a.html").path()), warnings[0].path());
+        assert!(
+            warnings[0].message().contains("missing book title"),
+            "{}",
+            warnings[0]
+        );
+        assert!(read_paths(&paths, None, ReadMode::Strict).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skip_symlinked_directories() {
+        use assert_fs::prelude::*;
+
+        let dir = assert_fs::TempDir::new().unwrap();
+        dir.child("books/My Clippings.txt")
+            .write_str("Title (Author)\n- Your Highlight on Location 1\n\nQuote\n==========\n")
+            .unwrap();
+        std::os::unix::fs::symlink(dir.path(), dir.child("books/loop").path()).unwrap();
+
+        let (library, _) = read_paths(&[dir.path().to_path_buf()], None, ReadMode::Strict).unwrap();
+
+        assert_eq!(1, library.len());
+        assert_eq!(1, library.books()[0].highlights().len());
+    }
+
+    #[test]
+    fn read_library_from_files() {
+        let content = "Title (Author)\n- Your Highlight on Location 1\n\nQuote\n==========\n";
+        let file = assert_fs::NamedTempFile::new("clippings.txt").unwrap();
+        fs::write(file.path(), content).unwrap();
+        let paths = vec![file.path().to_path_buf(), file.path().to_path_buf()];
+
+        let (library, _) = read_paths(&paths, None, ReadMode::Strict).unwrap();
+
+        assert_eq!(1, library.len());
+        assert_eq!(1, library.books()[0].highlights().len());
+    }
+
+    #[test]
+    fn find_first_apple_books_library() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        for name in [
+            "BKLibrary-2.sqlite",
+            "BKLibrary-1.sqlite",
+            "BKLibrary-1.sqlite-wal",
+            "AEAnnotation_1.sqlite",
+        ] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+
+        let library = apple_books_library(&dir.path().join("AEAnnotation_1.sqlite"));
+
+        assert_eq!(Some(dir.path().join("BKLibrary-1.sqlite")), library);
+    }
+
+    #[test]
+    fn fail_on_undetected_format() {
+        let error = read_input("plain text".as_bytes(), None, ReadMode::Strict).unwrap_err();
+
+        assert!(error.to_string().contains("--from"), "{}", error);
+    }
+}
@@ -0,0 +1,173 @@
+//! Import highlights from the JSON schema written by [`crate::render::json`].
+//!
+//! The input is a sequence of book documents, so the output of several books can be read back at once.
+use std::io::Read;
+
+use serde::Deserialize;
+
+use crate::error::HighlightError;
+use crate::highlights::Book;
+use crate::input::{json_error, position, HighlightsRead};
+use crate::render::json::SCHEMA_VERSION;
+
+/// Books read from the JSON documents.
+#[derive(Debug)]
+pub struct JsonDocuments {
+    books: Vec<Book>,
+}
+
+#[derive(Deserialize)]
+struct Document {
+    version: u32,
+    #[serde(flatten)]
+    book: Book,
+}
+
+impl From<JsonDocuments> for Vec<Book> {
+    fn from(documents: JsonDocuments) -> Self {
+        documents.books
+    }
+}
+
+const INVALID_JSON: &str = "invalid highlights json file";
+
+impl HighlightsRead for JsonDocuments {
+    fn from_reader(mut reader: impl Read) -> Result<Self, HighlightError> {
+        let mut content = String::new();
+        reader
+            .read_to_string(&mut content)
+            .map_err(|e| HighlightError::format(INVALID_JSON, e))?;
+        let mut books = vec![];
+        let mut documents = serde_json::Deserializer::from_str(&content).into_iter::<Document>();
+        loop {
+            let offset = documents.byte_offset();
+            let document = match documents.next() {
+                Some(document) => document.map_err(|e| json_error(INVALID_JSON, e))?,
+                None => break,
+            };
+            if document.version != SCHEMA_VERSION {
+                let start = content[offset..]
+                    .find(|c: char| !c.is_whitespace())
+                    .map_or(offset, |skipped| offset + skipped);
+                let (line, column) = position(&content, start);
+                return Err(HighlightError::parse(
+                    INVALID_JSON,
+                    format!(
+                        "unsupported schema version {}, expected {}",
+                        document.version, SCHEMA_VERSION
+                    ),
+                    line,
+                    column,
+                ));
+            }
+            books.push(document.book);
+        }
+        Ok(JsonDocuments { books })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::highlights::details::{Color, Timestamp};
+    use crate::highlights::{examples, Book, Highlight, Location};
+    use crate::render::json::JsonRenderer;
+    use crate::render::Render;
+
+    #[test]
+    fn read_highlight_types() {
+        let json = r#"{
+  "version": 1,
+  "title": "Title",
+  "authors": "Author",
+  "highlights": [
+    { "type": "quote", "quote": "Quote", "location": { "value": 1, "link": "" } },
+    { "type": "note", "note": "Note", "location": { "value": 2, "link": "" } },
+    { "type": "comment", "quote": "Quote", "note": "Note", "location": { "value": 3, "link": "" } }
+  ]
+}"#;
+
+        let books = read(json);
+        let highlights = books[0].highlights();
+
+        assert_eq!("Title", books[0].title());
+        assert!(matches!(highlights[0], Highlight::Quote { .. }));
+        assert!(matches!(highlights[1], Highlight::Note { .. }));
+        match &highlights[2] {
+            Highlight::Comment { note, location, .. } => {
+                assert_eq!("Note", note);
+                assert_eq!(3, location.value());
+            }
+            other => panic!("expected comment, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn round_trip_several_books() {
+        let book = examples::chess_book();
+        let json = JsonRenderer.as_string(&book).repeat(2);
+
+        let books = read(&json);
+
+        assert_eq!(2, books.len());
+        assert_eq!(
+            JsonRenderer.as_string(&book),
+            JsonRenderer.as_string(&books[1])
+        );
+    }
+
+    #[test]
+    fn round_trip_chapters_and_details() {
+        let book = Book::new(
+            "Title",
+            "Author",
+            [
+                Highlight::quote("Quote", Location::new(1, "").with_chapter("One"))
+                    .with_created(Timestamp::parse("2022-10-04 10:12:01").unwrap())
+                    .with_color(Color::Green)
+                    .with_tags(["chess"]),
+                Highlight::note("Note", Location::new(2, "")),
+            ],
+        );
+        let json = JsonRenderer.as_string(&book);
+
+        let books = read(&json);
+
+        assert!(
+            json.contains(r#""created": "2022-10-04T10:12:01""#),
+            "{}",
+            json
+        );
+        assert!(json.contains(r#""color": "green""#), "{}", json);
+        assert_eq!(1, json.matches(r#""tags""#).count());
+        assert_eq!(book.highlights(), books[0].highlights());
+    }
+
+    #[test]
+    fn reject_unknown_version() {
+        let json = r#"{ "version": 1, "title": "Title", "authors": "Author", "highlights": [] }
+  { "version": 2, "title": "Title", "authors": "Author", "highlights": [] }"#;
+
+        let error = JsonDocuments::from_reader(json.as_bytes()).unwrap_err();
+
+        match error {
+            HighlightError::Parse(error) => {
+                assert_eq!("unsupported schema version 2, expected 1", error.reason());
+                assert_eq!((2, 3), (error.line(), error.column()));
+            }
+            other => panic!("expected parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reject_unknown_highlight_type() {
+        let json = r#"{ "version": 1, "title": "Title", "authors": "Author",
+            "highlights": [{ "type": "bookmark", "location": { "value": 1, "link": "" } }] }"#;
+
+        assert!(JsonDocuments::from_reader(json.as_bytes()).is_err());
+    }
+
+    fn read(json: &str) -> Vec<Book> {
+        JsonDocuments::from_reader(json.as_bytes()).unwrap().into()
+    }
+}
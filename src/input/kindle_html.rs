@@ -0,0 +1,471 @@
+//! Import highlights from the kindle notebook HTML export.
+//!
+//! Kindle apps export the notebook as an HTML file with the book title and authors,
+//! followed by section headings and pairs of `noteHeading` and `noteText` blocks:
+//!
+//! ```html
+//! <div class="sectionHeading">Chapter 1</div>
+//! <div class="noteHeading">Highlight (<span class="highlight_yellow">yellow</span>) - Page 12 · Location 157</div>
+//! <div class="noteText">the reality is that we discard our decisions</div>
+//! ```
+use std::io::Read;
+
+use scraper::{ElementRef, Html, Node, Selector};
+
+use crate::error::HighlightError;
+use crate::highlights::details::Color;
+use crate::highlights::{Book, Highlight, Location};
+use crate::input::{position, HighlightsRead};
+
+const INVALID_NOTEBOOK: &str = "invalid kindle notebook file";
+
+const BLOCK_CLASSES: [&str; 5] = [
+    "bookTitle",
+    "authors",
+    "sectionHeading",
+    "noteHeading",
+    "noteText",
+];
+
+/// Parsed kindle notebook export.
+#[derive(Debug)]
+pub struct KindleNotebook {
+    title: String,
+    authors: String,
+    entries: Vec<NotebookEntry>,
+}
+
+/// Single highlight, note or bookmark of the notebook.
+#[derive(Clone, Debug)]
+pub struct NotebookEntry {
+    kind: EntryKind,
+    section: Option<String>,
+    color: Option<String>,
+    page: Option<usize>,
+    location: Option<usize>,
+    text: String,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum EntryKind {
+    Highlight,
+    Note,
+    Bookmark,
+}
+
+impl KindleNotebook {
+    fn parse(html: &str) -> Result<Self, HighlightError> {
+        let document = Html::parse_document(html);
+        let selector = Selector::parse(
+            &BLOCK_CLASSES
+                .iter()
+                .map(|class| format!(".{}", class))
+                .collect::<Vec<String>>()
+                .join(", "),
+        )
+        .map_err(|e| invalid_notebook(html, 0, e.to_string()))?;
+
+        let mut title = None;
+        let mut authors = String::new();
+        let mut section = None;
+        let mut entries: Vec<NotebookEntry> = vec![];
+        for element in document.select(&selector) {
+            let text = own_text(element);
+            let class = BLOCK_CLASSES
+                .iter()
+                .find(|class| element.value().classes().any(|c| c == **class));
+            match class {
+                Some(&"bookTitle") => title = Some(text),
+                Some(&"authors") => authors = text,
+                Some(&"sectionHeading") => section = Some(text).filter(|s| !s.is_empty()),
+                Some(&"noteHeading") => entries.push(NotebookEntry::parse_heading(
+                    &text,
+                    color(element),
+                    &section,
+                )),
+                Some(&"noteText") => {
+                    if let Some(entry) = entries.last_mut() {
+                        entry.text = text;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let title = title.ok_or_else(|| {
+            // The title belongs at the start of the body, so the error points there.
+            let offset = html
+                .find("<body")
+                .and_then(|start| html[start..].find('>').map(|end| start + end + 1))
+                .unwrap_or(0);
+            invalid_notebook(html, offset, "missing book title".to_owned())
+        })?;
+        Ok(KindleNotebook {
+            title,
+            authors,
+            entries,
+        })
+    }
+
+    /// Notebook entries in the order of the export.
+    pub fn entries(&self) -> &[NotebookEntry] {
+        &self.entries
+    }
+}
+
+impl NotebookEntry {
+    /// Parses the `Highlight (yellow) - Page 12 · Location 157` heading.
+    fn parse_heading(heading: &str, color: Option<String>, section: &Option<String>) -> Self {
+        let lowercase = heading.to_lowercase();
+        let kind = if lowercase.starts_with("note") {
+            EntryKind::Note
+        } else if lowercase.starts_with("bookmark") {
+            EntryKind::Bookmark
+        } else {
+            EntryKind::Highlight
+        };
+        NotebookEntry {
+            kind,
+            section: section.clone(),
+            color,
+            page: number_after(&lowercase, "page "),
+            location: number_after(&lowercase, "location "),
+            text: String::new(),
+        }
+    }
+
+    /// Name of the section containing the entry.
+    pub fn section(&self) -> Option<&str> {
+        self.section.as_deref()
+    }
+
+    /// Name of the highlight color.
+    pub fn color(&self) -> Option<&str> {
+        self.color.as_deref()
+    }
+
+    /// Page number of the entry.
+    pub fn page(&self) -> Option<usize> {
+        self.page
+    }
+
+    /// Kindle location of the entry.
+    pub fn location(&self) -> Option<usize> {
+        self.location
+    }
+
+    /// Highlighted text or note.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    fn location_value(&self) -> usize {
+        self.location.or(self.page).unwrap_or_default()
+    }
+}
+
+/// Collects the element text without the nested blocks.
+///
+/// Older exports leave blocks unclosed, so the next heading ends up inside the previous text block.
+fn own_text(element: ElementRef) -> String {
+    let mut text = String::new();
+    collect_text(element, &mut text);
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+fn collect_text(element: ElementRef, text: &mut String) {
+    for child in element.children() {
+        match child.value() {
+            Node::Text(value) => text.push_str(value),
+            Node::Element(nested) => {
+                let is_block = nested.classes().any(|class| BLOCK_CLASSES.contains(&class));
+                if let (false, Some(nested)) = (is_block, ElementRef::wrap(child)) {
+                    collect_text(nested, text);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Reads the color from the `highlight_<color>` class of the heading span.
+fn color(heading: ElementRef) -> Option<String> {
+    heading
+        .descendent_elements()
+        .flat_map(|element| element.value().classes())
+        .find_map(|class| class.strip_prefix("highlight_"))
+        .map(str::to_owned)
+}
+
+fn number_after(text: &str, marker: &str) -> Option<usize> {
+    let start = text.find(marker)? + marker.len();
+    let digits: String = text[start..]
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect();
+    digits.parse().ok()
+}
+
+/// Parse error at the byte offset of the notebook source.
+fn invalid_notebook(html: &str, offset: usize, reason: String) -> HighlightError {
+    let (line, column) = position(html, offset);
+    HighlightError::parse(INVALID_NOTEBOOK, reason, line, column)
+}
+
+impl From<KindleNotebook> for Book {
+    /// Converts notebook entries to highlights.
+    ///
+    /// A note directly following the highlight in the same section at the same or later location
+    /// becomes the comment on that highlight.
+    /// Section headings become the chapters of the highlights.
+    /// Highlights keep their color, and `.tag` words at the start of notes become tags.
+    /// Bookmarks are skipped.
+    fn from(notebook: KindleNotebook) -> Self {
+        let mut highlights: Vec<(&NotebookEntry, Highlight)> = vec![];
+        for entry in &notebook.entries {
+            let mut location = Location::new(entry.location_value(), "");
+            if let Some(section) = entry.section() {
+                location = location.with_chapter(section);
+            }
+            match entry.kind {
+                EntryKind::Highlight => {
+                    highlights.push((entry, Highlight::quote(entry.text.clone(), location)))
+                }
+                EntryKind::Note => {
+                    let previous = highlights.last_mut().filter(|(quoted, highlight)| {
+                        matches!(highlight, Highlight::Quote { .. })
+                            && quoted.section == entry.section
+                            && quoted.location_value() <= entry.location_value()
+                    });
+                    match previous {
+                        Some((quoted, highlight)) => {
+                            *highlight = Highlight::comment(
+                                quoted.text.clone(),
+                                entry.text.clone(),
+                                highlight.location(),
+                            )
+                        }
+                        None => {
+                            highlights.push((entry, Highlight::note(entry.text.clone(), location)))
+                        }
+                    }
+                }
+                EntryKind::Bookmark => {}
+            }
+        }
+
+        let highlights = highlights.into_iter().map(|(entry, highlight)| {
+            let highlight = match entry.color().and_then(Color::parse) {
+                Some(color) => highlight.with_color(color),
+                None => highlight,
+            };
+            highlight.with_note_tags()
+        });
+        Book::new(notebook.title, notebook.authors, highlights)
+    }
+}
+
+impl From<KindleNotebook> for Vec<Book> {
+    /// Notebook exports always contain exactly one book.
+    fn from(notebook: KindleNotebook) -> Self {
+        vec![notebook.into()]
+    }
+}
+
+impl HighlightsRead for KindleNotebook {
+    fn from_reader(mut reader: impl Read) -> Result<Self, HighlightError> {
+        let mut content = vec![];
+        reader
+            .read_to_end(&mut content)
+            .map_err(|e| HighlightError::format(INVALID_NOTEBOOK, e))?;
+        let html = match String::from_utf8(content) {
+            Ok(html) => html,
+            Err(e) => {
+                let valid = e.utf8_error().valid_up_to();
+                let html = String::from_utf8_lossy(e.as_bytes());
+                return Err(invalid_notebook(&html, valid, e.utf8_error().to_string()));
+            }
+        };
+        KindleNotebook::parse(&html)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTEBOOK: &str = r#"<?xml version="1.0" encoding="UTF-8" ?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml">
+<head><meta charset="UTF-8"/><title>Notebook</title></head>
+<body>
+<div class="bodyContainer">
+<div class="notebookFor">Notebook Export</div>
+<div class="bookTitle">How Life Imitates Chess</div>
+<div class="authors">Garry Kasparov</div>
+<div class="citation"></div>
+<hr/>
+<div class="sectionHeading">Introduction</div>
+<div class="noteHeading">Highlight (<span class="highlight_yellow">yellow</span>) - Page 12 · Location 157</div>
+<div class="noteText">the reality is that we discard our decisions</div>
+<div class="sectionHeading">Strategy</div>
+<div class="noteHeading">Highlight (<span class="highlight_blue">blue</span>) - Page 25 · Location 445</div>
+<div class="noteText">Why this move? What am I trying to achieve?</div>
+<div class="noteHeading">Note - Page 25 · Location 447</div>
+<div class="noteText">Each move should contribute to some objective</div>
+<div class="noteHeading">Bookmark - Page 30 · Location 512</div>
+<div class="noteText"></div>
+<div class="noteHeading">Note - Page 31 · Location 530</div>
+<div class="noteText">.maps Create a personalized map of your decision-making process</div>
+</div>
+</body>
+</html>
+"#;
+
+    #[test]
+    fn read_book_details() {
+        let book = read(NOTEBOOK);
+
+        assert_eq!("How Life Imitates Chess", book.title());
+        assert_eq!("Garry Kasparov", book.authors());
+        assert_eq!(3, book.highlights().len());
+    }
+
+    #[test]
+    fn read_quote() {
+        let book = read(NOTEBOOK);
+
+        match &book.highlights()[0] {
+            Highlight::Quote {
+                quote, location, ..
+            } => {
+                assert_eq!("the reality is that we discard our decisions", quote);
+                assert_eq!(157, location.value());
+            }
+            other => panic!("expected quote, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn pair_note_with_previous_highlight() {
+        let book = read(NOTEBOOK);
+
+        match &book.highlights()[1] {
+            Highlight::Comment {
+                quote,
+                note,
+                location,
+                ..
+            } => {
+                assert_eq!("Why this move? What am I trying to achieve?", quote);
+                assert_eq!("Each move should contribute to some objective", note);
+                assert_eq!(445, location.value());
+            }
+            other => panic!("expected comment, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn keep_standalone_note() {
+        let book = read(NOTEBOOK);
+
+        match &book.highlights()[2] {
+            Highlight::Note { note, location, .. } => {
+                assert_eq!(
+                    "Create a personalized map of your decision-making process",
+                    note
+                );
+                assert_eq!(530, location.value());
+            }
+            other => panic!("expected note, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn keep_sections_and_colors() {
+        let notebook = KindleNotebook::from_reader(NOTEBOOK.as_bytes()).unwrap();
+        let entries = notebook.entries();
+
+        assert_eq!(Some("Introduction"), entries[0].section());
+        assert_eq!(Some("yellow"), entries[0].color());
+        assert_eq!(Some("Strategy"), entries[1].section());
+        assert_eq!(Some(25), entries[1].page());
+    }
+
+    #[test]
+    fn read_sections_as_chapters() {
+        let book = read(NOTEBOOK);
+        let chapters: Vec<Option<String>> = book
+            .highlights()
+            .iter()
+            .map(|highlight| highlight.location().chapter().map(str::to_owned))
+            .collect();
+
+        assert_eq!(
+            vec![
+                Some("Introduction".to_owned()),
+                Some("Strategy".to_owned()),
+                Some("Strategy".to_owned())
+            ],
+            chapters
+        );
+    }
+
+    #[test]
+    fn read_colors_and_note_tags() {
+        let book = read(NOTEBOOK);
+        let highlights = book.highlights();
+
+        assert_eq!(Some(Color::Yellow), highlights[0].color());
+        assert_eq!(Some(Color::Blue), highlights[1].color());
+        assert_eq!(["maps"], highlights[2].tags());
+    }
+
+    #[test]
+    fn read_unclosed_blocks() {
+        let html = r#"<div class='bookTitle'>Rustonomicon
+</div><div class='authors'>The Rust Community</div>
+<h2 class='sectionHeading'>Meet Safe and Unsafe</h2>
+<h3 class='noteHeading'>Highlight (<span class='highlight_pink'>pink</span>) - Location 42</h3>
+<div class='noteText'>Unsafe Rust is dangerous</h3>
+<h3 class='noteHeading'>Highlight (<span class='highlight_pink'>pink</span>) - Location 57</h3>
+<div class='noteText'>Safe Rust is the true Rust</h3>
+"#;
+        let book = read(html);
+        let highlights = book.highlights();
+
+        assert_eq!("Rustonomicon", book.title());
+        assert_eq!(2, highlights.len());
+        match &highlights[0] {
+            Highlight::Quote { quote, .. } => assert_eq!("Unsafe Rust is dangerous", quote),
+            other => panic!("expected quote, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reject_other_html() {
+        let result = KindleNotebook::from_reader("<html>\n<body>Hello</body></html>".as_bytes());
+
+        match result {
+            Err(HighlightError::Parse(error)) => {
+                assert_eq!("missing book title", error.reason());
+                assert_eq!((2, 7), (error.line(), error.column()));
+            }
+            other => panic!("expected parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn report_position_of_invalid_text() {
+        let result = KindleNotebook::from_reader(&b"<html>\n<body>\xff</body></html>"[..]);
+
+        match result {
+            Err(HighlightError::Parse(error)) => assert_eq!((2, 7), (error.line(), error.column())),
+            other => panic!("expected parse error, got {:?}", other),
+        }
+    }
+
+    fn read(html: &str) -> Book {
+        KindleNotebook::from_reader(html.as_bytes()).unwrap().into()
+    }
+}
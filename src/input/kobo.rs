@@ -0,0 +1,426 @@
+//! Import highlights from the Kobo `KoboReader.sqlite` database.
+//!
+//! Kobo devices keep highlights and annotations in the `Bookmark` table.
+//! Each bookmark refers to the book and the chapter rows of the `content` table.
+//! Copy the `.kobo/KoboReader.sqlite` file from the device to read highlights from it.
+use std::io::Read;
+use std::path::Path;
+
+use rusqlite::Connection;
+
+use crate::error::HighlightError;
+use crate::highlights::details::Timestamp;
+use crate::highlights::{Book, Highlight, Location};
+use crate::input::{sqlite, HighlightsRead, ReadMode, Warning};
+
+const FORMAT: &str = "kobo";
+
+/// Location steps in one chapter.
+const CHAPTER_LOCATIONS: usize = 10_000;
+
+const BOOKMARKS_QUERY: &str = "
+    SELECT b.VolumeID, book.Title, book.Attribution, b.Text, b.Annotation,
+        b.DateCreated, b.DateModified, b.ChapterProgress,
+        (SELECT chapter.VolumeIndex FROM content chapter
+            WHERE chapter.BookID = b.VolumeID
+                AND chapter.ContentType IN (9, 899)
+                AND substr(chapter.ContentID, 1, length(b.ContentID)) = b.ContentID
+            ORDER BY chapter.VolumeIndex
+            LIMIT 1) AS ChapterIndex,
+        (SELECT chapter.Title FROM content chapter
+            WHERE chapter.BookID = b.VolumeID
+                AND chapter.ContentType IN (9, 899)
+                AND substr(chapter.ContentID, 1, length(b.ContentID)) = b.ContentID
+            ORDER BY chapter.VolumeIndex
+            LIMIT 1) AS ChapterTitle
+    FROM Bookmark b
+    JOIN content book ON book.ContentID = b.VolumeID AND book.ContentType = 6
+    ORDER BY book.Title, b.VolumeID, ChapterIndex, b.ChapterProgress";
+
+/// Bookmarks read from the Kobo database.
+#[derive(Debug)]
+pub struct KoboBookmarks {
+    bookmarks: Vec<KoboBookmark>,
+}
+
+#[derive(Debug)]
+struct KoboBookmark {
+    volume_id: String,
+    title: String,
+    authors: Option<String>,
+    text: Option<String>,
+    annotation: Option<String>,
+    created: Option<String>,
+    modified: Option<String>,
+    progress: Option<f64>,
+    chapter_index: Option<i64>,
+    chapter: Option<String>,
+}
+
+impl KoboBookmarks {
+    /// Reads bookmarks from the local copy of the `KoboReader.sqlite` file.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, HighlightError> {
+        let connection = sqlite::open(path.as_ref(), FORMAT)?;
+        Ok(KoboBookmarks::read(&connection, ReadMode::Strict)?.0)
+    }
+
+    /// Reads bookmarks skipping the rows that cannot be decoded, reporting them as warnings.
+    pub fn open_lenient(path: impl AsRef<Path>) -> Result<(Self, Vec<Warning>), HighlightError> {
+        let connection = sqlite::open(path.as_ref(), FORMAT)?;
+        KoboBookmarks::read(&connection, ReadMode::Lenient)
+    }
+
+    fn read(
+        connection: &Connection,
+        mode: ReadMode,
+    ) -> Result<(Self, Vec<Warning>), HighlightError> {
+        let (bookmarks, warnings) =
+            sqlite::query_rows(connection, BOOKMARKS_QUERY, FORMAT, mode, |row| {
+                Ok(KoboBookmark {
+                    volume_id: row.get(0)?,
+                    title: row.get(1)?,
+                    authors: row.get(2)?,
+                    text: row.get(3)?,
+                    annotation: row.get(4)?,
+                    created: row.get(5)?,
+                    modified: row.get(6)?,
+                    progress: row.get(7)?,
+                    chapter_index: row.get(8)?,
+                    chapter: row.get(9)?,
+                })
+            })?;
+        Ok((KoboBookmarks { bookmarks }, warnings))
+    }
+}
+
+impl KoboBookmark {
+    /// Location made of the chapter index and the progress in the chapter.
+    ///
+    /// The value depends only on the bookmark, so it stays the same when other highlights are added.
+    fn location(&self) -> Location {
+        let chapter = self.chapter_index.unwrap_or(0).max(0) as usize;
+        let progress = self.progress.unwrap_or(0.0).clamp(0.0, 1.0);
+        let step =
+            ((progress * CHAPTER_LOCATIONS as f64).round() as usize).min(CHAPTER_LOCATIONS - 1);
+        Location::new(chapter * CHAPTER_LOCATIONS + step, "")
+    }
+
+    fn highlight(&self) -> Option<Highlight> {
+        let mut location = self.location();
+        if let Some(chapter) = &self.chapter {
+            location = location.with_chapter(chapter.as_str());
+        }
+        let text = non_blank(&self.text);
+        let annotation = non_blank(&self.annotation);
+        let mut highlight = match (text, annotation) {
+            (Some(quote), Some(note)) => Highlight::comment(quote, note, location),
+            (Some(quote), None) => Highlight::quote(quote, location),
+            (None, Some(note)) => Highlight::note(note, location),
+            (None, None) => return None,
+        };
+        if let Some(created) = self.created.as_deref().and_then(Timestamp::parse) {
+            highlight = highlight.with_created(created);
+        }
+        if let Some(modified) = self.modified.as_deref().and_then(Timestamp::parse) {
+            highlight = highlight.with_modified(modified);
+        }
+        Some(highlight)
+    }
+}
+
+fn non_blank(value: &Option<String>) -> Option<&str> {
+    value
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+impl From<KoboBookmarks> for Vec<Book> {
+    /// Groups bookmarks by book.
+    ///
+    /// Kobo has no location numbers, so the location is made of the chapter index
+    /// and the progress in the chapter: location 12000 is 20% into the chapter 1.
+    /// Such locations keep the reading order and have no link.
+    /// Titles of the chapter rows become the chapters of the highlights.
+    /// Bookmarks without text or annotation (dog ears) are skipped.
+    fn from(kobo: KoboBookmarks) -> Self {
+        let mut books: Vec<(&KoboBookmark, Vec<Highlight>)> = vec![];
+        for bookmark in &kobo.bookmarks {
+            let same_book =
+                matches!(books.last(), Some((book, _)) if book.volume_id == bookmark.volume_id);
+            if !same_book {
+                books.push((bookmark, vec![]));
+            }
+            if let Some((_, highlights)) = books.last_mut() {
+                highlights.extend(bookmark.highlight());
+            }
+        }
+
+        books
+            .into_iter()
+            .filter(|(_, highlights)| !highlights.is_empty())
+            .map(|(book, highlights)| {
+                let authors = book.authors.clone().unwrap_or_default();
+                Book::new(book.title.clone(), authors, highlights)
+            })
+            .collect()
+    }
+}
+
+impl HighlightsRead for KoboBookmarks {
+    fn from_reader(reader: impl Read) -> Result<Self, HighlightError> {
+        let connection = sqlite::from_reader(reader, FORMAT)?;
+        Ok(KoboBookmarks::read(&connection, ReadMode::Strict)?.0)
+    }
+
+    /// Skips bookmark rows that cannot be decoded.
+    fn from_reader_lenient(reader: impl Read) -> Result<(Self, Vec<Warning>), HighlightError> {
+        let connection = sqlite::from_reader(reader, FORMAT)?;
+        KoboBookmarks::read(&connection, ReadMode::Lenient)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use assert_fs::NamedTempFile;
+
+    use super::*;
+
+    const BOOK_ID: &str = "file:///mnt/onboard/kasparov.kepub.epub";
+
+    #[test]
+    fn read_books() {
+        let books = read_fixture();
+
+        assert_eq!(2, books.len());
+        assert_eq!("How Life Imitates Chess", books[0].title());
+        assert_eq!("Garry Kasparov", books[0].authors());
+        assert_eq!("Rustonomicon", books[1].title());
+    }
+
+    #[test]
+    fn read_quote() {
+        let books = read_fixture();
+        let highlights = books[0].highlights();
+
+        match &highlights[0] {
+            Highlight::Quote {
+                quote, location, ..
+            } => {
+                assert_eq!("we discard our decisions", quote);
+                assert_eq!(12000, location.value());
+            }
+            other => panic!("expected quote, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn read_annotated_highlight_as_comment() {
+        let books = read_fixture();
+        let highlights = books[0].highlights();
+
+        match &highlights[1] {
+            Highlight::Comment {
+                quote,
+                note,
+                location,
+                ..
+            } => {
+                assert_eq!("Why this move?", quote);
+                assert_eq!("Every move needs a purpose", note);
+                assert_eq!(15000, location.value());
+            }
+            other => panic!("expected comment, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn order_highlights_by_chapter() {
+        let books = read_fixture();
+        let highlights = books[0].highlights();
+
+        assert_eq!(3, highlights.len(), "dog ears must be skipped");
+        match &highlights[2] {
+            Highlight::Quote { quote, .. } => {
+                assert_eq!("Strategy comes first", quote);
+            }
+            other => panic!("expected quote, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn read_chapter_titles() {
+        let books = read_fixture();
+        let highlights = books[0].highlights();
+
+        assert_eq!(Some("Introduction"), highlights[0].location().chapter());
+        assert_eq!(Some("Strategy"), highlights[2].location().chapter());
+    }
+
+    #[test]
+    fn keep_locations_when_highlights_are_added() {
+        let database = fixture_database();
+        Connection::open(database.path())
+            .unwrap()
+            .execute(
+                "INSERT INTO Bookmark VALUES ('b6', ?1, ?2, 'Opening move', NULL, 0.05, NULL, NULL)",
+                [BOOK_ID, &format!("{}!OEBPS/chapter1.xhtml", BOOK_ID)],
+            )
+            .unwrap();
+
+        let books: Vec<Book> = KoboBookmarks::open(database.path()).unwrap().into();
+        let locations: Vec<usize> = books[0]
+            .highlights()
+            .iter()
+            .map(|highlight| highlight.location().value())
+            .collect();
+
+        assert_eq!(vec![10500, 12000, 15000, 21000], locations);
+    }
+
+    #[test]
+    fn match_chapter_ids_literally() {
+        let database = fixture_database();
+        let book = "file:///mnt/onboard/under_score.epub";
+        Connection::open(database.path())
+            .unwrap()
+            .execute_batch(&format!(
+                "INSERT INTO content VALUES
+                    ('{book}', 6, NULL, 'Underscores', NULL, -1),
+                    ('{book}!OEBPS/partA1.xhtml-1', 899, '{book}', 'Wildcard match', NULL, 1),
+                    ('{book}!OEBPS/part_1.xhtml-1', 899, '{book}', 'Exact match', NULL, 2);
+                INSERT INTO Bookmark VALUES
+                    ('b7', '{book}', '{book}!OEBPS/part_1.xhtml', 'Literal', NULL, 0.5, NULL, NULL);",
+                book = book,
+            ))
+            .unwrap();
+
+        let books: Vec<Book> = KoboBookmarks::open(database.path()).unwrap().into();
+        let book = books
+            .iter()
+            .find(|book| book.title() == "Underscores")
+            .unwrap();
+
+        assert_eq!(
+            Some("Exact match"),
+            book.highlights()[0].location().chapter()
+        );
+    }
+
+    #[test]
+    fn read_dates() {
+        let books = read_fixture();
+        let highlights = books[0].highlights();
+
+        assert_eq!(
+            Timestamp::parse("2022-10-04 10:02:44"),
+            highlights[0].created()
+        );
+        assert_eq!(
+            Timestamp::parse("2022-10-06 09:00:00"),
+            highlights[1].modified()
+        );
+        assert_eq!(None, highlights[2].created());
+    }
+
+    #[test]
+    fn read_from_reader() {
+        let database = fixture_database();
+        let file = File::open(database.path()).unwrap();
+
+        let books: Vec<Book> = KoboBookmarks::from_reader(file).unwrap().into();
+
+        assert_eq!(2, books.len());
+    }
+
+    #[test]
+    fn skip_broken_bookmarks_leniently() {
+        let database = fixture_database();
+        Connection::open(database.path())
+            .unwrap()
+            .execute(
+                "INSERT INTO Bookmark VALUES ('b6', ?1, ?2, X'FF', NULL, 0.9, NULL, NULL)",
+                [BOOK_ID, &format!("{}!OEBPS/chapter2.xhtml", BOOK_ID)],
+            )
+            .unwrap();
+
+        assert!(KoboBookmarks::open(database.path()).is_err());
+        let (bookmarks, warnings) = KoboBookmarks::open_lenient(database.path()).unwrap();
+        let books: Vec<Book> = bookmarks.into();
+
+        assert_eq!(4, books[0].highlights().len() + books[1].highlights().len());
+        assert_eq!(1, warnings.len());
+        assert!(warnings[0].message().ends_with(", skipped"));
+    }
+
+    #[test]
+    fn reject_other_databases() {
+        let database = NamedTempFile::new("other.sqlite").unwrap();
+        Connection::open(database.path())
+            .unwrap()
+            .execute_batch("CREATE TABLE notes (text TEXT);")
+            .unwrap();
+
+        let result = KoboBookmarks::open(database.path());
+
+        assert!(result.is_err());
+    }
+
+    fn read_fixture() -> Vec<Book> {
+        let database = fixture_database();
+        KoboBookmarks::open(database.path()).unwrap().into()
+    }
+
+    fn fixture_database() -> NamedTempFile {
+        let database = NamedTempFile::new("KoboReader.sqlite").unwrap();
+        let connection = Connection::open(database.path()).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE content (
+                    ContentID TEXT PRIMARY KEY,
+                    ContentType INTEGER,
+                    BookID TEXT,
+                    Title TEXT,
+                    Attribution TEXT,
+                    VolumeIndex INTEGER
+                );
+                CREATE TABLE Bookmark (
+                    BookmarkID TEXT PRIMARY KEY,
+                    VolumeID TEXT,
+                    ContentID TEXT,
+                    Text TEXT,
+                    Annotation TEXT,
+                    ChapterProgress REAL,
+                    DateCreated TEXT,
+                    DateModified TEXT
+                );",
+            )
+            .unwrap();
+        let chapter_1 = format!("{}!OEBPS/chapter1.xhtml", BOOK_ID);
+        let chapter_2 = format!("{}!OEBPS/chapter2.xhtml", BOOK_ID);
+        let rust_book = "file:///mnt/onboard/rustonomicon.epub";
+        let rust_chapter = format!("{}!OEBPS/intro.xhtml", rust_book);
+        connection
+            .execute_batch(&format!(
+                "INSERT INTO content VALUES
+                    ('{book}', 6, NULL, 'How Life Imitates Chess', 'Garry Kasparov', -1),
+                    ('{chapter_1}-1', 899, '{book}', 'Introduction', NULL, 1),
+                    ('{chapter_2}-1', 899, '{book}', 'Strategy', NULL, 2),
+                    ('{rust_book}', 6, NULL, 'Rustonomicon', 'The Rust Community', -1),
+                    ('{rust_chapter}', 9, '{rust_book}', 'Meet Safe and Unsafe', NULL, 0);
+                INSERT INTO Bookmark VALUES
+                    ('b1', '{book}', '{chapter_2}', 'Strategy comes first', NULL, 0.1, NULL, NULL),
+                    ('b2', '{book}', '{chapter_1}', 'we discard our decisions', '', 0.2,
+                        '2022-10-04T10:02:44.000', '2022-10-04T10:02:44.000'),
+                    ('b3', '{book}', '{chapter_1}', 'Why this move?', 'Every move needs a purpose', 0.5,
+                        '2022-10-04T10:05:01Z', '2022-10-06T09:00:00Z'),
+                    ('b4', '{book}', '{chapter_1}', NULL, NULL, 0.7, NULL, NULL),
+                    ('b5', '{rust_book}', '{rust_chapter}', 'Unsafe Rust is dangerous', NULL, 0.3, NULL, NULL);",
+                book = BOOK_ID,
+            ))
+            .unwrap();
+        database
+    }
+}
@@ -0,0 +1,355 @@
+//! Minimal parser for the Lua table literals KOReader writes into sidecar files.
+//!
+//! Supports the subset of Lua used by `metadata.*.lua` files:
+//! a single `return { ... }` statement with nested tables, strings, numbers, booleans and `nil`.
+use std::fmt::{Display, Formatter};
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::input::position;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Table(Vec<(Value, Value)>),
+}
+
+impl Value {
+    /// Looks up the string key in the table.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Table(entries) => entries
+                .iter()
+                .find(|(k, _)| matches!(k, Value::String(k) if k == key))
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Value::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Table entries in the order of the source file.
+    pub fn entries(&self) -> &[(Value, Value)] {
+        match self {
+            Value::Table(entries) => entries,
+            _ => &[],
+        }
+    }
+}
+
+/// Parses the `return { ... }` statement of the sidecar file.
+pub fn parse(source: &str) -> Result<Value, SyntaxError> {
+    let mut parser = Parser {
+        chars: source.chars().peekable(),
+        source,
+    };
+    parser.skip_whitespace();
+    if !parser.keyword("return") {
+        return Err(parser.error("expected 'return'"));
+    }
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    match parser.chars.peek().copied() {
+        None => Ok(value),
+        Some(c) => Err(parser.error(&format!("unexpected '{}' after the table", c))),
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    source: &'a str,
+}
+
+/// Syntax error at the 1-based line and column of the source.
+#[derive(Debug)]
+pub struct SyntaxError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Display for SyntaxError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl<'a> Parser<'a> {
+    fn value(&mut self) -> Result<Value, SyntaxError> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('{') => self.table(),
+            Some('"') | Some('\'') => self.string().map(Value::String),
+            Some(c) if c.is_ascii_digit() || *c == '-' || *c == '.' => self.number(),
+            Some(_) => {
+                let word = self.word();
+                match word.as_str() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    "nil" => Ok(Value::Nil),
+                    _ => Err(self.error(&format!("unexpected '{}'", word))),
+                }
+            }
+            None => Err(self.error("unexpected end of file")),
+        }
+    }
+
+    fn table(&mut self) -> Result<Value, SyntaxError> {
+        self.chars.next();
+        let mut entries = vec![];
+        let mut index = 1;
+        loop {
+            self.skip_whitespace();
+            match self.chars.peek() {
+                Some('}') => {
+                    self.chars.next();
+                    return Ok(Value::Table(entries));
+                }
+                Some('[') => {
+                    self.chars.next();
+                    let key = self.value()?;
+                    self.expect(']')?;
+                    self.expect('=')?;
+                    entries.push((key, self.value()?));
+                }
+                Some(c) if c.is_alphabetic() || *c == '_' => {
+                    let word = self.word();
+                    self.skip_whitespace();
+                    if self.chars.peek() == Some(&'=') {
+                        self.chars.next();
+                        entries.push((Value::String(word), self.value()?));
+                    } else {
+                        let value = match word.as_str() {
+                            "true" => Value::Bool(true),
+                            "false" => Value::Bool(false),
+                            "nil" => Value::Nil,
+                            _ => return Err(self.error(&format!("unexpected '{}'", word))),
+                        };
+                        entries.push((Value::Number(index as f64), value));
+                        index += 1;
+                    }
+                }
+                Some(_) => {
+                    let value = self.value()?;
+                    entries.push((Value::Number(index as f64), value));
+                    index += 1;
+                }
+                None => return Err(self.error("unclosed table")),
+            }
+            self.skip_whitespace();
+            match self.chars.peek() {
+                Some(',') | Some(';') => {
+                    self.chars.next();
+                }
+                Some('}') => {}
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    /// Reads the quoted string.
+    ///
+    /// Decimal escapes encode bytes, so the string is collected as bytes and decoded at the end.
+    fn string(&mut self) -> Result<String, SyntaxError> {
+        let quote = self.chars.next();
+        let mut bytes = vec![];
+        loop {
+            match self.chars.next() {
+                Some(c) if Some(c) == quote => return Ok(String::from_utf8_lossy(&bytes).into()),
+                Some('\\') => bytes.push(self.escape()?),
+                Some('\n') | None => return Err(self.error("unfinished string")),
+                Some(c) => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<u8, SyntaxError> {
+        match self.chars.next() {
+            Some('n') => Ok(b'\n'),
+            Some('t') => Ok(b'\t'),
+            Some('r') => Ok(b'\r'),
+            Some('a') => Ok(0x07),
+            Some('b') => Ok(0x08),
+            Some('f') => Ok(0x0c),
+            Some('v') => Ok(0x0b),
+            Some('\n') => Ok(b'\n'),
+            Some(c) if c.is_ascii_digit() => {
+                let mut code = c.to_digit(10).unwrap_or_default();
+                for _ in 0..2 {
+                    match self.chars.peek().and_then(|c| c.to_digit(10)) {
+                        Some(digit) => {
+                            code = code * 10 + digit;
+                            self.chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                u8::try_from(code).map_err(|_| self.error("invalid escape sequence"))
+            }
+            Some(c) if c.is_ascii() => Ok(c as u8),
+            _ => Err(self.error("invalid escape sequence")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, SyntaxError> {
+        let mut literal = String::new();
+        while let Some(c) = self.chars.peek() {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '+' | '.') {
+                literal.push(*c);
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+        literal
+            .parse()
+            .map(Value::Number)
+            .map_err(|_| self.error(&format!("invalid number '{}'", literal)))
+    }
+
+    fn word(&mut self) -> String {
+        let mut word = String::new();
+        while let Some(c) = self.chars.peek() {
+            if c.is_alphanumeric() || *c == '_' {
+                word.push(*c);
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+        word
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        self.word() == keyword
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), SyntaxError> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            _ => Err(self.error(&format!("expected '{}'", expected))),
+        }
+    }
+
+    /// Skips whitespace and `--` line comments.
+    fn skip_whitespace(&mut self) {
+        loop {
+            match self.chars.peek() {
+                Some(c) if c.is_whitespace() => {
+                    self.chars.next();
+                }
+                Some('-') => {
+                    let mut lookahead = self.chars.clone();
+                    lookahead.next();
+                    if lookahead.next() != Some('-') {
+                        return;
+                    }
+                    while let Some(c) = self.chars.peek() {
+                        if *c == '\n' {
+                            break;
+                        }
+                        self.chars.next();
+                    }
+                }
+                _ => return,
+            }
+        }
+    }
+
+    /// Error at the next unread character.
+    fn error(&self, message: &str) -> SyntaxError {
+        let remaining: usize = self.chars.clone().map(char::len_utf8).sum();
+        let (line, column) = position(self.source, self.source.len() - remaining);
+        SyntaxError {
+            line,
+            column,
+            message: message.to_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_nested_tables() {
+        let value = parse(
+            r#"-- we can read Lua syntax here!
+return {
+    ["doc_props"] = {
+        ["title"] = "Rustonomicon",
+    },
+    ["percent_finished"] = 0.25,
+    ["highlighted"] = true,
+}
+"#,
+        )
+        .unwrap();
+
+        let title = value.get("doc_props").and_then(|props| props.get("title"));
+        assert_eq!(Some("Rustonomicon"), title.and_then(Value::as_str));
+        assert_eq!(
+            Some(0.25),
+            value.get("percent_finished").and_then(Value::as_number)
+        );
+        assert_eq!(
+            Some(true),
+            value.get("highlighted").and_then(Value::as_bool)
+        );
+    }
+
+    #[test]
+    fn parse_string_escapes() {
+        let value = parse(r#"return { ["text"] = "line\nnext \"quoted\" \226\128\148" }"#).unwrap();
+
+        assert_eq!(
+            Some("line\nnext \"quoted\" —"),
+            value.get("text").and_then(Value::as_str)
+        );
+    }
+
+    #[test]
+    fn parse_positional_entries() {
+        let value = parse("return { 'first', 'second', [5] = -3 }").unwrap();
+
+        let entries = value.entries();
+        assert_eq!(3, entries.len());
+        assert_eq!(Value::Number(2.0), entries[1].0);
+        assert_eq!(
+            (Value::Number(5.0), Value::Number(-3.0)),
+            entries[2].clone()
+        );
+    }
+
+    #[test]
+    fn report_error_line() {
+        let error = parse("return {\n  [\"title\"] = ,\n}").unwrap_err();
+
+        assert_eq!((2, 15), (error.line, error.column));
+        assert!(error.to_string().starts_with("line 2"), "{}", error);
+    }
+}
@@ -0,0 +1,647 @@
+//! Import highlights from KOReader sidecar files.
+//!
+//! KOReader keeps annotations of every book in the `<book>.sdr/metadata.<ext>.lua` file next to the book.
+//! Recent versions store them in the `annotations` table,
+//! older versions use the `highlight` table paired with the `bookmarks` list.
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::error::HighlightError;
+use crate::highlights::details::{Color, Timestamp};
+use crate::highlights::{Book, Highlight, Location};
+use crate::input::koreader::lua::Value;
+use crate::input::{skipped_file, HighlightsRead, ReadMode, Warning};
+
+mod lua;
+
+/// Annotations read from one or many KOReader sidecar files.
+#[derive(Debug)]
+pub struct KoreaderSidecars {
+    sidecars: Vec<Sidecar>,
+}
+
+/// Book annotations from a single sidecar file.
+#[derive(Debug)]
+pub struct Sidecar {
+    title: String,
+    authors: String,
+    annotations: Vec<Annotation>,
+}
+
+/// Single KOReader annotation.
+#[derive(Clone, Debug, Default)]
+pub struct Annotation {
+    text: Option<String>,
+    note: Option<String>,
+    chapter: Option<String>,
+    datetime: Option<String>,
+    datetime_updated: Option<String>,
+    color: Option<String>,
+    page: Option<usize>,
+    position: Option<String>,
+}
+
+impl KoreaderSidecars {
+    /// Reads a single sidecar file or all sidecar files found in the library directory.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, HighlightError> {
+        KoreaderSidecars::read_files(path.as_ref(), ReadMode::Strict).map(|(sidecars, _)| sidecars)
+    }
+
+    /// Reads sidecar files skipping the ones that cannot be read.
+    ///
+    /// Every skipped sidecar is reported as the warning with its path.
+    pub fn open_lenient(path: impl AsRef<Path>) -> Result<(Self, Vec<Warning>), HighlightError> {
+        KoreaderSidecars::read_files(path.as_ref(), ReadMode::Lenient)
+    }
+
+    fn read_files(path: &Path, mode: ReadMode) -> Result<(Self, Vec<Warning>), HighlightError> {
+        let mut files = vec![];
+        if path.is_dir() {
+            find_sidecars(path, &mut files)?;
+            files.sort();
+        } else {
+            files.push(path.to_path_buf());
+        }
+
+        let mut sidecars = vec![];
+        let mut warnings = vec![];
+        for (index, file) in files.iter().enumerate() {
+            match (Sidecar::open(file), mode) {
+                (Ok(sidecar), _) => sidecars.push(sidecar),
+                (Err(error), ReadMode::Lenient) => {
+                    warnings.push(skipped_file(index + 1, file, &error))
+                }
+                (Err(error), ReadMode::Strict) => return Err(error),
+            }
+        }
+        Ok((KoreaderSidecars { sidecars }, warnings))
+    }
+
+    /// Sidecar files in the order they were read.
+    pub fn sidecars(&self) -> &[Sidecar] {
+        &self.sidecars
+    }
+}
+
+/// Recursively collects `metadata.*.lua` files from the `.sdr` directories.
+///
+/// Symbolic links are skipped, so links pointing back to the library cannot loop.
+fn find_sidecars(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), HighlightError> {
+    let cannot_read =
+        |e| HighlightError::io(format!("cannot read directory: {}", dir.display()), e);
+    for entry in fs::read_dir(dir).map_err(cannot_read)? {
+        let entry = entry.map_err(cannot_read)?;
+        let file_type = entry.file_type().map_err(cannot_read)?;
+        let path = entry.path();
+        if file_type.is_dir() {
+            find_sidecars(&path, files)?;
+        } else if file_type.is_file() && is_sidecar(&path) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn is_sidecar(path: &Path) -> bool {
+    let in_sdr = path
+        .parent()
+        .and_then(Path::file_name)
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.ends_with(".sdr"));
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("");
+    in_sdr && name.starts_with("metadata.") && name.ends_with(".lua")
+}
+
+impl Sidecar {
+    fn open(path: &Path) -> Result<Self, HighlightError> {
+        let content = fs::read_to_string(path).map_err(|e| {
+            HighlightError::io(format!("cannot read input file: {}", path.display()), e)
+        })?;
+        let mut sidecar = Sidecar::parse(&content)
+            .map_err(|e| invalid_sidecar(e).with_source(Some(path), content.as_bytes()))?;
+        if sidecar.title.is_empty() {
+            sidecar.title = path
+                .parent()
+                .and_then(Path::file_stem)
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+        }
+        Ok(sidecar)
+    }
+
+    fn parse(content: &str) -> Result<Self, lua::SyntaxError> {
+        let root = lua::parse(content)?;
+        let props = root.get("doc_props").or_else(|| root.get("stats"));
+        let title = props
+            .and_then(|props| string(props, "title"))
+            .or_else(|| document_name(&root))
+            .unwrap_or_default();
+        let authors = props
+            .and_then(|props| string(props, "authors"))
+            .map(|authors| authors.lines().collect::<Vec<&str>>().join(", "))
+            .unwrap_or_default();
+        let annotations = match root.get("annotations") {
+            Some(annotations) => annotations
+                .entries()
+                .iter()
+                .map(|(_, annotation)| Annotation::parse(annotation))
+                .collect(),
+            None => legacy_annotations(&root),
+        };
+        Ok(Sidecar {
+            title,
+            authors,
+            annotations,
+        })
+    }
+
+    /// Book title.
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// Book authors.
+    pub fn authors(&self) -> &str {
+        &self.authors
+    }
+
+    /// Book annotations in the order of the sidecar file.
+    pub fn annotations(&self) -> &[Annotation] {
+        &self.annotations
+    }
+}
+
+impl Annotation {
+    fn parse(table: &Value) -> Self {
+        // page bookmarks have no text position and carry generated text rather than a quote
+        let highlighted = table.get("pos0").is_some();
+        Annotation {
+            position: string(table, "pos0"),
+            text: string(table, "text").filter(|_| highlighted),
+            note: string(table, "note"),
+            chapter: string(table, "chapter"),
+            datetime: string(table, "datetime"),
+            datetime_updated: string(table, "datetime_updated"),
+            color: string(table, "color"),
+            page: number(table, "pageno").or_else(|| number(table, "page")),
+        }
+    }
+
+    /// Highlighted text.
+    pub fn text(&self) -> Option<&str> {
+        self.text.as_deref()
+    }
+
+    /// Reader note.
+    pub fn note(&self) -> Option<&str> {
+        self.note.as_deref()
+    }
+
+    /// Name of the chapter containing the annotation.
+    pub fn chapter(&self) -> Option<&str> {
+        self.chapter.as_deref()
+    }
+
+    /// Creation time in the `YYYY-MM-DD HH:MM:SS` format.
+    pub fn datetime(&self) -> Option<&str> {
+        self.datetime.as_deref()
+    }
+
+    /// Last edit time in the `YYYY-MM-DD HH:MM:SS` format.
+    pub fn datetime_updated(&self) -> Option<&str> {
+        self.datetime_updated.as_deref()
+    }
+
+    /// Name of the highlight color.
+    pub fn color(&self) -> Option<&str> {
+        self.color.as_deref()
+    }
+
+    /// Page number of the annotation.
+    pub fn page(&self) -> Option<usize> {
+        self.page
+    }
+
+    /// Start of the highlighted text, the XPointer in reflowable documents.
+    pub fn position(&self) -> Option<&str> {
+        self.position.as_deref()
+    }
+
+    /// Highlight at the page, linked to the start of the highlighted text.
+    fn highlight(&self) -> Option<Highlight> {
+        let link = self.position().unwrap_or_default();
+        let mut location = Location::new(self.page.unwrap_or_default(), link);
+        if let Some(chapter) = self.chapter() {
+            location = location.with_chapter(chapter);
+        }
+        let mut highlight = match (self.text.clone(), self.note.clone()) {
+            (Some(quote), Some(note)) => Highlight::comment(quote, note, location),
+            (Some(quote), None) => Highlight::quote(quote, location),
+            (None, Some(note)) => Highlight::note(note, location),
+            (None, None) => return None,
+        };
+        if let Some(created) = self.datetime().and_then(Timestamp::parse) {
+            highlight = highlight.with_created(created);
+        }
+        if let Some(modified) = self.datetime_updated().and_then(Timestamp::parse) {
+            highlight = highlight.with_modified(modified);
+        }
+        if let Some(color) = self.color().and_then(Color::parse) {
+            highlight = highlight.with_color(color);
+        }
+        Some(highlight)
+    }
+}
+
+/// Reads annotations from the `highlight` and `bookmarks` tables of older KOReader versions.
+///
+/// Highlights are grouped by page, while notes live in bookmarks with the same timestamp.
+fn legacy_annotations(root: &Value) -> Vec<Annotation> {
+    let mut annotations = vec![];
+    if let Some(pages) = root.get("highlight") {
+        for (page, highlights) in pages.entries() {
+            for (_, highlight) in highlights.entries() {
+                annotations.push(Annotation {
+                    text: string(highlight, "text"),
+                    position: string(highlight, "pos0"),
+                    chapter: string(highlight, "chapter"),
+                    datetime: string(highlight, "datetime"),
+                    page: page.as_number().map(|page| page as usize),
+                    ..Annotation::default()
+                });
+            }
+        }
+    }
+
+    if let Some(bookmarks) = root.get("bookmarks") {
+        for (_, bookmark) in bookmarks.entries() {
+            let note = string(bookmark, "text").filter(|text| !is_generated(text, bookmark));
+            let note = match note {
+                Some(note) => note,
+                None => continue,
+            };
+            let datetime = string(bookmark, "datetime");
+            let highlighted = bookmark
+                .get("highlighted")
+                .and_then(Value::as_bool)
+                .unwrap_or(false);
+            let highlight = annotations
+                .iter_mut()
+                .find(|annotation| highlighted && annotation.datetime == datetime);
+            match highlight {
+                Some(highlight) => highlight.note = Some(note),
+                None => annotations.push(Annotation {
+                    note: Some(note),
+                    position: string(bookmark, "pos0"),
+                    chapter: string(bookmark, "chapter"),
+                    datetime,
+                    page: number(bookmark, "pageno").or_else(|| number(bookmark, "page")),
+                    ..Annotation::default()
+                }),
+            }
+        }
+    }
+
+    annotations.sort_by_key(|annotation| annotation.page);
+    annotations
+}
+
+/// Older KOReader versions fill bookmark text with `Page N <highlight> @ <datetime>` until the reader edits it.
+fn is_generated(text: &str, bookmark: &Value) -> bool {
+    let highlighted = string(bookmark, "notes");
+    text.starts_with("Page ")
+        && text.contains(" @ ")
+        && highlighted.is_none_or(|highlighted| text.contains(&highlighted))
+}
+
+fn document_name(root: &Value) -> Option<String> {
+    string(root, "doc_path").and_then(|path| {
+        Path::new(&path)
+            .file_stem()
+            .map(|name| name.to_string_lossy().into_owned())
+    })
+}
+
+fn string(table: &Value, key: &str) -> Option<String> {
+    table
+        .get(key)
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_owned)
+}
+
+fn number(table: &Value, key: &str) -> Option<usize> {
+    table
+        .get(key)
+        .and_then(Value::as_number)
+        .map(|value| value as usize)
+}
+
+fn invalid_sidecar(error: lua::SyntaxError) -> HighlightError {
+    HighlightError::parse(
+        "invalid koreader sidecar file",
+        error.message,
+        error.line,
+        error.column,
+    )
+}
+
+impl From<Sidecar> for Book {
+    fn from(sidecar: Sidecar) -> Self {
+        let highlights = sidecar.annotations.iter().filter_map(Annotation::highlight);
+        Book::new(sidecar.title, sidecar.authors, highlights)
+    }
+}
+
+impl From<KoreaderSidecars> for Vec<Book> {
+    /// Converts every sidecar into a book, skipping sidecars without annotations.
+    fn from(koreader: KoreaderSidecars) -> Self {
+        koreader
+            .sidecars
+            .into_iter()
+            .map(Book::from)
+            .filter(|book| !book.highlights().is_empty())
+            .collect()
+    }
+}
+
+impl HighlightsRead for KoreaderSidecars {
+    /// Reads a single sidecar file.
+    fn from_reader(mut reader: impl Read) -> Result<Self, HighlightError> {
+        let mut content = String::new();
+        reader
+            .read_to_string(&mut content)
+            .map_err(|e| HighlightError::format("invalid koreader sidecar file", e))?;
+        let sidecar = Sidecar::parse(&content).map_err(invalid_sidecar)?;
+        Ok(KoreaderSidecars {
+            sidecars: vec![sidecar],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_fs::prelude::*;
+    use assert_fs::TempDir;
+
+    use super::*;
+
+    const SIDECAR: &str = r#"-- we can read Lua syntax here!
+return {
+    ["annotations"] = {
+        [1] = {
+            ["chapter"] = "The Relationship Between Safe and Unsafe Rust",
+            ["datetime"] = "2022-10-04 10:02:44",
+            ["drawer"] = "lighten",
+            ["page"] = "/body/DocFragment[4]/body/p[2]/text().0",
+            ["pageno"] = 12,
+            ["pos0"] = "/body/DocFragment[4]/body/p[2]/text().0",
+            ["pos1"] = "/body/DocFragment[4]/body/p[2]/text().52",
+            ["text"] = "Safe Rust is the true Rust programming language.",
+        },
+        [2] = {
+            ["chapter"] = "Data Layout",
+            ["color"] = "orange",
+            ["datetime"] = "2022-10-04 10:12:01",
+            ["datetime_updated"] = "2022-10-06 09:00:00",
+            ["note"] = "Check repr(C) too",
+            ["pageno"] = 30,
+            ["pos0"] = "/body/DocFragment[9]/body/p[1]/text().0",
+            ["pos1"] = "/body/DocFragment[9]/body/p[1]/text().20",
+            ["text"] = "Rust gives you the following ways to lay out composite data",
+        },
+        [3] = {
+            ["datetime"] = "2022-10-04 10:20:00",
+            ["page"] = "/body/DocFragment[12]/body/p[1]",
+            ["pageno"] = 41,
+            ["text"] = "in Ownership",
+        },
+    },
+    ["doc_path"] = "/mnt/onboard/books/rustonomicon.epub",
+    ["doc_props"] = {
+        ["authors"] = "The Rust Community",
+        ["title"] = "Rustonomicon",
+    },
+}
+"#;
+
+    const LEGACY_SIDECAR: &str = r#"return {
+    ["bookmarks"] = {
+        [1] = {
+            ["datetime"] = "2020-01-02 12:00:00",
+            ["highlighted"] = true,
+            ["notes"] = "Why this move?",
+            ["page"] = "/body/DocFragment[8]/body/p[4]/text().0",
+            ["pos0"] = "/body/DocFragment[8]/body/p[4]/text().0",
+            ["text"] = "Every move needs a purpose",
+        },
+        [2] = {
+            ["datetime"] = "2020-01-01 10:00:00",
+            ["highlighted"] = true,
+            ["notes"] = "we discard our decisions",
+            ["page"] = "/body/DocFragment[3]/body/p[1]/text().0",
+            ["pos0"] = "/body/DocFragment[3]/body/p[1]/text().0",
+            ["text"] = "Page 12 we discard our decisions @ 2020-01-01 10:00:00",
+        },
+    },
+    ["highlight"] = {
+        [45] = {
+            [1] = {
+                ["chapter"] = "Strategy",
+                ["datetime"] = "2020-01-02 12:00:00",
+                ["pos0"] = "/body/DocFragment[8]/body/p[4]/text().0",
+                ["text"] = "Why this move?",
+            },
+        },
+        [12] = {
+            [1] = {
+                ["chapter"] = "Introduction",
+                ["datetime"] = "2020-01-01 10:00:00",
+                ["pos0"] = "/body/DocFragment[3]/body/p[1]/text().0",
+                ["text"] = "we discard our decisions",
+            },
+        },
+    },
+    ["stats"] = {
+        ["authors"] = "Garry Kasparov",
+        ["title"] = "How Life Imitates Chess",
+    },
+}
+"#;
+
+    #[test]
+    fn read_book_details() {
+        let books = read(SIDECAR);
+
+        assert_eq!(1, books.len());
+        assert_eq!("Rustonomicon", books[0].title());
+        assert_eq!("The Rust Community", books[0].authors());
+    }
+
+    #[test]
+    fn read_annotations() {
+        let books = read(SIDECAR);
+        let highlights = books[0].highlights();
+
+        assert_eq!(2, highlights.len(), "page bookmarks must be skipped");
+        match &highlights[0] {
+            Highlight::Quote {
+                quote, location, ..
+            } => {
+                assert_eq!("Safe Rust is the true Rust programming language.", quote);
+                assert_eq!(12, location.value());
+            }
+            other => panic!("expected quote, got {:?}", other),
+        }
+        match &highlights[1] {
+            Highlight::Comment {
+                quote: _,
+                note,
+                location,
+                ..
+            } => {
+                assert_eq!("Check repr(C) too", note);
+                assert_eq!(30, location.value());
+            }
+            other => panic!("expected comment, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn carry_chapter_dates_and_color() {
+        let sidecars = KoreaderSidecars::from_reader(SIDECAR.as_bytes()).unwrap();
+        let annotation = &sidecars.sidecars()[0].annotations()[1];
+
+        assert_eq!(Some("Data Layout"), annotation.chapter());
+        assert_eq!(Some("2022-10-04 10:12:01"), annotation.datetime());
+        let books = read(SIDECAR);
+        let highlight = &books[0].highlights()[1];
+        assert_eq!(Some("Data Layout"), highlight.location().chapter());
+        assert_eq!(Timestamp::parse("2022-10-04 10:12:01"), highlight.created());
+        assert_eq!(
+            Timestamp::parse("2022-10-06 09:00:00"),
+            highlight.modified()
+        );
+        assert_eq!(Some(Color::Orange), highlight.color());
+    }
+
+    #[test]
+    fn read_legacy_highlights() {
+        let books = read(LEGACY_SIDECAR);
+        let highlights = books[0].highlights();
+
+        assert_eq!("How Life Imitates Chess", books[0].title());
+        assert_eq!(2, highlights.len());
+        match &highlights[0] {
+            Highlight::Quote {
+                quote, location, ..
+            } => {
+                assert_eq!("we discard our decisions", quote);
+                assert_eq!(12, location.value());
+            }
+            other => panic!("expected quote, got {:?}", other),
+        }
+        match &highlights[1] {
+            Highlight::Comment {
+                quote,
+                note,
+                location,
+                ..
+            } => {
+                assert_eq!("Why this move?", quote);
+                assert_eq!("Every move needs a purpose", note);
+                assert_eq!(45, location.value());
+            }
+            other => panic!("expected comment, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn walk_library_directory() {
+        let library = TempDir::new().unwrap();
+        library
+            .child("rust/rustonomicon.sdr/metadata.epub.lua")
+            .write_str(SIDECAR)
+            .unwrap();
+        library
+            .child("chess.sdr/metadata.epub.lua")
+            .write_str(LEGACY_SIDECAR)
+            .unwrap();
+        library
+            .child("chess.sdr/metadata.epub.lua.old")
+            .write_str("broken")
+            .unwrap();
+
+        let books: Vec<Book> = KoreaderSidecars::open(library.path()).unwrap().into();
+
+        assert_eq!(2, books.len());
+        assert_eq!("How Life Imitates Chess", books[0].title());
+        assert_eq!("Rustonomicon", books[1].title());
+    }
+
+    #[test]
+    fn link_highlights_to_text_position() {
+        let books = read(SIDECAR);
+        let highlight = &books[0].highlights()[0];
+
+        assert_eq!(
+            "/body/DocFragment[4]/body/p[2]/text().0",
+            highlight.location().link()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skip_symlinked_directories() {
+        let library = TempDir::new().unwrap();
+        library
+            .child("chess.sdr/metadata.epub.lua")
+            .write_str(LEGACY_SIDECAR)
+            .unwrap();
+        std::os::unix::fs::symlink(library.path(), library.child("loop").path()).unwrap();
+
+        let books: Vec<Book> = KoreaderSidecars::open(library.path()).unwrap().into();
+
+        assert_eq!(1, books.len());
+    }
+
+    #[test]
+    fn skip_broken_sidecars_leniently() {
+        let library = TempDir::new().unwrap();
+        library
+            .child("chess.sdr/metadata.epub.lua")
+            .write_str(LEGACY_SIDECAR)
+            .unwrap();
+        let broken = library.child("rust.sdr/metadata.epub.lua");
+        broken.write_str("return { [\"title\"] = }").unwrap();
+
+        assert!(KoreaderSidecars::open(library.path()).is_err());
+        let (sidecars, warnings) = KoreaderSidecars::open_lenient(library.path()).unwrap();
+        let books: Vec<Book> = sidecars.into();
+
+        assert_eq!(1, books.len());
+        assert_eq!(1, warnings.len());
+        assert_eq!(Some(broken.path()), warnings[0].path());
+        assert!(warnings[0]
+            .message()
+            .starts_with("invalid koreader sidecar file at line 1"));
+    }
+
+    #[test]
+    fn reject_invalid_sidecar() {
+        let result = KoreaderSidecars::from_reader("return { [\"title\"] = }".as_bytes());
+
+        assert!(result.is_err());
+    }
+
+    fn read(content: &str) -> Vec<Book> {
+        KoreaderSidecars::from_reader(content.as_bytes())
+            .unwrap()
+            .into()
+    }
+}
@@ -0,0 +1,572 @@
+//! Import highlights from the Markdown notes written by the markdown renderer.
+//!
+//! Notes start with the book title and authors followed by highlights separated with `---` lines:
+//!
+//! ```text
+//! # How Life Imitates Chess
+//!
+//! *by Garry Kasparov*
+//!
+//! ## Chapter 1
+//!
+//! ---
+//! > the reality is that we discard our decisions almost as soon as we make them
+//!
+//! Learn from the decisions
+//!
+//! [Location 157](kindle://book?action=open&asin=B0049U443Q&location=157)
+//!
+//! Added 2022-10-04 · Yellow
+//!
+//! #chess #decisions
+//! ```
+//!
+//! Blockquotes are the quote and other paragraphs are the note of the highlight.
+//! The details and tags lines after the location are optional.
+//! Light manual edits are tolerated: text written after the location joins the note,
+//! sections under headings other than chapters of highlights are skipped and lines may be wrapped.
+//! Blocks after `---` without a quote or a location line are sections written by hand and are skipped too.
+//! The `<!-- end of chapter -->` line ends the chapter before highlights without chapter.
+//! YAML frontmatter and block IDs of the Obsidian output without callouts are skipped as well.
+use std::io::Read;
+
+use crate::error::HighlightError;
+use crate::highlights::details::{Color, Timestamp};
+use crate::highlights::{Book, Highlight, Location};
+use crate::input::{HighlightsRead, ReadMode, Warning};
+use crate::render::markdown::escape::unescape;
+use crate::render::markdown::CHAPTER_END;
+
+/// Books read from the Markdown notes.
+#[derive(Debug)]
+pub struct MarkdownNotes {
+    books: Vec<Book>,
+}
+
+/// Highlight between two `---` lines.
+#[derive(Debug, Default)]
+struct Block {
+    /// Line of the `---` separator.
+    line: usize,
+    chapter: Option<String>,
+    quote: Vec<String>,
+    note: Vec<String>,
+    location: Option<Location>,
+    created: Option<Timestamp>,
+    modified: Option<Timestamp>,
+    color: Option<Color>,
+    tags: Vec<String>,
+}
+
+/// Book being read with its highlights.
+struct BookNotes {
+    title: String,
+    authors: Option<String>,
+    highlights: Vec<Highlight>,
+}
+
+/// Malformed highlight at the line of the notes.
+struct NotesError {
+    line: usize,
+    message: String,
+}
+
+impl MarkdownNotes {
+    /// Parses the notes.
+    ///
+    /// Lenient mode puts highlights without the location at location 0
+    /// and skips highlights without text and highlights before the book title, reporting them as warnings.
+    fn parse(content: &str, mode: ReadMode) -> Result<(Self, Vec<Warning>), HighlightError> {
+        let content = content.trim_start_matches('\u{feff}');
+        let lines: Vec<&str> = content.lines().collect();
+        let mut books: Vec<BookNotes> = vec![];
+        let mut warnings = vec![];
+        let mut chapter: Option<String> = None;
+        let mut block: Option<Block> = None;
+        let mut entries = 0;
+
+        let mut finish = |block: Option<Block>, books: &mut Vec<BookNotes>| {
+            let Some(block) = block.filter(Block::is_highlight) else {
+                return Ok(());
+            };
+            let line = block.line;
+            entries += 1;
+            if mode == ReadMode::Lenient && block.location.is_none() && block.has_text() {
+                warnings.push(Warning::new(
+                    entries,
+                    "missing location line, location set to 0",
+                ));
+            }
+            let result = match books.last_mut() {
+                Some(book) => block.into_highlight(mode).map(|highlight| {
+                    book.highlights.extend(highlight);
+                }),
+                None => Err(NotesError {
+                    line,
+                    message: "highlight before the book title".to_owned(),
+                }),
+            };
+            match (result, mode) {
+                (Ok(()), _) => Ok(()),
+                (Err(error), ReadMode::Lenient) => {
+                    warnings.push(Warning::new(entries, format!("{}, skipped", error.message)));
+                    Ok(())
+                }
+                (Err(error), ReadMode::Strict) => Err(error),
+            }
+        };
+
+        for (index, line) in lines.iter().enumerate().skip(frontmatter_end(&lines)) {
+            let number = index + 1;
+            let trimmed = line.trim();
+            if let Some(title) = line.strip_prefix("# ") {
+                finish(block.take(), &mut books)?;
+                chapter = None;
+                books.push(BookNotes {
+                    title: inline_text(title),
+                    authors: None,
+                    highlights: vec![],
+                });
+            } else if let Some(title) = line.strip_prefix("## ") {
+                finish(block.take(), &mut books)?;
+                chapter = Some(inline_text(title));
+            } else if trimmed == CHAPTER_END {
+                finish(block.take(), &mut books)?;
+                chapter = None;
+            } else if trimmed == "---" {
+                finish(block.take(), &mut books)?;
+                block = Some(Block {
+                    line: number,
+                    chapter: chapter.clone(),
+                    ..Block::default()
+                });
+            } else if let Some(block) = &mut block {
+                block.read_line(line);
+            } else if let Some(book) = books.last_mut() {
+                if book.authors.is_none() {
+                    book.authors = parse_authors(trimmed);
+                }
+            }
+        }
+        finish(block.take(), &mut books)?;
+
+        let books = books
+            .into_iter()
+            .map(|book| {
+                Book::new(
+                    book.title,
+                    book.authors.unwrap_or_default(),
+                    book.highlights,
+                )
+            })
+            .collect();
+        Ok((MarkdownNotes { books }, warnings))
+    }
+}
+
+impl Block {
+    /// Reads the line of the highlight.
+    ///
+    /// Details and tags are recognised only after the location, like the renderer writes them.
+    fn read_line(&mut self, line: &str) {
+        let trimmed = line.trim();
+        if let Some(quote) = trimmed.strip_prefix('>') {
+            self.quote.push(quote.trim().to_owned());
+        } else if trimmed.is_empty() {
+            if !self.note.last().is_none_or(String::is_empty) {
+                self.note.push(String::new());
+            }
+        } else if let Some(location) = parse_location(trimmed).filter(|_| self.location.is_none()) {
+            self.location = Some(location);
+        } else if self.location.is_none() || !self.read_details(trimmed) {
+            self.note.push(trimmed.to_owned());
+        }
+    }
+
+    /// Reads the `Added … · Updated … · Color` or the `#tag` line.
+    fn read_details(&mut self, line: &str) -> bool {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words
+            .iter()
+            .all(|word| word.len() > 1 && word.starts_with('#'))
+        {
+            self.tags
+                .extend(words.iter().map(|word| word[1..].to_owned()));
+            return true;
+        }
+        match parse_details(line) {
+            Some((created, modified, color)) => {
+                self.created = self.created.or(created);
+                self.modified = self.modified.or(modified);
+                self.color = self.color.or(color);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Checks if the block is a highlight, blocks without a quote or a location line are written by hand.
+    fn is_highlight(&self) -> bool {
+        !self.quote.is_empty() || self.location.is_some()
+    }
+
+    fn has_text(&self) -> bool {
+        !text(&self.quote).is_empty() || !text(&self.note).is_empty()
+    }
+
+    /// Builds the highlight, `None` for the empty block.
+    fn into_highlight(self, mode: ReadMode) -> Result<Option<Highlight>, NotesError> {
+        let quote = text(&self.quote);
+        let note = text(&self.note);
+        let location = match (self.location, mode) {
+            (Some(location), _) => location,
+            (None, _) if quote.is_empty() && note.is_empty() => return Ok(None),
+            (None, ReadMode::Strict) => {
+                return Err(NotesError {
+                    line: self.line,
+                    message: "missing location line of the highlight".to_owned(),
+                })
+            }
+            (None, ReadMode::Lenient) => Location::new(0, ""),
+        };
+        let location = match self.chapter {
+            Some(chapter) => location.with_chapter(chapter),
+            None => location,
+        };
+        let highlight = match (quote.is_empty(), note.is_empty()) {
+            (false, true) => Highlight::quote(quote, location),
+            (true, false) => Highlight::note(note, location),
+            (false, false) => Highlight::comment(quote, note, location),
+            (true, true) => {
+                return Err(NotesError {
+                    line: self.line,
+                    message: "highlight without quote or note".to_owned(),
+                })
+            }
+        };
+        let highlight = match self.created {
+            Some(created) => highlight.with_created(created),
+            None => highlight,
+        };
+        let highlight = match self.modified {
+            Some(modified) => highlight.with_modified(modified),
+            None => highlight,
+        };
+        let highlight = match self.color {
+            Some(color) => highlight.with_color(color),
+            None => highlight,
+        };
+        Ok(Some(highlight.with_tags(self.tags)))
+    }
+}
+
+/// Line number after the YAML frontmatter, 0 without the frontmatter.
+fn frontmatter_end(lines: &[&str]) -> usize {
+    match lines.first() {
+        Some(line) if line.trim() == "---" => lines[1..]
+            .iter()
+            .position(|line| line.trim() == "---")
+            .map_or(0, |end| end + 2),
+        _ => 0,
+    }
+}
+
+/// Authors from the `*by Author*` line.
+fn parse_authors(line: &str) -> Option<String> {
+    let authors = line.strip_prefix("*by ")?.strip_suffix('*')?;
+    Some(inline_text(authors))
+}
+
+/// Location from the `[Location 157](link)` or `Location 157` line, block IDs after it are skipped.
+fn parse_location(line: &str) -> Option<Location> {
+    let (label, link) = match line.strip_prefix('[') {
+        Some(rest) => {
+            let (label, rest) = rest.split_once("](")?;
+            let (link, rest) = rest.rsplit_once(')')?;
+            if !rest.trim().is_empty() && !rest.trim().starts_with('^') {
+                return None;
+            }
+            (label, link.trim())
+        }
+        None => {
+            let label = line.split(" ^").next().unwrap_or(line);
+            (label, "")
+        }
+    };
+    let value = label.strip_prefix("Location ")?.trim().parse().ok()?;
+    let link = match link
+        .strip_prefix('<')
+        .and_then(|link| link.strip_suffix('>'))
+    {
+        Some(link) => link.replace("%3C", "<").replace("%3E", ">"),
+        None => link.to_owned(),
+    };
+    Some(Location::new(value, link))
+}
+
+/// Creation date, modification date and color from the details line.
+fn parse_details(line: &str) -> Option<(Option<Timestamp>, Option<Timestamp>, Option<Color>)> {
+    let mut details = (None, None, None);
+    for part in line.split('·').map(str::trim) {
+        if let Some(date) = part.strip_prefix("Added ") {
+            details.0 = Some(parse_date(date)?);
+        } else if let Some(date) = part.strip_prefix("Updated ") {
+            details.1 = Some(parse_date(date)?);
+        } else {
+            details.2 = Some(Color::parse(part)?);
+        }
+    }
+    Some(details)
+}
+
+/// Date of the details line, `None` when it is not a date.
+fn parse_date(date: &str) -> Option<Timestamp> {
+    let mut parts = date.trim().splitn(3, '-');
+    let year = parts.next()?.parse().ok()?;
+    let month = parts.next()?.parse().ok()?;
+    let day = parts.next()?.parse().ok()?;
+    Timestamp::new(year, month, day, 0, 0, 0)
+}
+
+/// Text of the heading or the emphasis with the escapes removed.
+fn inline_text(text: &str) -> String {
+    unescape(text.trim())
+}
+
+/// Joins the lines of paragraphs separated with empty lines.
+///
+/// Lines ending with the backslash are hard line breaks, other lines are wrapped.
+fn text(lines: &[String]) -> String {
+    let mut paragraphs: Vec<String> = vec![];
+    let mut paragraph = String::new();
+    for line in lines {
+        if line.is_empty() {
+            if !paragraph.is_empty() {
+                paragraphs.push(std::mem::take(&mut paragraph));
+            }
+            continue;
+        }
+        let backslashes = line.chars().rev().take_while(|c| *c == '\\').count();
+        let (line, hard_break) = match backslashes % 2 {
+            1 => (&line[..line.len() - 1], true),
+            _ => (line.as_str(), false),
+        };
+        paragraph.push_str(&unescape(line));
+        paragraph.push(if hard_break { '\n' } else { ' ' });
+    }
+    if !paragraph.is_empty() {
+        paragraphs.push(paragraph);
+    }
+    paragraphs
+        .iter()
+        .map(|paragraph| paragraph.trim_end())
+        .collect::<Vec<&str>>()
+        .join("\n\n")
+}
+
+impl From<NotesError> for HighlightError {
+    fn from(error: NotesError) -> Self {
+        HighlightError::parse("invalid markdown notes", error.message, error.line, 1)
+    }
+}
+
+impl From<MarkdownNotes> for Vec<Book> {
+    fn from(notes: MarkdownNotes) -> Self {
+        notes.books
+    }
+}
+
+impl HighlightsRead for MarkdownNotes {
+    fn from_reader(mut reader: impl Read) -> Result<Self, HighlightError> {
+        let mut content = String::new();
+        reader
+            .read_to_string(&mut content)
+            .map_err(|e| HighlightError::format("invalid markdown notes", e))?;
+        Ok(MarkdownNotes::parse(&content, ReadMode::Strict)?.0)
+    }
+
+    fn from_reader_lenient(mut reader: impl Read) -> Result<(Self, Vec<Warning>), HighlightError> {
+        let mut content = String::new();
+        reader
+            .read_to_string(&mut content)
+            .map_err(|e| HighlightError::format("invalid markdown notes", e))?;
+        MarkdownNotes::parse(&content, ReadMode::Lenient)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::highlights::examples;
+    use crate::render::markdown::obsidian::{ObsidianOptions, ObsidianRenderer};
+    use crate::render::markdown::MarkdownRenderer;
+    use crate::render::Render;
+
+    fn parse(content: &str) -> Vec<Book> {
+        MarkdownNotes::from_reader(content.as_bytes())
+            .unwrap()
+            .into()
+    }
+
+    fn details_book() -> Book {
+        let date = |day| Timestamp::new(2022, 10, day, 0, 0, 0).unwrap();
+        Book::new(
+            "Title: *Escaped* [sic]",
+            "First Author, Second Author",
+            [
+                Highlight::quote(
+                    "# Quote\nwith a line break\n\nand a paragraph",
+                    Location::new(1, "https://book.org/(1)").with_chapter("One"),
+                )
+                .with_created(date(4))
+                .with_modified(date(5))
+                .with_color(Color::Blue)
+                .with_tags(["chess", "plans"]),
+                Highlight::note("Note 1984. > 2", Location::new(2, "").with_chapter("One")),
+                Highlight::comment(
+                    "Quote",
+                    "Comment",
+                    Location::new(3, "kindle://3").with_chapter("Two"),
+                ),
+            ],
+        )
+    }
+
+    #[test]
+    fn read_rendered_markdown() {
+        for book in [examples::chess_book(), details_book()] {
+            let markdown = MarkdownRenderer.as_string(&book);
+
+            let books = parse(&markdown);
+
+            assert_eq!(1, books.len());
+            assert_eq!(book.title(), books[0].title());
+            assert_eq!(book.authors(), books[0].authors());
+            assert_eq!(book.highlights(), books[0].highlights());
+        }
+    }
+
+    #[test]
+    fn read_obsidian_output_without_callouts() {
+        let book = details_book();
+        let mut renderer = ObsidianRenderer::new(ObsidianOptions {
+            callouts: false,
+            ..ObsidianOptions::default()
+        });
+
+        let books = parse(&renderer.as_string(&book));
+
+        assert_eq!(book.highlights(), books[0].highlights());
+    }
+
+    #[test]
+    fn read_several_books() {
+        let content = MarkdownRenderer.as_string(&examples::chess_book())
+            + &MarkdownRenderer.as_string(&details_book());
+
+        let books = parse(&content);
+
+        assert_eq!(2, books.len());
+        assert_eq!(3, books[1].highlights().len());
+    }
+
+    #[test]
+    fn tolerate_manual_edits() {
+        let content = "# Title\n\n\
+                       ---\n\
+                       > the reality is that we discard\n\
+                       > our decisions\n\
+                       \n\
+                       [Location 157](kindle://157)\n\
+                       Agreed, wrapped\n\
+                       by hand\n\
+                       \n\
+                       ---\n\
+                       ---\n\
+                       > Quote\n\
+                       Location 200\n\
+                       \n\
+                       ## My thoughts\n\
+                       \n\
+                       Great book\n";
+
+        let books = parse(content);
+
+        assert_eq!("", books[0].authors());
+        assert_eq!(
+            vec![
+                Highlight::comment(
+                    "the reality is that we discard our decisions",
+                    "Agreed, wrapped by hand",
+                    Location::new(157, "kindle://157")
+                ),
+                Highlight::quote("Quote", Location::new(200, "")),
+            ],
+            books[0].highlights()
+        );
+    }
+
+    #[test]
+    fn skip_sections_written_by_hand() {
+        let content = "# Title\n\n\
+                       ---\n\
+                       > Quote\n\
+                       \n\
+                       Location 1\n\
+                       \n\
+                       ## My thoughts\n\
+                       \n\
+                       ---\n\
+                       Chess is life\n\
+                       \n\
+                       ---\n\
+                       More thoughts\n";
+
+        let books = parse(content);
+
+        assert_eq!(
+            vec![Highlight::quote("Quote", Location::new(1, ""))],
+            books[0].highlights()
+        );
+        let (_, warnings) = MarkdownNotes::from_reader_lenient(content.as_bytes()).unwrap();
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn reject_highlight_without_location() {
+        let content = "# Title\n\n*by Author*\n\n---\n> Quote\n\n---\n> Other\n";
+
+        let error = MarkdownNotes::from_reader(content.as_bytes()).unwrap_err();
+
+        match error {
+            HighlightError::Parse(error) => {
+                assert_eq!(5, error.line());
+                assert_eq!("missing location line of the highlight", error.reason());
+            }
+            other => panic!("expected parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn repair_highlights_leniently() {
+        let content = "\n---\n> Orphan\n\nLocation 1\n\n\
+                       # Title\n\n---\n> Quote\n\n---\nLocation 2\n";
+
+        let (notes, warnings) = MarkdownNotes::from_reader_lenient(content.as_bytes()).unwrap();
+        let books: Vec<Book> = notes.into();
+
+        assert_eq!(
+            vec![Highlight::quote("Quote", Location::new(0, ""))],
+            books[0].highlights()
+        );
+        let messages: Vec<&str> = warnings.iter().map(Warning::message).collect();
+        assert_eq!(
+            vec![
+                "highlight before the book title, skipped",
+                "missing location line, location set to 0",
+                "highlight without quote or note, skipped",
+            ],
+            messages
+        );
+    }
+}
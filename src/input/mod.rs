@@ -1,15 +1,159 @@
 //! Various input formats to read highlights from.
+use std::fmt::{Display, Formatter};
 use std::io::Read;
+use std::path::{Path, PathBuf};
 
 use crate::error::HighlightError;
 use crate::highlights::Book;
 
+pub mod apple_books;
 pub mod bookcision;
+pub mod clippings;
+pub mod format;
+pub mod json;
+pub mod kindle_html;
+pub mod kobo;
+pub mod koreader;
+pub mod markdown;
+pub mod readwise;
+pub(crate) mod sqlite;
 
 /// Read highlights from input sources.
 ///
-/// Each read object is guaranteed to be convertable to book highlights.
-pub trait HighlightsRead: Into<Book> {
+/// Readers of a single book are convertable to the [`Book`],
+/// readers of several books are convertable to the list of books with [`IntoBooks`].
+pub trait HighlightsRead: Sized {
     /// Creates highlights from the input source.
     fn from_reader(reader: impl Read) -> Result<Self, HighlightError>;
+
+    /// Creates highlights from the input source skipping or repairing the broken entries.
+    ///
+    /// Every skipped or repaired entry is reported as the warning.
+    /// Formats without recoverable entries read the input strictly.
+    fn from_reader_lenient(reader: impl Read) -> Result<(Self, Vec<Warning>), HighlightError> {
+        Ok((Self::from_reader(reader)?, vec![]))
+    }
+}
+
+/// Conversion of the read highlights to the list of books they contain.
+///
+/// Implemented for every type convertable into `Vec<Book>`.
+/// Single-book formats produce a list with exactly one book.
+pub trait IntoBooks {
+    /// Books of the input in the input order.
+    fn into_books(self) -> Vec<Book>;
+}
+
+impl<T: Into<Vec<Book>>> IntoBooks for T {
+    fn into_books(self) -> Vec<Book> {
+        self.into()
+    }
+}
+
+/// Parse error at the position reported by `serde_json`.
+///
+/// Errors without the position, like IO errors, become format errors.
+pub(crate) fn json_error(message: &str, error: serde_json::Error) -> HighlightError {
+    let (line, column) = (error.line(), error.column());
+    if line == 0 {
+        return HighlightError::format(message, std::io::Error::from(error));
+    }
+    HighlightError::parse(message, json_reason(&error), line, column)
+}
+
+/// Message of the `serde_json` error without the position suffix.
+pub(crate) fn json_reason(error: &serde_json::Error) -> String {
+    let reason = error.to_string();
+    let suffix = format!(" at line {} column {}", error.line(), error.column());
+    match reason.strip_suffix(&suffix) {
+        Some(reason) => reason.to_owned(),
+        None => reason,
+    }
+}
+
+/// Warning for the input file skipped because it cannot be read.
+pub(crate) fn skipped_file(entry: usize, path: &Path, error: &HighlightError) -> Warning {
+    let reason = match error {
+        HighlightError::Parse(error) => format!(
+            "{} at line {} column {}: {}",
+            error.message(),
+            error.line(),
+            error.column(),
+            error.reason()
+        ),
+        error => error
+            .to_string()
+            .lines()
+            .map(str::trim)
+            .collect::<Vec<&str>>()
+            .join(": "),
+    };
+    Warning::new(entry, format!("{}, skipped", reason)).with_path(path)
+}
+
+/// Converts the byte offset in the text to the 1-based line and column.
+pub(crate) fn position(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+    let line = before.matches('\n').count() + 1;
+    (line, before[line_start..].chars().count() + 1)
+}
+
+/// How the readers treat broken entries of the input.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ReadMode {
+    /// Fail on the first broken entry.
+    #[default]
+    Strict,
+    /// Skip or repair broken entries and report them as warnings.
+    Lenient,
+}
+
+/// Broken input entry skipped or repaired by the lenient reading.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Warning {
+    entry: usize,
+    message: String,
+    path: Option<PathBuf>,
+}
+
+impl Warning {
+    /// Creates the warning for the entry with 1-based position in the input.
+    pub fn new(entry: usize, message: impl Into<String>) -> Self {
+        Warning {
+            entry,
+            message: message.into(),
+            path: None,
+        }
+    }
+
+    /// Sets the path of the input file, so warnings from several inputs can be told apart.
+    pub fn with_path(mut self, path: &Path) -> Self {
+        self.path = Some(path.to_path_buf());
+        self
+    }
+
+    /// Position of the entry in the input, starting from 1.
+    pub fn entry(&self) -> usize {
+        self.entry
+    }
+
+    /// What was wrong with the entry and how it was handled.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Path of the input file, if set.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}: ", path.display())?;
+        }
+        write!(f, "entry {}: {}", self.entry, self.message)
+    }
 }
@@ -0,0 +1,353 @@
+//! Import highlights from the Readwise CSV export.
+//!
+//! Readwise exports every highlight as a row with the book details repeated on each row.
+//! The reader also accepts the bulk-import columns written by [`crate::render::readwise`].
+use std::io::Read;
+
+use serde::Deserialize;
+
+use crate::error::HighlightError;
+use crate::highlights::details::{Color, Timestamp};
+use crate::highlights::{Book, Highlight, Location};
+use crate::input::{position, HighlightsRead, Warning};
+
+/// Rows of the Readwise CSV export.
+#[derive(Debug)]
+pub struct ReadwiseCsv {
+    rows: Vec<ReadwiseRow>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ReadwiseRow {
+    #[serde(rename = "Highlight", default)]
+    highlight: String,
+    #[serde(rename = "Book Title", alias = "Title", default)]
+    title: String,
+    #[serde(rename = "Book Author", alias = "Author", default)]
+    authors: String,
+    #[serde(rename = "Amazon Book ID", default)]
+    asin: String,
+    #[serde(rename = "URL", default)]
+    url: String,
+    #[serde(rename = "Note", default)]
+    note: String,
+    #[serde(rename = "Location Type", default)]
+    location_type: String,
+    #[serde(rename = "Location", default)]
+    location: String,
+    #[serde(rename = "Color", default)]
+    color: String,
+    #[serde(rename = "Tags", default)]
+    tags: String,
+    #[serde(rename = "Highlighted at", alias = "Date", default)]
+    highlighted_at: String,
+}
+
+impl ReadwiseRow {
+    fn location(&self) -> Location {
+        let value = self.location.trim().parse().unwrap_or_default();
+        let link = if !self.url.is_empty() {
+            self.url.clone()
+        } else if !self.asin.is_empty() && self.location_type == "location" {
+            format!(
+                "kindle://book?action=open&asin={}&location={}",
+                self.asin, value
+            )
+        } else {
+            String::new()
+        };
+        Location::new(value, link)
+    }
+
+    fn highlight(&self) -> Option<Highlight> {
+        let quote = self.highlight.trim();
+        let note = self.note.trim();
+        let location = self.location();
+        let mut highlight = match (quote.is_empty(), note.is_empty()) {
+            (false, false) => Highlight::comment(quote, note, location),
+            (false, true) => Highlight::quote(quote, location),
+            (true, false) => Highlight::note(note, location),
+            (true, true) => return None,
+        };
+        if let Some(created) = Timestamp::parse(&self.highlighted_at) {
+            highlight = highlight.with_created(created);
+        }
+        if let Some(color) = Color::parse(&self.color) {
+            highlight = highlight.with_color(color);
+        }
+        Some(highlight.with_tags(self.tags.split(',')).with_note_tags())
+    }
+}
+
+impl From<ReadwiseCsv> for Vec<Book> {
+    /// Groups rows by the book title and authors keeping the order of the first appearance.
+    fn from(readwise: ReadwiseCsv) -> Self {
+        let mut books: Vec<(&ReadwiseRow, Vec<Highlight>)> = vec![];
+        for row in &readwise.rows {
+            let highlight = match row.highlight() {
+                Some(highlight) => highlight,
+                None => continue,
+            };
+            let book = books
+                .iter_mut()
+                .find(|(first, _)| first.title == row.title && first.authors == row.authors);
+            match book {
+                Some((_, highlights)) => highlights.push(highlight),
+                None => books.push((row, vec![highlight])),
+            }
+        }
+
+        books
+            .into_iter()
+            .map(|(row, highlights)| {
+                let book = Book::new(row.title.clone(), row.authors.clone(), highlights);
+                if row.asin.is_empty() {
+                    book
+                } else {
+                    book.with_asin(row.asin.clone())
+                }
+            })
+            .collect()
+    }
+}
+
+impl HighlightsRead for ReadwiseCsv {
+    fn from_reader(mut reader: impl Read) -> Result<Self, HighlightError> {
+        let mut content = vec![];
+        reader
+            .read_to_end(&mut content)
+            .map_err(|e| HighlightError::format("invalid readwise csv file", e))?;
+        let rows = csv::Reader::from_reader(content.as_slice())
+            .deserialize()
+            .collect::<Result<Vec<ReadwiseRow>, _>>()
+            .map_err(|e| invalid_csv(e, &content))?;
+        Ok(ReadwiseCsv { rows })
+    }
+
+    /// Skips rows that cannot be read, such as rows with the wrong number of fields.
+    fn from_reader_lenient(reader: impl Read) -> Result<(Self, Vec<Warning>), HighlightError> {
+        let mut rows = vec![];
+        let mut warnings = vec![];
+        for (index, row) in csv::Reader::from_reader(reader)
+            .deserialize::<ReadwiseRow>()
+            .enumerate()
+        {
+            match row {
+                Ok(row) => rows.push(row),
+                Err(e) if e.is_io_error() => {
+                    return Err(HighlightError::format(
+                        "invalid readwise csv file",
+                        std::io::Error::from(e),
+                    ))
+                }
+                Err(e) => {
+                    warnings.push(Warning::new(index + 1, format!("{}, skipped", reason(&e))))
+                }
+            }
+        }
+        Ok((ReadwiseCsv { rows }, warnings))
+    }
+}
+
+/// Parse error at the broken field, or at the start of the broken row.
+fn invalid_csv(error: csv::Error, content: &[u8]) -> HighlightError {
+    let offset = match (error.position(), error.kind()) {
+        (Some(position), csv::ErrorKind::Deserialize { err, .. }) => match err.field() {
+            Some(field) => field_offset(content, position.byte() as usize, field as usize),
+            None => position.byte() as usize,
+        },
+        (Some(position), _) => position.byte() as usize,
+        (None, _) => {
+            return HighlightError::format("invalid readwise csv file", std::io::Error::from(error))
+        }
+    };
+    let (line, column) = position(&String::from_utf8_lossy(content), offset);
+    HighlightError::parse("invalid readwise csv file", reason(&error), line, column)
+}
+
+/// Byte offset of the field in the row starting at the given offset.
+///
+/// Commas inside quoted fields do not separate fields.
+fn field_offset(content: &[u8], start: usize, field: usize) -> usize {
+    let mut quoted = false;
+    let mut remaining = field;
+    for (index, byte) in content.iter().enumerate().skip(start) {
+        if remaining == 0 {
+            return index;
+        }
+        match byte {
+            b'"' => quoted = !quoted,
+            b',' if !quoted => remaining -= 1,
+            b'\n' if !quoted => break,
+            _ => {}
+        }
+    }
+    start
+}
+
+/// Message of the CSV error without the position.
+fn reason(error: &csv::Error) -> String {
+    match error.kind() {
+        csv::ErrorKind::Deserialize { err, .. } => err.to_string(),
+        csv::ErrorKind::UnequalLengths {
+            expected_len, len, ..
+        } => format!(
+            "found record with {} fields, but the previous record has {} fields",
+            len, expected_len
+        ),
+        csv::ErrorKind::Utf8 { err, .. } => err.to_string(),
+        _ => error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPORT: &str = "\
+Highlight,Book Title,Book Author,Amazon Book ID,Note,Color,Tags,Location Type,Location,Highlighted at,Document tags
+the reality is that we discard our decisions,How Life Imitates Chess,Garry Kasparov,B0049U443Q,,yellow,,location,157,2022-10-04 10:02:44+00:00,
+\"Why this move? What am I trying to achieve, and how?\",How Life Imitates Chess,Garry Kasparov,B0049U443Q,\"Each move should contribute to some objective\",blue,\"strategy,chess\",location,447,2022-10-04 12:05:01+02:00,
+Unsafe Rust is dangerous,Rustonomicon,The Rust Community,,,,,order,3,,
+";
+
+    #[test]
+    fn read_books() {
+        let books = read(EXPORT);
+
+        assert_eq!(2, books.len());
+        assert_eq!("How Life Imitates Chess", books[0].title());
+        assert_eq!("Garry Kasparov", books[0].authors());
+        assert_eq!(2, books[0].highlights().len());
+        assert_eq!("Rustonomicon", books[1].title());
+    }
+
+    #[test]
+    fn read_asin() {
+        let books = read(EXPORT);
+
+        assert_eq!(Some("B0049U443Q"), books[0].asin());
+        assert_eq!(None, books[1].asin());
+    }
+
+    #[test]
+    fn read_kindle_location() {
+        let books = read(EXPORT);
+        let location = books[0].highlights()[0].location();
+
+        assert_eq!(157, location.value());
+        assert_eq!(
+            "kindle://book?action=open&asin=B0049U443Q&location=157",
+            location.link()
+        );
+    }
+
+    #[test]
+    fn read_note_as_comment() {
+        let books = read(EXPORT);
+
+        match &books[0].highlights()[1] {
+            Highlight::Comment { quote, note, .. } => {
+                assert_eq!(
+                    "Why this move? What am I trying to achieve, and how?",
+                    quote
+                );
+                assert_eq!("Each move should contribute to some objective", note);
+            }
+            other => panic!("expected comment, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn read_date_color_and_tags() {
+        let books = read(EXPORT);
+        let highlight = &books[0].highlights()[1];
+
+        assert_eq!(Timestamp::parse("2022-10-04 10:05:01"), highlight.created());
+        assert_eq!(Some(Color::Blue), highlight.color());
+        assert_eq!(["strategy", "chess"], highlight.tags());
+        assert!(books[1].highlights()[0].tags().is_empty());
+    }
+
+    #[test]
+    fn read_order_location_without_link() {
+        let books = read(EXPORT);
+        let location = books[1].highlights()[0].location();
+
+        assert_eq!(3, location.value());
+        assert_eq!("", location.link());
+    }
+
+    #[test]
+    fn read_import_columns() {
+        let csv = "\
+Highlight,Title,Author,URL,Note,Location,Date
+Quote,Title,Author,http://book.org/quotes/1,,1,
+";
+        let books = read(csv);
+
+        assert_eq!("Title", books[0].title());
+        assert_eq!(
+            "http://book.org/quotes/1",
+            books[0].highlights()[0].location().link()
+        );
+    }
+
+    #[test]
+    fn skip_broken_rows_leniently() {
+        let csv = "\
+Highlight,Title,Author
+First,Title,Author
+Broken,Title
+Second,Title,Author
+";
+
+        let (readwise, warnings) = ReadwiseCsv::from_reader_lenient(csv.as_bytes()).unwrap();
+        let books: Vec<Book> = readwise.into();
+
+        assert_eq!(2, books[0].highlights().len());
+        assert_eq!(1, warnings.len());
+        assert_eq!(2, warnings[0].entry());
+        match ReadwiseCsv::from_reader(csv.as_bytes()) {
+            Err(HighlightError::Parse(error)) => {
+                assert_eq!(3, error.line());
+                assert_eq!(
+                    "found record with 2 fields, but the previous record has 3 fields",
+                    error.reason()
+                );
+            }
+            other => panic!("expected parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn report_column_of_broken_field() {
+        let csv = "\
+Highlight,Title,Author,Highlighted at
+First,Title,Author,1
+\"Second, with a comma\",Title,Author,yesterday
+";
+        #[derive(Deserialize, Debug)]
+        #[allow(dead_code)]
+        struct Row {
+            #[serde(rename = "Highlighted at")]
+            highlighted_at: u32,
+        }
+        let error = csv::Reader::from_reader(csv.as_bytes())
+            .deserialize::<Row>()
+            .find_map(Result::err)
+            .unwrap();
+
+        match invalid_csv(error, csv.as_bytes()) {
+            HighlightError::Parse(error) => {
+                assert_eq!(3, error.line());
+                assert_eq!(37, error.column());
+            }
+            other => panic!("expected parse error, got {:?}", other),
+        }
+    }
+
+    fn read(csv: &str) -> Vec<Book> {
+        ReadwiseCsv::from_reader(csv.as_bytes()).unwrap().into()
+    }
+}
@@ -0,0 +1,70 @@
+//! Shared helpers for the readers of device SQLite databases.
+use std::io::{ErrorKind, Read};
+use std::path::Path;
+
+use rusqlite::{Connection, OpenFlags, Row, MAIN_DB};
+
+use crate::error::HighlightError;
+use crate::input::{ReadMode, Warning};
+
+/// Opens the database file in the read-only mode.
+pub(crate) fn open(path: &Path, format: &str) -> Result<Connection, HighlightError> {
+    Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| invalid_database(format, e))
+}
+
+/// Loads the whole database from the reader into memory.
+pub(crate) fn from_reader(
+    mut reader: impl Read,
+    format: &str,
+) -> Result<Connection, HighlightError> {
+    let mut bytes = vec![];
+    reader
+        .read_to_end(&mut bytes)
+        .map_err(|e| HighlightError::io(format!("cannot read {} database", format), e))?;
+    let mut connection = Connection::open_in_memory().map_err(|e| invalid_database(format, e))?;
+    connection
+        .deserialize_read_exact(MAIN_DB, bytes.as_slice(), bytes.len(), true)
+        .map_err(|e| invalid_database(format, e))?;
+    Ok(connection)
+}
+
+/// Wraps database errors into the format error.
+pub(crate) fn invalid_database(format: &str, error: rusqlite::Error) -> HighlightError {
+    HighlightError::format(
+        format!("invalid {} database", format),
+        std::io::Error::new(ErrorKind::InvalidData, error),
+    )
+}
+
+/// Reads the rows of the query.
+///
+/// Strict mode fails on the first row that cannot be decoded,
+/// lenient mode skips such rows and reports them as warnings.
+pub(crate) fn query_rows<T>(
+    connection: &Connection,
+    query: &str,
+    format: &str,
+    mode: ReadMode,
+    mut decode: impl FnMut(&Row) -> rusqlite::Result<T>,
+) -> Result<(Vec<T>, Vec<Warning>), HighlightError> {
+    let mut statement = connection
+        .prepare(query)
+        .map_err(|e| invalid_database(format, e))?;
+    let mut rows = statement
+        .query([])
+        .map_err(|e| invalid_database(format, e))?;
+    let mut decoded = vec![];
+    let mut warnings = vec![];
+    while let Some(row) = rows.next().map_err(|e| invalid_database(format, e))? {
+        match (decode(row), mode) {
+            (Ok(value), _) => decoded.push(value),
+            (Err(error), ReadMode::Lenient) => warnings.push(Warning::new(
+                decoded.len() + warnings.len() + 1,
+                format!("{}, skipped", error),
+            )),
+            (Err(error), ReadMode::Strict) => return Err(invalid_database(format, error)),
+        }
+    }
+    Ok((decoded, warnings))
+}
@@ -1,6 +1,7 @@
+use std::fs;
 use std::fs::File;
-use std::io::{stdin, stdout, Read, Write};
-use std::path::PathBuf;
+use std::io::{stdout, Write};
+use std::path::{Path, PathBuf};
 
 use highlights::error::HighlightError;
 
@@ -17,15 +18,24 @@ pub fn output(out_arg: Option<PathBuf>) -> Result<Box<dyn Write>, HighlightError
     }
 }
 
-pub fn input(in_arg: Option<PathBuf>) -> Result<Box<dyn Read>, HighlightError> {
-    match in_arg {
-        Some(path_buf) => {
-            let path = path_buf.as_path();
-            let input_file = File::open(path).map_err(|e| {
-                HighlightError::io(format!("cannot read input file: {}", path.display()), e)
-            })?;
-            Ok(Box::new(input_file))
-        }
-        None => Ok(Box::new(stdin())),
+/// Reads the output file written earlier.
+pub fn read_existing(path: &Path) -> Result<String, HighlightError> {
+    fs::read_to_string(path)
+        .map_err(|e| HighlightError::io(format!("cannot read file: {}", path.display()), e))
+}
+
+/// Replaces the content of the output file written earlier.
+pub fn write_existing(path: &Path, content: &str) -> Result<(), HighlightError> {
+    fs::write(path, content)
+        .map_err(|e| HighlightError::io(format!("cannot write to file: {}", path.display()), e))
+}
+
+/// Creates the directory of the output file with all missing parents.
+pub fn create_parent(path: &Path) -> Result<(), HighlightError> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => fs::create_dir_all(dir).map_err(|e| {
+            HighlightError::io(format!("cannot create directory: {}", dir.display()), e)
+        }),
+        _ => Ok(()),
     }
 }
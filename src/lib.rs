@@ -20,7 +20,7 @@
 //! let output_file = File::create("highlights.md").unwrap();
 //!
 //! let book = JsonBook::from_reader(input_file).unwrap().into();
-//! let mut renderer = MarkdownRenderer::default();
+//! let mut renderer = MarkdownRenderer;
 //! renderer.render(&book, output_file).unwrap();
 //!
 //! ```
@@ -1,12 +1,27 @@
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::io::stdin;
+use std::path::{Path, PathBuf};
 
 use clap::Parser;
 
+use regex::Regex;
+
 use highlights::error::HighlightError;
-use highlights::input::bookcision::JsonBook;
-use highlights::input::HighlightsRead;
-use highlights::render::markdown::MarkdownRenderer;
-use highlights::render::Render;
+use highlights::highlights::dedup::{dedup, Merge};
+use highlights::highlights::filter::{Filter, Kind, Locations};
+use highlights::highlights::library::{file_name, Library};
+use highlights::highlights::order::{Order, SortKey};
+use highlights::highlights::Book;
+use highlights::input::format;
+use highlights::input::format::InputFormat;
+use highlights::input::{ReadMode, Warning};
+use highlights::render::anki::AnkiRenderer;
+use highlights::render::html::HtmlRenderer;
+use highlights::render::markdown::obsidian::{ObsidianOptions, ObsidianRenderer};
+use highlights::render::markdown::update::update;
+use highlights::render::registry::Registry;
+use highlights::render::template::TemplateRenderer;
+use highlights::render::{DynRender, Render};
 
 mod io;
 
@@ -14,13 +29,137 @@ mod io;
 #[command(name = "highlights")]
 #[command(about = "Convert kindle highlights to markdown")]
 #[command(version = "v0.3.0-dev")]
+#[command(override_usage = "highlights [OPTIONS] [SOURCE] [TARGET]
+       highlights [OPTIONS] --out-dir <DIR> <SOURCE>...")]
 struct Cli {
-    #[arg(help = "input file")]
-    source: Option<PathBuf>,
-    #[arg(help = "output file")]
-    target: Option<PathBuf>,
+    #[arg(
+        value_name = "PATH",
+        help = "input file and output file, or input files and directories with --out-dir"
+    )]
+    paths: Vec<PathBuf>,
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        help = "input format, detected from the input when omitted"
+    )]
+    from: Option<InputFormat>,
+    #[arg(
+        long,
+        overrides_with = "strict",
+        help = "skip or repair broken highlights and report them as warnings"
+    )]
+    lenient: bool,
+    #[arg(
+        long,
+        overrides_with = "lenient",
+        help = "fail on the first broken highlight [default]"
+    )]
+    strict: bool,
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        help = "output format, detected from the output file extension when omitted"
+    )]
+    to: Option<String>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "stylesheet replacing the default one in html output"
+    )]
+    css: Option<PathBuf>,
+    #[arg(long, help = "render comments as cloze deletions in anki output")]
+    cloze: bool,
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with = "to",
+        help = "render with the minijinja template instead of the output format"
+    )]
+    template: Option<PathBuf>,
+    #[arg(
+        long = "tag",
+        value_name = "TAG",
+        help = "tag listed in the obsidian frontmatter, can be repeated"
+    )]
+    tags: Vec<String>,
+    #[arg(long, help = "skip the frontmatter in obsidian output")]
+    no_frontmatter: bool,
+    #[arg(
+        long,
+        help = "render plain blockquotes instead of callouts in obsidian output"
+    )]
+    no_callouts: bool,
+    #[arg(long, help = "skip the block IDs in obsidian output")]
+    no_block_ids: bool,
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "write one file per book into the directory"
+    )]
+    out_dir: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        default_value = DEFAULT_NAME,
+        requires = "out_dir",
+        help = "file name of each book with --out-dir, {ext} is the output format extension"
+    )]
+    name: String,
+    #[arg(
+        long,
+        help = "add only new highlights to existing markdown output, keeping the rest of the file"
+    )]
+    update: bool,
+    #[arg(
+        long,
+        requires = "update",
+        help = "show the highlights --update would add without writing them"
+    )]
+    dry_run: bool,
+    #[arg(
+        long,
+        help = "merge duplicate and overlapping highlights and attach orphaned notes to quotes"
+    )]
+    dedup: bool,
+    #[arg(
+        long,
+        value_name = "KINDS",
+        value_delimiter = ',',
+        help = "keep only highlights of the kinds: quotes, notes, comments"
+    )]
+    only: Vec<Kind>,
+    #[arg(
+        long,
+        value_name = "RANGE",
+        help = "keep only highlights in the location range, e.g. 100..400"
+    )]
+    location: Option<Locations>,
+    #[arg(
+        long,
+        value_name = "REGEX",
+        help = "keep only highlights with the quote or note matching the pattern"
+    )]
+    grep: Option<Regex>,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "keep only highlights with at least N characters"
+    )]
+    min_length: Option<usize>,
+    #[arg(
+        long,
+        value_name = "ORDER",
+        default_value_t = SortKey::Location,
+        help = "order of highlights: input, location, created or length"
+    )]
+    sort: SortKey,
+    #[arg(long, help = "reverse the order of highlights")]
+    reverse: bool,
 }
 
+/// File name of each book in the batch mode.
+const DEFAULT_NAME: &str = "{author} - {title}.{ext}";
+
 fn main() {
     let result = convert_highlights();
     match result {
@@ -34,7 +173,7 @@ fn main() {
                 HighlightError::IOError(_, _) => {
                     std::process::exit(74);
                 }
-                HighlightError::InvalidFormat(_, _) => {
+                HighlightError::InvalidFormat(_, _) | HighlightError::Parse(_) => {
                     std::process::exit(65);
                 }
             }
@@ -44,11 +183,259 @@ fn main() {
 
 fn convert_highlights() -> Result<(), HighlightError> {
     let cli = Cli::parse();
+    let registry = registry(&cli)?;
+    match &cli.out_dir {
+        Some(dir) => convert_library(&cli, &registry, dir),
+        None => convert_book(&cli, &registry),
+    }
+}
+
+fn read_mode(cli: &Cli) -> ReadMode {
+    if cli.lenient {
+        ReadMode::Lenient
+    } else {
+        ReadMode::Strict
+    }
+}
+
+fn report(warnings: &[Warning]) {
+    for warning in warnings {
+        eprintln!("warning: {}", warning);
+    }
+    match warnings.len() {
+        0 => {}
+        1 => eprintln!("1 broken entry skipped or repaired"),
+        n => eprintln!("{} broken entries skipped or repaired", n),
+    }
+}
+
+/// Prepares the book for rendering: merges, filters and orders the highlights.
+fn select(cli: &Cli, book: Book, filter: &Filter, order: &Order) -> Book {
+    let book = if cli.dedup {
+        let (book, merges) = dedup(book);
+        report_merges(&book, &merges);
+        book
+    } else {
+        book
+    };
+    order.apply(filter.apply(book))
+}
+
+fn report_merges(book: &Book, merges: &[Merge]) {
+    for merge in merges {
+        eprintln!("merged: {}: {}", book.title(), merge);
+    }
+    match merges.len() {
+        0 => {}
+        1 => eprintln!("1 highlight merged in {}", book.title()),
+        n => eprintln!("{} highlights merged in {}", n, book.title()),
+    }
+}
+
+/// Filter selecting highlights with the command line options.
+fn filter(cli: &Cli) -> Filter {
+    let mut filter = Filter::new().only(cli.only.iter().copied());
+    if let Some(locations) = cli.location {
+        filter = filter.locations(locations);
+    }
+    if let Some(pattern) = &cli.grep {
+        filter = filter.grep(pattern.clone());
+    }
+    if let Some(length) = cli.min_length {
+        filter = filter.min_length(length);
+    }
+    filter
+}
+
+/// Order of highlights selected with the command line options.
+fn order(cli: &Cli) -> Order {
+    let order = Order::new(cli.sort);
+    if cli.reverse {
+        order.reversed()
+    } else {
+        order
+    }
+}
+
+/// Registry with the renderers configured by the command line options.
+fn registry(cli: &Cli) -> Result<Registry, HighlightError> {
+    let mut registry = Registry::default();
+    if let Some(path) = &cli.css {
+        let stylesheet = std::fs::read_to_string(path).map_err(|e| {
+            HighlightError::io(format!("cannot read stylesheet: {}", path.display()), e)
+        })?;
+        registry.register("html", &["html", "htm"], move || {
+            Box::new(HtmlRenderer::with_stylesheet(stylesheet.clone()))
+        });
+    }
+    let obsidian = ObsidianOptions {
+        frontmatter: !cli.no_frontmatter,
+        callouts: !cli.no_callouts,
+        block_ids: !cli.no_block_ids,
+        tags: cli.tags.clone(),
+    };
+    registry.register("obsidian", &[], move || {
+        Box::new(ObsidianRenderer::new(obsidian.clone()))
+    });
+    if cli.cloze {
+        registry.register("anki", &["apkg"], || Box::new(AnkiRenderer::with_cloze()));
+    }
+    Ok(registry)
+}
+
+fn renderer(
+    cli: &Cli,
+    registry: &Registry,
+    output: Option<&Path>,
+) -> Result<Box<dyn DynRender>, HighlightError> {
+    match &cli.template {
+        Some(path) => Ok(Box::new(TemplateRenderer::from_file(path)?)),
+        None => registry.select(cli.to.as_deref(), output),
+    }
+}
+
+/// Converts a single input to a single output.
+fn convert_book(cli: &Cli, registry: &Registry) -> Result<(), HighlightError> {
+    if cli.paths.len() > 2 {
+        return Err(HighlightError::General(
+            "too many paths, use --out-dir to convert several inputs".to_owned(),
+        ));
+    }
+    let source = cli.paths.first();
+    let target = cli.paths.get(1);
+
+    let mode = read_mode(cli);
+    let (books, warnings) = match source {
+        Some(path) => format::read_path(path, cli.from, mode)?,
+        None => format::read_input(stdin(), cli.from, mode)?,
+    };
+    report(&warnings);
+    let filter = filter(cli);
+    let order = order(cli);
+
+    let mut renderer = renderer(cli, registry, target.map(PathBuf::as_path))?;
+    if cli.update {
+        let Some(target) = target else {
+            return Err(HighlightError::General(
+                "no output file, --update needs the file to update".to_owned(),
+            ));
+        };
+        let [book] = <[Book; 1]>::try_from(books).map_err(|_| {
+            HighlightError::General(
+                "several books in the input, use --out-dir to update one file per book".to_owned(),
+            )
+        })?;
+        return update_file(
+            cli,
+            &mut renderer,
+            &select(cli, book, &filter, &order),
+            target,
+        );
+    }
+    let books: Vec<Book> = books
+        .into_iter()
+        .map(|book| select(cli, book, &filter, &order))
+        .collect();
+    let out = io::output(target.cloned())?;
+    renderer.render_all(&books, out)
+}
+
+/// Converts all inputs to one output file per book in the directory.
+fn convert_library(cli: &Cli, registry: &Registry, dir: &Path) -> Result<(), HighlightError> {
+    if cli.paths.is_empty() {
+        return Err(HighlightError::General(
+            "no input files, --out-dir needs at least one input".to_owned(),
+        ));
+    }
+    let (library, warnings) = format::read_paths(&cli.paths, cli.from, read_mode(cli))?;
+    report(&warnings);
+    let filter = filter(cli);
+    let order = order(cli);
+    let library: Library = library
+        .into_iter()
+        .map(|book| select(cli, book, &filter, &order))
+        .filter(|book| filter.is_empty() || !book.highlights().is_empty())
+        .collect();
 
-    let input = io::input(cli.source)?;
-    let book = JsonBook::from_reader(input)?.into();
+    let extension = match (&cli.template, &cli.to) {
+        (Some(template), _) => template.extension().and_then(|e| e.to_str()),
+        (None, Some(to)) => registry.extension(to),
+        (None, None) => None,
+    };
+    let pattern = cli.name.replace("{ext}", extension.unwrap_or("md"));
+    let mut renderer = renderer(cli, registry, Some(Path::new(&pattern)))?;
+    for (book, path) in library.iter().zip(library_paths(&library, dir, &pattern)) {
+        if cli.update {
+            update_file(cli, &mut renderer, book, &path)?;
+            continue;
+        }
+        io::create_parent(&path)?;
+        let mut out = io::output(Some(path))?;
+        renderer.render(book, &mut out)?;
+    }
+    Ok(())
+}
 
-    let out = io::output(cli.target)?;
-    let mut renderer = MarkdownRenderer::default();
-    renderer.render(&book, out)
+/// Adds new highlights of the book to the existing output file, writes the file when it is missing.
+fn update_file(
+    cli: &Cli,
+    renderer: &mut Box<dyn DynRender>,
+    book: &Book,
+    path: &Path,
+) -> Result<(), HighlightError> {
+    if !path.exists() {
+        if cli.dry_run {
+            println!("would create {}", path.display());
+            return Ok(());
+        }
+        io::create_parent(path)?;
+        let mut out = io::output(Some(path.to_path_buf()))?;
+        return renderer.render(book, &mut out);
+    }
+    let existing = io::read_existing(path)?;
+    let mut rendered = vec![];
+    renderer.render(book, &mut rendered)?;
+    let update = update(&existing, book, &String::from_utf8_lossy(&rendered))?;
+    if cli.dry_run {
+        for addition in update.additions() {
+            println!("would add {} to {}", addition, path.display());
+            print!("{}", addition.text());
+        }
+        return Ok(());
+    }
+    if !update.is_empty() {
+        io::write_existing(path, update.content())?;
+    }
+    match update.additions().len() {
+        1 => eprintln!("1 new highlight added to {}", path.display()),
+        n => eprintln!("{} new highlights added to {}", n, path.display()),
+    }
+    Ok(())
+}
+
+/// Output file of every book, numbering books that get the same file name.
+fn library_paths(library: &Library, dir: &Path, pattern: &str) -> Vec<PathBuf> {
+    let mut taken = HashSet::new();
+    library
+        .iter()
+        .map(|book| {
+            let path = dir.join(file_name(book, pattern));
+            let mut unique = path.clone();
+            let mut number = 1;
+            while !taken.insert(unique.clone()) {
+                number += 1;
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                unique = match path.extension() {
+                    Some(extension) => path.with_file_name(format!(
+                        "{} ({}).{}",
+                        stem,
+                        number,
+                        extension.to_string_lossy()
+                    )),
+                    None => path.with_file_name(format!("{} ({})", stem, number)),
+                };
+            }
+            unique
+        })
+        .collect()
 }
@@ -0,0 +1,683 @@
+//! Anki flashcard package rendering for book highlights.
+//!
+//! The `.apkg` package is a zip archive with the `collection.anki2` SQLite database
+//! and the `media` manifest. Every book becomes a deck with a card per highlight,
+//! several books rendered together share one package.
+//! Note GUIDs are derived from the book and the highlight location,
+//! so importing an updated package replaces the cards instead of duplicating them.
+use std::collections::HashMap;
+use std::io::{Cursor, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection, MAIN_DB};
+use serde_json::{json, Value};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::error::HighlightError;
+use crate::highlights::{Book, Highlight, Location};
+use crate::render::html::{escape, is_safe_link};
+use crate::render::id::{hash, location_id};
+use crate::render::Render;
+
+const BASIC_MODEL_ID: i64 = 1_607_392_319_001;
+const CLOZE_MODEL_ID: i64 = 1_607_392_319_002;
+const FIELD_SEPARATOR: &str = "\x1f";
+
+const SCHEMA: &str = "
+CREATE TABLE col (
+    id integer primary key, crt integer not null, mod integer not null, scm integer not null,
+    ver integer not null, dty integer not null, usn integer not null, ls integer not null,
+    conf text not null, models text not null, decks text not null, dconf text not null,
+    tags text not null
+);
+CREATE TABLE notes (
+    id integer primary key, guid text not null, mid integer not null, mod integer not null,
+    usn integer not null, tags text not null, flds text not null, sfld integer not null,
+    csum integer not null, flags integer not null, data text not null
+);
+CREATE TABLE cards (
+    id integer primary key, nid integer not null, did integer not null, ord integer not null,
+    mod integer not null, usn integer not null, type integer not null, queue integer not null,
+    due integer not null, ivl integer not null, factor integer not null, reps integer not null,
+    lapses integer not null, left integer not null, odue integer not null, odid integer not null,
+    flags integer not null, data text not null
+);
+CREATE TABLE revlog (
+    id integer primary key, cid integer not null, usn integer not null, ease integer not null,
+    ivl integer not null, lastIvl integer not null, factor integer not null, time integer not null,
+    type integer not null
+);
+CREATE TABLE graves (usn integer not null, oid integer not null, type integer not null);
+CREATE INDEX ix_notes_usn on notes (usn);
+CREATE INDEX ix_cards_usn on cards (usn);
+CREATE INDEX ix_revlog_usn on revlog (usn);
+CREATE INDEX ix_cards_nid on cards (nid);
+CREATE INDEX ix_cards_sched on cards (did, queue, due);
+CREATE INDEX ix_revlog_cid on revlog (cid);
+CREATE INDEX ix_notes_csum on notes (csum);
+";
+
+const CARD_CSS: &str = ".card { font-family: Georgia, serif; font-size: 20px; text-align: left; }
+.source { font-size: 14px; color: #777; }
+.cloze { font-weight: bold; color: #0a5cab; }";
+
+/// Renders book highlights to the Anki `.apkg` package.
+///
+/// Quotes are on the front of the card, notes and the book location are on the back.
+/// With cloze enabled, comments become cloze deletions of the quote prompted by the note.
+#[derive(Default)]
+pub struct AnkiRenderer {
+    cloze: bool,
+}
+
+impl AnkiRenderer {
+    /// Creates the renderer turning comments into cloze deletions.
+    pub fn with_cloze() -> Self {
+        AnkiRenderer { cloze: true }
+    }
+
+    fn collection(&self, books: &[Book]) -> rusqlite::Result<Vec<u8>> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let now_ms = now.as_millis() as i64;
+        let now_s = now.as_secs() as i64;
+        let default_deck = books.first().map_or(1, deck_id);
+
+        let connection = Connection::open_in_memory()?;
+        connection.execute_batch(SCHEMA)?;
+        connection.execute(
+            "INSERT INTO col VALUES (1, ?1, ?2, ?3, 11, 0, 0, 0, ?4, ?5, ?6, ?7, '{}')",
+            params![
+                now_s,
+                now_ms,
+                now_ms,
+                collection_config().to_string(),
+                models(default_deck, now_s).to_string(),
+                decks(books, now_s).to_string(),
+                deck_config().to_string(),
+            ],
+        )?;
+
+        let mut position = 0;
+        for book in books {
+            let deck = deck_id(book);
+            let mut guids: HashMap<String, usize> = HashMap::new();
+            for highlight in book.highlights().iter() {
+                position += 1;
+                let id = now_ms + position;
+                let guid = location_id(book, &highlight.location());
+                let repeats = guids.entry(guid.clone()).or_default();
+                *repeats += 1;
+                // Highlights sharing the location are told apart by their order.
+                let guid = match *repeats {
+                    1 => guid,
+                    n => format!("{}-{}", guid, n),
+                };
+                let note = AnkiNote::new(book, highlight, self.cloze);
+                connection.execute(
+                    "INSERT INTO notes VALUES (?1, ?2, ?3, ?4, -1, '', ?5, ?6, ?7, 0, '')",
+                    params![
+                        id,
+                        guid,
+                        note.model,
+                        now_s,
+                        note.fields.join(FIELD_SEPARATOR),
+                        note.sort_field,
+                        checksum(&note.sort_field),
+                    ],
+                )?;
+                connection.execute(
+                    "INSERT INTO cards VALUES (?1, ?1, ?2, 0, ?3, -1, 0, 0, ?4, 0, 0, 0, 0, 0, 0, 0, 0, '')",
+                    params![id, deck, now_s, position],
+                )?;
+            }
+        }
+
+        let data = connection.serialize(MAIN_DB)?;
+        Ok(data.to_vec())
+    }
+}
+
+impl Render for AnkiRenderer {
+    /// Renders highlights to the Anki package.
+    ///
+    /// ## Example:
+    ///
+    /// ```
+    /// # use highlights::highlights::examples;
+    /// # use highlights::render::anki::AnkiRenderer;
+    /// # use highlights::render::Render;
+    /// let book = examples::chess_book();
+    /// let mut package = vec![];
+    /// let mut renderer = AnkiRenderer::default();
+    /// renderer.render(&book, &mut package).unwrap();
+    /// ```
+    fn render(&mut self, book: &Book, out: impl Write) -> Result<(), HighlightError> {
+        self.render_all(std::slice::from_ref(book), out)
+    }
+
+    /// Renders all books into one package with a deck per book.
+    fn render_all(&mut self, books: &[Book], mut out: impl Write) -> Result<(), HighlightError> {
+        let collection = self.collection(books).map_err(|e| {
+            HighlightError::io("cannot build anki collection", std::io::Error::other(e))
+        })?;
+        let package =
+            package(&collection).map_err(|e| HighlightError::io("cannot build anki package", e))?;
+        out.write_all(&package)
+            .map_err(|e| HighlightError::io("cannot write anki package", e))
+    }
+}
+
+struct AnkiNote {
+    model: i64,
+    fields: Vec<String>,
+    sort_field: String,
+}
+
+impl AnkiNote {
+    fn new(book: &Book, highlight: &Highlight, cloze: bool) -> Self {
+        let source = source(book, &highlight.location());
+        match highlight {
+            Highlight::Comment { quote, note, .. } if cloze => AnkiNote {
+                model: CLOZE_MODEL_ID,
+                fields: vec![
+                    format!(
+                        "{}<br><br>{{{{c1::{}}}}}",
+                        cloze_text(note),
+                        cloze_text(quote)
+                    ),
+                    source,
+                ],
+                sort_field: note.clone(),
+            },
+            Highlight::Quote { quote, .. } => AnkiNote {
+                model: BASIC_MODEL_ID,
+                fields: vec![text(quote), source],
+                sort_field: quote.clone(),
+            },
+            Highlight::Note { note, .. } => AnkiNote {
+                model: BASIC_MODEL_ID,
+                fields: vec![text(note), source],
+                sort_field: note.clone(),
+            },
+            Highlight::Comment { quote, note, .. } => AnkiNote {
+                model: BASIC_MODEL_ID,
+                fields: vec![text(quote), format!("{}<br><br>{}", text(note), source)],
+                sort_field: quote.clone(),
+            },
+        }
+    }
+}
+
+/// Escapes the field text keeping line breaks.
+fn text(text: &str) -> String {
+    text.trim()
+        .lines()
+        .map(escape)
+        .collect::<Vec<_>>()
+        .join("<br>")
+}
+
+/// Escapes the text of the cloze field, so braces and colons do not end the deletion
+/// or start another one.
+fn cloze_text(value: &str) -> String {
+    text(value)
+        .replace('{', "&#123;")
+        .replace('}', "&#125;")
+        .replace(':', "&#58;")
+}
+
+/// Book details and the location link shown on the back of the card.
+fn source(book: &Book, location: &Location) -> String {
+    let name = format!("Location {}", location.value());
+    let location = if is_safe_link(location.link()) {
+        format!("<a href=\"{}\">{}</a>", escape(location.link()), name)
+    } else {
+        name
+    };
+    format!(
+        "<div class=\"source\"><i>{}</i> by {}<br>{}</div>",
+        escape(book.title()),
+        escape(book.authors()),
+        location
+    )
+}
+
+/// Anki checksum of the sort field used to find duplicate notes.
+fn checksum(field: &str) -> i64 {
+    let digest = sha1_smol::Sha1::from(field).digest().to_string();
+    i64::from_str_radix(&digest[..8], 16).unwrap_or_default()
+}
+
+/// Deck identifier stays the same for the book, so updated cards go to the same deck.
+fn deck_id(book: &Book) -> i64 {
+    let id = hash(&[book.title(), book.authors()])[0] & ((1 << 52) - 1);
+    id.max(2) as i64
+}
+
+fn package(collection: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut zip = ZipWriter::new(Cursor::new(vec![]));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.start_file("collection.anki2", options)
+        .map_err(std::io::Error::other)?;
+    zip.write_all(collection)?;
+    zip.start_file("media", options)
+        .map_err(std::io::Error::other)?;
+    zip.write_all(b"{}")?;
+    let cursor = zip.finish().map_err(std::io::Error::other)?;
+    Ok(cursor.into_inner())
+}
+
+fn collection_config() -> Value {
+    json!({
+        "nextPos": 1,
+        "estTimes": true,
+        "activeDecks": [1],
+        "sortType": "noteFld",
+        "timeLim": 0,
+        "sortBackwards": false,
+        "addToCur": true,
+        "curDeck": 1,
+        "newBury": true,
+        "newSpread": 0,
+        "dueCounts": true,
+        "curModel": BASIC_MODEL_ID.to_string(),
+        "collapseTime": 1200
+    })
+}
+
+fn models(deck: i64, modified: i64) -> Value {
+    let basic = model(
+        BASIC_MODEL_ID,
+        "Highlights Basic",
+        0,
+        deck,
+        modified,
+        &["Front", "Back"],
+        json!({
+            "name": "Card 1",
+            "ord": 0,
+            "qfmt": "{{Front}}",
+            "afmt": "{{FrontSide}}<hr id=answer>{{Back}}",
+            "did": null,
+            "bqfmt": "",
+            "bafmt": ""
+        }),
+    );
+    let cloze = model(
+        CLOZE_MODEL_ID,
+        "Highlights Cloze",
+        1,
+        deck,
+        modified,
+        &["Text", "Back Extra"],
+        json!({
+            "name": "Cloze",
+            "ord": 0,
+            "qfmt": "{{cloze:Text}}",
+            "afmt": "{{cloze:Text}}<br>{{Back Extra}}",
+            "did": null,
+            "bqfmt": "",
+            "bafmt": ""
+        }),
+    );
+    json!({
+        BASIC_MODEL_ID.to_string(): basic,
+        CLOZE_MODEL_ID.to_string(): cloze,
+    })
+}
+
+fn model(
+    id: i64,
+    name: &str,
+    kind: u8,
+    deck: i64,
+    modified: i64,
+    fields: &[&str],
+    template: Value,
+) -> Value {
+    let fields: Vec<Value> = fields
+        .iter()
+        .enumerate()
+        .map(|(ord, name)| {
+            json!({
+                "name": name,
+                "ord": ord,
+                "sticky": false,
+                "rtl": false,
+                "font": "Arial",
+                "size": 20,
+                "media": []
+            })
+        })
+        .collect();
+    json!({
+        "id": id,
+        "name": name,
+        "type": kind,
+        "mod": modified,
+        "usn": -1,
+        "sortf": 0,
+        "did": deck,
+        "tmpls": [template],
+        "flds": fields,
+        "css": CARD_CSS,
+        "latexPre": "\\documentclass[12pt]{article}\n\\special{papersize=3in,5in}\n\\usepackage[utf8]{inputenc}\n\\usepackage{amssymb,amsmath}\n\\pagestyle{empty}\n\\setlength{\\parindent}{0in}\n\\begin{document}\n",
+        "latexPost": "\\end{document}",
+        "req": [[0, "any", [0]]],
+        "tags": [],
+        "vers": []
+    })
+}
+
+fn decks(books: &[Book], modified: i64) -> Value {
+    let mut decks = serde_json::Map::new();
+    decks.insert("1".to_owned(), deck_details(1, "Default", "", 0));
+    for book in books {
+        let deck = deck_id(book);
+        // Anki treats `::` in the deck name as the subdeck separator.
+        let name = book.title().replace("::", ":");
+        let description = format!("by {}", book.authors());
+        decks.insert(
+            deck.to_string(),
+            deck_details(deck, &name, &description, modified),
+        );
+    }
+    Value::Object(decks)
+}
+
+fn deck_details(id: i64, name: &str, description: &str, modified: i64) -> Value {
+    json!({
+        "id": id,
+        "name": name,
+        "desc": description,
+        "mod": modified,
+        "usn": -1,
+        "collapsed": false,
+        "browserCollapsed": false,
+        "newToday": [0, 0],
+        "revToday": [0, 0],
+        "lrnToday": [0, 0],
+        "timeToday": [0, 0],
+        "dyn": 0,
+        "conf": 1,
+        "extendNew": 10,
+        "extendRev": 50
+    })
+}
+
+fn deck_config() -> Value {
+    json!({
+        "1": {
+            "id": 1,
+            "name": "Default",
+            "mod": 0,
+            "usn": 0,
+            "dyn": false,
+            "maxTaken": 60,
+            "timer": 0,
+            "autoplay": true,
+            "replayq": true,
+            "new": {
+                "delays": [1, 10],
+                "ints": [1, 4, 7],
+                "initialFactor": 2500,
+                "separate": true,
+                "order": 1,
+                "perDay": 20,
+                "bury": false
+            },
+            "rev": {
+                "perDay": 200,
+                "ease4": 1.3,
+                "fuzz": 0.05,
+                "minSpace": 1,
+                "ivlFct": 1,
+                "maxIvl": 36500,
+                "bury": false
+            },
+            "lapse": {
+                "delays": [10],
+                "mult": 0,
+                "minInt": 1,
+                "leechFails": 8,
+                "leechAction": 0
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+    use crate::highlights::examples;
+    use crate::input::sqlite;
+
+    #[test]
+    fn package_collection_and_media() {
+        let package = render(&mut AnkiRenderer::default(), &examples::chess_book());
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(package)).unwrap();
+        let mut media = String::new();
+        archive
+            .by_name("media")
+            .unwrap()
+            .read_to_string(&mut media)
+            .unwrap();
+
+        assert_eq!("{}", media);
+        assert!(archive.by_name("collection.anki2").is_ok());
+    }
+
+    #[test]
+    fn create_card_per_highlight() {
+        let book = examples::chess_book();
+        let collection = collection(&mut AnkiRenderer::default(), &book);
+
+        let notes: i64 = collection
+            .query_row("SELECT count(*) FROM notes", [], |row| row.get(0))
+            .unwrap();
+        let cards: i64 = collection
+            .query_row(
+                "SELECT count(*) FROM cards JOIN notes ON cards.nid = notes.id",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+
+        assert_eq!(book.highlights().len() as i64, notes);
+        assert_eq!(notes, cards);
+    }
+
+    #[test]
+    fn render_several_books_into_one_package() {
+        let books = [
+            examples::chess_book(),
+            Book::new(
+                "Other",
+                "Author",
+                [Highlight::quote("Quote", Location::new(1, ""))],
+            ),
+        ];
+        let mut package = vec![];
+        AnkiRenderer::default()
+            .render_all(&books, &mut package)
+            .unwrap();
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(package)).unwrap();
+        let entry = archive.by_name("collection.anki2").unwrap();
+        let collection = sqlite::from_reader(entry, "anki").unwrap();
+        let decks: String = collection
+            .query_row("SELECT decks FROM col", [], |row| row.get(0))
+            .unwrap();
+        let card_decks: i64 = collection
+            .query_row("SELECT count(DISTINCT did) FROM cards", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        let cards: i64 = collection
+            .query_row("SELECT count(*) FROM cards", [], |row| row.get(0))
+            .unwrap();
+
+        assert_eq!(
+            1,
+            archive.len() - 1,
+            "one collection and the media manifest"
+        );
+        assert_eq!(
+            3,
+            serde_json::from_str::<Value>(&decks)
+                .unwrap()
+                .as_object()
+                .unwrap()
+                .len()
+        );
+        assert_eq!(2, card_decks);
+        assert_eq!(books[0].highlights().len() as i64 + 1, cards);
+    }
+
+    #[test]
+    fn put_quote_on_front_and_note_on_back() {
+        let book = Book::new(
+            "Title",
+            "Author",
+            [Highlight::comment(
+                "Quote <b>",
+                "Note",
+                Location::new(1, "http://book.org/1"),
+            )],
+        );
+
+        let fields = fields(&mut AnkiRenderer::default(), &book);
+
+        assert_eq!("Quote &lt;b&gt;", fields[0]);
+        assert!(fields[1].starts_with("Note<br><br>"), "{}", fields[1]);
+        assert!(
+            fields[1].contains("<a href=\"http://book.org/1\">Location 1</a>"),
+            "{}",
+            fields[1]
+        );
+    }
+
+    #[test]
+    fn skip_script_links() {
+        let book = Book::new(
+            "Title",
+            "Author",
+            [Highlight::quote(
+                "Quote",
+                Location::new(1, "java\tscript:alert(1)"),
+            )],
+        );
+
+        let fields = fields(&mut AnkiRenderer::default(), &book);
+
+        assert!(!fields[1].contains("href"), "{}", fields[1]);
+        assert!(fields[1].ends_with("<br>Location 1</div>"), "{}", fields[1]);
+    }
+
+    #[test]
+    fn render_comment_as_cloze() {
+        let book = Book::new(
+            "Title",
+            "Author",
+            [Highlight::comment("Quote", "Note", Location::new(1, ""))],
+        );
+
+        let collection = collection(&mut AnkiRenderer::with_cloze(), &book);
+        let model: i64 = collection
+            .query_row("SELECT mid FROM notes", [], |row| row.get(0))
+            .unwrap();
+        let fields = fields(&mut AnkiRenderer::with_cloze(), &book);
+
+        assert_eq!(CLOZE_MODEL_ID, model);
+        assert_eq!("Note<br><br>{{c1::Quote}}", fields[0]);
+    }
+
+    #[test]
+    fn escape_cloze_syntax() {
+        let book = Book::new(
+            "Title",
+            "Author",
+            [Highlight::comment(
+                "a}} b::c {{c1::d",
+                "{{c2::e}}",
+                Location::new(1, ""),
+            )],
+        );
+
+        let fields = fields(&mut AnkiRenderer::with_cloze(), &book);
+
+        assert_eq!(
+            "&#123;&#123;c2&#58;&#58;e&#125;&#125;<br><br>\
+             {{c1::a&#125;&#125; b&#58;&#58;c &#123;&#123;c1&#58;&#58;d}}",
+            fields[0]
+        );
+    }
+
+    #[test]
+    fn keep_guid_for_updated_highlight() {
+        let original = Book::new(
+            "Title",
+            "Author",
+            [Highlight::quote("Quote", Location::new(1, ""))],
+        );
+        let updated = Book::new(
+            "Title",
+            "Author",
+            [Highlight::comment("Quote", "Note", Location::new(1, ""))],
+        );
+
+        assert_eq!(guid(&original), guid(&updated));
+    }
+
+    #[test]
+    fn distinguish_highlights_at_same_location() {
+        let book = Book::new(
+            "Title",
+            "Author",
+            [
+                Highlight::quote("First", Location::new(1, "")),
+                Highlight::quote("Second", Location::new(1, "")),
+            ],
+        );
+
+        let distinct: i64 = collection(&mut AnkiRenderer::default(), &book)
+            .query_row("SELECT count(DISTINCT guid) FROM notes", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+
+        assert_eq!(2, distinct);
+    }
+
+    fn guid(book: &Book) -> String {
+        collection(&mut AnkiRenderer::default(), book)
+            .query_row("SELECT guid FROM notes", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    fn fields(renderer: &mut AnkiRenderer, book: &Book) -> Vec<String> {
+        let fields: String = collection(renderer, book)
+            .query_row("SELECT flds FROM notes", [], |row| row.get(0))
+            .unwrap();
+        fields.split(FIELD_SEPARATOR).map(str::to_owned).collect()
+    }
+
+    fn collection(renderer: &mut AnkiRenderer, book: &Book) -> Connection {
+        let package = render(renderer, book);
+        let mut archive = zip::ZipArchive::new(Cursor::new(package)).unwrap();
+        let entry = archive.by_name("collection.anki2").unwrap();
+        sqlite::from_reader(entry, "anki").unwrap()
+    }
+
+    fn render(renderer: &mut AnkiRenderer, book: &Book) -> Vec<u8> {
+        let mut package = vec![];
+        renderer.render(book, &mut package).unwrap();
+        package
+    }
+}
@@ -0,0 +1,385 @@
+//! HTML format rendering for book highlights.
+//!
+//! Renders the book as a standalone HTML5 document with an embedded stylesheet.
+//! Several books rendered together share one document with an article per book.
+use std::io::Write;
+
+use crate::error::HighlightError;
+use crate::highlights::{Book, Highlight, Location};
+use crate::render::{visit_book, BookVisitor, Render};
+
+/// Stylesheet embedded into the document unless replaced by the user stylesheet.
+pub const DEFAULT_STYLESHEET: &str = "\
+body { max-width: 42em; margin: 2em auto; padding: 0 1em; font-family: Georgia, serif; line-height: 1.5; color: #222; }
+header { border-bottom: 1px solid #ccc; margin-bottom: 1.5em; }
+.authors { font-style: italic; color: #555; }
+.highlight { margin: 1.5em 0; padding-bottom: 1em; border-bottom: 1px solid #eee; }
+blockquote { margin: 0 0 0.5em; padding-left: 1em; border-left: 3px solid #e0b000; }
+.note { margin: 0.5em 0; }
+.location { font-size: 0.85em; color: #777; }
+";
+
+/// Schemes of the links to books, other links are not rendered.
+const SAFE_SCHEMES: [&str; 5] = ["http", "https", "kindle", "ibooks", "file"];
+
+/// Renders book highlights to HTML format.
+pub struct HtmlRenderer {
+    stylesheet: String,
+}
+
+impl HtmlRenderer {
+    /// Creates the renderer embedding the given stylesheet instead of the default one.
+    pub fn with_stylesheet(stylesheet: impl Into<String>) -> Self {
+        HtmlRenderer {
+            stylesheet: stylesheet.into(),
+        }
+    }
+}
+
+impl Render for HtmlRenderer {
+    /// Renders highlights to HTML format.
+    ///
+    /// ## Example:
+    ///
+    /// ```
+    /// # use std::io::stdout;
+    /// # use highlights::highlights::examples;
+    /// # use highlights::render::html::HtmlRenderer;
+    /// # use highlights::render::Render;
+    /// let book = examples::chess_book();
+    /// let mut renderer = HtmlRenderer::default();
+    /// renderer.render(&book, stdout()).unwrap();
+    /// ```
+    fn render(&mut self, book: &Book, out: impl Write) -> Result<(), HighlightError> {
+        self.render_all(std::slice::from_ref(book), out)
+    }
+
+    /// Renders all books into one document.
+    fn render_all(&mut self, books: &[Book], out: impl Write) -> Result<(), HighlightError> {
+        let mut writer = HtmlWriter { writer: out };
+        let title = match books {
+            [book] => book.title(),
+            _ => "Highlights",
+        };
+        writer
+            .start_document(title, &self.stylesheet)
+            .and_then(|_| {
+                books
+                    .iter()
+                    .try_for_each(|book| visit_book(book, &mut writer))
+            })
+            .and_then(|_| writer.end_document())
+            .map_err(|e| HighlightError::io("cannot write html notes", e))
+    }
+}
+
+impl Default for HtmlRenderer {
+    fn default() -> Self {
+        HtmlRenderer::with_stylesheet(DEFAULT_STYLESHEET)
+    }
+}
+
+struct HtmlWriter<W> {
+    writer: W,
+}
+
+impl<W: Write> HtmlWriter<W> {
+    fn start_document(&mut self, title: &str, stylesheet: &str) -> std::io::Result<()> {
+        writeln!(self.writer, "<!DOCTYPE html>")?;
+        writeln!(self.writer, "<html>")?;
+        writeln!(self.writer, "<head>")?;
+        writeln!(self.writer, "<meta charset=\"utf-8\">")?;
+        writeln!(self.writer, "<title>{}</title>", escape(title))?;
+        writeln!(self.writer, "<style>\n{}\n</style>", style(stylesheet))?;
+        writeln!(self.writer, "</head>")?;
+        writeln!(self.writer, "<body>")
+    }
+
+    fn end_document(&mut self) -> std::io::Result<()> {
+        writeln!(self.writer, "</body>")?;
+        writeln!(self.writer, "</html>")
+    }
+}
+
+impl<W: Write> BookVisitor for HtmlWriter<W> {
+    fn book(&mut self, book: &Book) -> std::io::Result<()> {
+        writeln!(self.writer, "<article class=\"book\">")?;
+        writeln!(self.writer, "<header>")?;
+        writeln!(self.writer, "<h1>{}</h1>", escape(book.title()))?;
+        writeln!(
+            self.writer,
+            "<p class=\"authors\">by {}</p>",
+            escape(book.authors())
+        )?;
+        writeln!(self.writer, "</header>")
+    }
+
+    fn chapter(&mut self, title: &str) -> std::io::Result<()> {
+        writeln!(self.writer, "<section class=\"chapter\">")?;
+        writeln!(self.writer, "<h2>{}</h2>", escape(title))
+    }
+
+    fn end_chapter(&mut self) -> std::io::Result<()> {
+        writeln!(self.writer, "</section>")
+    }
+
+    fn highlight(&mut self, _highlight: &Highlight) -> std::io::Result<()> {
+        writeln!(self.writer, "<section class=\"highlight\">")
+    }
+
+    fn quote(&mut self, quote: &str) -> std::io::Result<()> {
+        writeln!(
+            self.writer,
+            "<blockquote>{}</blockquote>",
+            paragraphs(quote)
+        )
+    }
+
+    fn note(&mut self, note: &str) -> std::io::Result<()> {
+        writeln!(
+            self.writer,
+            "<div class=\"note\">{}</div>",
+            paragraphs(note)
+        )
+    }
+
+    fn location(&mut self, location: &Location) -> std::io::Result<()> {
+        let name = format!("Location {}", location.value());
+        if is_safe_link(location.link()) {
+            writeln!(
+                self.writer,
+                "<p class=\"location\"><a href=\"{}\">{}</a></p>",
+                escape(location.link()),
+                name
+            )
+        } else {
+            writeln!(self.writer, "<p class=\"location\">{}</p>", name)
+        }
+    }
+
+    fn end_highlight(&mut self) -> std::io::Result<()> {
+        writeln!(self.writer, "</section>")
+    }
+
+    fn end_book(&mut self) -> std::io::Result<()> {
+        writeln!(self.writer, "</article>")
+    }
+}
+
+/// Escapes the text for use in HTML content and attribute values.
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Splits the text into paragraphs on blank lines keeping single line breaks.
+fn paragraphs(text: &str) -> String {
+    text.split("\n\n")
+        .map(str::trim)
+        .filter(|paragraph| !paragraph.is_empty())
+        .map(|paragraph| {
+            let lines: Vec<String> = paragraph.lines().map(escape).collect();
+            format!("<p>{}</p>", lines.join("<br>"))
+        })
+        .collect()
+}
+
+/// Links are rendered only when present and relative or with a known scheme.
+///
+/// Browsers ignore ASCII whitespace and control characters in the scheme,
+/// so they are removed before the scheme is checked.
+pub(crate) fn is_safe_link(link: &str) -> bool {
+    let link: String = link
+        .chars()
+        .filter(|c| !c.is_ascii_control() && !c.is_ascii_whitespace())
+        .collect();
+    if link.is_empty() {
+        return false;
+    }
+    match link.find([':', '/', '?', '#']) {
+        Some(end) if link[end..].starts_with(':') => {
+            let scheme = &link[..end];
+            SAFE_SCHEMES
+                .iter()
+                .any(|safe| safe.eq_ignore_ascii_case(scheme))
+        }
+        _ => true,
+    }
+}
+
+/// Prevents the stylesheet from closing the style element.
+fn style(stylesheet: &str) -> String {
+    stylesheet.trim().replace("</", "<\\/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::highlights::examples;
+
+    #[test]
+    fn render_document() {
+        let html = HtmlRenderer::default().as_string(&examples::chess_book());
+
+        assert!(html.starts_with("<!DOCTYPE html>\n"));
+        assert!(html.contains("<title>How Life Imitates Chess: Making the Right Moves"));
+        assert!(html.contains("<h1>How Life Imitates Chess: Making the Right Moves"));
+        assert!(html.contains("<p class=\"authors\">by Garry Kasparov</p>"));
+        assert!(html.trim_end().ends_with("</html>"));
+    }
+
+    #[test]
+    fn render_several_books_into_one_document() {
+        let books = [
+            examples::chess_book(),
+            Book::new(
+                "Other",
+                "Author",
+                [Highlight::quote("Quote", Location::new(1, ""))],
+            ),
+        ];
+        let mut html = vec![];
+
+        HtmlRenderer::default()
+            .render_all(&books, &mut html)
+            .unwrap();
+
+        let html = String::from_utf8(html).unwrap();
+        assert_eq!(1, html.matches("<!DOCTYPE html>").count());
+        assert_eq!(1, html.matches("</html>").count());
+        assert!(html.contains("<title>Highlights</title>"));
+        assert_eq!(2, html.matches("<article class=\"book\">").count());
+        assert!(html.contains("<h1>Other</h1>"));
+    }
+
+    #[test]
+    fn render_highlights() {
+        let book = Book::new(
+            "Title",
+            "Author",
+            [
+                Highlight::quote("Quote", Location::new(1, "http://book.org/quotes/1")),
+                Highlight::comment("Commented", "Note", Location::new(2, "")),
+            ],
+        );
+
+        let html = HtmlRenderer::default().as_string(&book);
+
+        assert_eq!(2, html.matches("<section class=\"highlight\">").count());
+        assert!(html.contains("<blockquote><p>Quote</p></blockquote>"));
+        assert!(html.contains(
+            "<p class=\"location\"><a href=\"http://book.org/quotes/1\">Location 1</a></p>"
+        ));
+        assert!(html.contains("<blockquote><p>Commented</p></blockquote>"));
+        assert!(html.contains("<div class=\"note\"><p>Note</p></div>"));
+        assert!(html.contains("<p class=\"location\">Location 2</p>"));
+    }
+
+    #[test]
+    fn wrap_chapters_into_sections() {
+        let book = Book::new(
+            "Title",
+            "Author",
+            [
+                Highlight::quote("First", Location::new(1, "").with_chapter("One")),
+                Highlight::quote("Second", Location::new(2, "")),
+            ],
+        );
+
+        let html = HtmlRenderer::default().as_string(&book);
+
+        assert!(html.contains("<section class=\"chapter\">\n<h2>One</h2>\n"));
+        let closed = html.find("</section>\n</section>").unwrap();
+        assert!(closed < html.find("Second").unwrap());
+    }
+
+    #[test]
+    fn escape_book_content() {
+        let book = Book::new(
+            "<script>alert(1)</script>",
+            "Tom & Jerry",
+            [Highlight::quote(
+                "\"a\" < 'b'",
+                Location::new(1, "http://book.org/?a=1&b=\"2\""),
+            )],
+        );
+
+        let html = HtmlRenderer::default().as_string(&book);
+
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("<h1>&lt;script&gt;alert(1)&lt;/script&gt;</h1>"));
+        assert!(html.contains("by Tom &amp; Jerry"));
+        assert!(html.contains("<p>&quot;a&quot; &lt; &#39;b&#39;</p>"));
+        assert!(html.contains("href=\"http://book.org/?a=1&amp;b=&quot;2&quot;\""));
+    }
+
+    #[test]
+    fn skip_script_links() {
+        let book = Book::new(
+            "Title",
+            "Author",
+            [Highlight::quote(
+                "Quote",
+                Location::new(1, " JavaScript:alert(1)"),
+            )],
+        );
+
+        let html = HtmlRenderer::default().as_string(&book);
+
+        assert!(!html.contains("href"));
+        assert!(html.contains("<p class=\"location\">Location 1</p>"));
+    }
+
+    #[test]
+    fn allow_only_known_schemes() {
+        for link in [
+            "https://book.org/1",
+            "kindle://book?action=open&location=157",
+            "ibooks://assetid/8D8A#epubcfi(/6/8!/4/2)",
+            "file:///mnt/onboard/book.epub",
+            "quotes/1.html#note:2",
+            "#loc-157",
+        ] {
+            assert!(is_safe_link(link), "{link}");
+        }
+        for link in [
+            "",
+            " JavaScript:alert(1)",
+            "java\tscript:alert(1)",
+            "java\nscript:alert(1)",
+            "\u{1}javascript:alert(1)",
+            "vbscript:msgbox(1)",
+            "data:text/html,<script>alert(1)</script>",
+        ] {
+            assert!(!is_safe_link(link), "{link:?}");
+        }
+    }
+
+    #[test]
+    fn render_paragraphs() {
+        assert_eq!(
+            "<p>first<br>line</p><p>second</p>",
+            paragraphs("first\nline\n\nsecond\n")
+        );
+    }
+
+    #[test]
+    fn replace_stylesheet() {
+        let mut renderer = HtmlRenderer::with_stylesheet("body { color: red; }</style>");
+
+        let html = renderer.as_string(&examples::chess_book());
+
+        assert!(html.contains("<style>\nbody { color: red; }<\\/style>\n</style>"));
+        assert!(!html.contains("max-width"));
+    }
+}
@@ -0,0 +1,96 @@
+//! Stable identifiers of rendered highlights.
+use crate::highlights::{Book, Highlight, Location};
+
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+const FNV_OFFSETS: [u64; 2] = [0xcbf2_9ce4_8422_2325, 0x6c62_272e_07bb_0142];
+
+/// Identifier of the highlight formatted as a UUID.
+///
+/// The identifier depends only on the book and highlight content,
+/// so rendering the same highlights again keeps references to them valid.
+pub(crate) fn highlight_id(book: &Book, highlight: &Highlight) -> String {
+    let (quote, note) = match highlight {
+        Highlight::Quote { quote, .. } => (quote.as_str(), ""),
+        Highlight::Note { note, .. } => ("", note.as_str()),
+        Highlight::Comment { quote, note, .. } => (quote.as_str(), note.as_str()),
+    };
+    let value = highlight.location().value().to_string();
+    let parts = [book.title(), book.authors(), &value, quote, note];
+    let [high, low] = hash(&parts);
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        high >> 32,
+        (high >> 16) & 0xffff,
+        high & 0xffff,
+        low >> 48,
+        low & 0xffff_ffff_ffff
+    )
+}
+
+/// Identifier of the book location.
+///
+/// Unlike [`highlight_id`] it does not change when the highlight text is edited.
+pub(crate) fn location_id(book: &Book, location: &Location) -> String {
+    let value = location.value().to_string();
+    format!("{:016x}", hash(&[book.title(), book.authors(), &value])[0])
+}
+
+/// 128-bit FNV-1a hash of the parts.
+pub(crate) fn hash(parts: &[&str]) -> [u64; 2] {
+    FNV_OFFSETS.map(|offset| fnv1a(offset, parts))
+}
+
+fn fnv1a(offset: u64, parts: &[&str]) -> u64 {
+    parts.iter().fold(offset, |hash, part| {
+        part.bytes()
+            .chain(std::iter::once(0))
+            .fold(hash, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::highlights::Location;
+
+    #[test]
+    fn keep_id_stable() {
+        let book = Book::new("Title", "Author", []);
+        let highlight = Highlight::quote("Quote", Location::new(1, ""));
+
+        assert_eq!(
+            "0589598d-7257-e43f-7779-9ce6584b2270",
+            highlight_id(&book, &highlight)
+        );
+    }
+
+    #[test]
+    fn keep_location_id_for_edited_text() {
+        let book = Book::new("Title", "Author", []);
+        let location = Location::new(1, "");
+        let other = Book::new("Other", "Author", []);
+
+        assert_eq!(16, location_id(&book, &location).len());
+        assert_eq!(
+            location_id(&book, &location),
+            location_id(&book, &Location::new(1, "http://book.org/1"))
+        );
+        assert_ne!(
+            location_id(&book, &location),
+            location_id(&other, &location)
+        );
+    }
+
+    #[test]
+    fn distinguish_highlights() {
+        let book = Book::new("Title", "Author", []);
+        let quote = Highlight::quote("Quote", Location::new(1, ""));
+        let note = Highlight::note("Quote", Location::new(1, ""));
+        let moved = Highlight::quote("Quote", Location::new(2, ""));
+
+        assert_ne!(highlight_id(&book, &quote), highlight_id(&book, &note));
+        assert_ne!(highlight_id(&book, &quote), highlight_id(&book, &moved));
+    }
+}
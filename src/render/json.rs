@@ -0,0 +1,128 @@
+//! JSON rendering of the highlights data model.
+//!
+//! Each book is written as a separate JSON document tagged with the schema version:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "title": "Rustonomicon",
+//!   "authors": "The Rust Community",
+//!   "highlights": [
+//!     {
+//!       "type": "comment",
+//!       "quote": "Unsafe Rust is dangerous",
+//!       "note": "Use with care",
+//!       "location": { "value": 42, "link": "kindle://book?action=open&location=42" }
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Highlight `type` is one of `quote`, `note` or `comment`.
+//! The location has the optional `chapter` field when the input knows the chapter.
+//! The documents are read back by [`crate::input::json`].
+use std::io::Write;
+
+use serde::Serialize;
+
+use crate::error::HighlightError;
+use crate::highlights::Book;
+use crate::render::Render;
+
+/// Version of the JSON schema written by the renderer.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct Document<'a> {
+    version: u32,
+    #[serde(flatten)]
+    book: &'a Book,
+}
+
+/// Renders book highlights to the versioned JSON schema.
+pub struct JsonRenderer;
+
+impl Render for JsonRenderer {
+    /// Renders the book as a pretty-printed JSON document.
+    ///
+    /// ## Example:
+    ///
+    /// ```
+    /// # use std::io::stdout;
+    /// # use highlights::highlights::examples;
+    /// # use highlights::render::json::JsonRenderer;
+    /// # use highlights::render::Render;
+    /// let book = examples::chess_book();
+    /// let mut renderer = JsonRenderer;
+    /// renderer.render(&book, stdout()).unwrap();
+    /// ```
+    fn render(&mut self, book: &Book, mut out: impl Write) -> Result<(), HighlightError> {
+        let document = Document {
+            version: SCHEMA_VERSION,
+            book,
+        };
+        serde_json::to_writer_pretty(&mut out, &document)
+            .map_err(std::io::Error::from)
+            .and_then(|_| writeln!(out))
+            .map_err(|e| HighlightError::io("cannot write json", e))
+    }
+}
+
+impl Default for JsonRenderer {
+    fn default() -> Self {
+        JsonRenderer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::highlights::{examples, Highlight, Location};
+    use serde_json::{json, Value};
+
+    #[test]
+    fn render_versioned_document() {
+        let json = JsonRenderer.as_string(&Book::new("Title", "Author", []));
+
+        let value: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            json!({ "version": 1, "title": "Title", "authors": "Author", "highlights": [] }),
+            value
+        );
+    }
+
+    #[test]
+    fn render_tagged_highlights() {
+        let book = Book::new(
+            "Title",
+            "Author",
+            [
+                Highlight::quote("Quote", Location::new(1, "http://book.org/quotes/1")),
+                Highlight::note("Note", Location::new(2, "")),
+                Highlight::comment("Quote", "Note", Location::new(3, "")),
+            ],
+        );
+
+        let json = JsonRenderer.as_string(&book);
+
+        let value: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            json!({
+                "type": "quote",
+                "quote": "Quote",
+                "location": { "value": 1, "link": "http://book.org/quotes/1" }
+            }),
+            value["highlights"][0]
+        );
+        assert_eq!("note", value["highlights"][1]["type"]);
+        assert_eq!("comment", value["highlights"][2]["type"]);
+        assert_eq!("Note", value["highlights"][2]["note"]);
+    }
+
+    #[test]
+    fn end_document_with_newline() {
+        let json = JsonRenderer.as_string(&examples::chess_book());
+
+        assert!(json.ends_with("}\n"));
+    }
+}
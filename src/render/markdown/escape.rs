@@ -0,0 +1,207 @@
+//! Escaping of user text in Markdown.
+//!
+//! Highlight text is split into paragraphs on blank lines.
+//! Lines are trimmed and joined with hard line breaks, so the rendered text keeps its line structure.
+
+/// Characters interpreted as Markdown anywhere in the line.
+const INLINE: [char; 10] = ['\\', '`', '*', '_', '[', ']', '<', '>', '~', '|'];
+
+/// Characters starting the heading, list or setext underline at the start of the line.
+const LINE_START: [char; 4] = ['#', '-', '+', '='];
+
+/// Escapes Markdown metacharacters in the single line of text.
+pub(crate) fn escape(line: &str) -> String {
+    let mut escaped = String::with_capacity(line.len());
+    for (i, c) in line.char_indices() {
+        // Ordered list marker like `1984.` at the start of the line.
+        let list_marker =
+            matches!(c, '.' | ')') && i > 0 && line[..i].chars().all(|c| c.is_ascii_digit());
+        if INLINE.contains(&c)
+            || (c == '&' && is_entity(&line[i..]))
+            || (i == 0 && LINE_START.contains(&c))
+            || list_marker
+        {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Removes the backslashes escaping punctuation, the reverse of [`escape`].
+pub(crate) fn unescape(line: &str) -> String {
+    let mut unescaped = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match chars.peek() {
+            Some(next) if c == '\\' && next.is_ascii_punctuation() => {
+                unescaped.push(*next);
+                chars.next();
+            }
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Escapes the text for the single-line context like headings and link labels.
+pub(crate) fn escape_inline(text: &str) -> String {
+    escape(&text.split_whitespace().collect::<Vec<&str>>().join(" "))
+}
+
+/// Escapes the text keeping paragraphs and line breaks.
+pub(crate) fn paragraphs(text: &str) -> Vec<String> {
+    let mut paragraphs: Vec<Vec<String>> = vec![];
+    let mut current: Vec<String> = vec![];
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() {
+            if !current.is_empty() {
+                paragraphs.push(std::mem::take(&mut current));
+            }
+        } else {
+            current.push(escape(line));
+        }
+    }
+    if !current.is_empty() {
+        paragraphs.push(current);
+    }
+    paragraphs
+        .into_iter()
+        .map(|lines| lines.join("\\\n"))
+        .collect()
+}
+
+/// Escaped text as Markdown paragraphs separated by blank lines.
+pub(crate) fn paragraph(text: &str) -> String {
+    paragraphs(text).join("\n\n")
+}
+
+/// Escaped text as the blockquote with every line prefixed.
+pub(crate) fn blockquote(text: &str) -> String {
+    paragraphs(text)
+        .iter()
+        .map(|paragraph| {
+            paragraph
+                .lines()
+                .map(|line| format!("> {}", line))
+                .collect::<Vec<String>>()
+                .join("\n")
+        })
+        .collect::<Vec<String>>()
+        .join("\n>\n")
+}
+
+/// Link destination, wrapped in angle brackets when it has spaces or parentheses.
+pub(crate) fn destination(url: &str) -> String {
+    let url = url.trim();
+    if url.is_empty() || url.contains(|c: char| c.is_whitespace() || "()<>".contains(c)) {
+        format!("<{}>", url.replace('<', "%3C").replace('>', "%3E"))
+    } else {
+        url.to_owned()
+    }
+}
+
+/// Formats the tag as the `#tag` word.
+///
+/// Characters not allowed in Obsidian tags are replaced with `-`, so the tag stays a single word.
+pub(crate) fn tag(tag: &str) -> String {
+    let tag: String = tag
+        .trim()
+        .trim_start_matches('#')
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || "-_/".contains(c) {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!("#{}", tag)
+}
+
+/// Checks if the text starts with the HTML entity reference like `&amp;` or `&#42;`.
+fn is_entity(text: &str) -> bool {
+    let Some(end) = text.find(';') else {
+        return false;
+    };
+    let name = &text[1..end];
+    let name = name.strip_prefix('#').unwrap_or(name);
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_inline_metacharacters() {
+        assert_eq!(
+            "2 \\* 3 \\_is\\_ \\[six\\] \\<b\\> \\`code\\` \\~x\\~ a\\\\b",
+            escape("2 * 3 _is_ [six] <b> `code` ~x~ a\\b")
+        );
+    }
+
+    #[test]
+    fn escape_table_pipes() {
+        assert_eq!("a \\| b", escape("a | b"));
+        assert_eq!("\\-\\|-", escape("-|-"));
+    }
+
+    #[test]
+    fn escape_line_start() {
+        assert_eq!("\\# not a heading", escape("# not a heading"));
+        assert_eq!("\\- not a list", escape("- not a list"));
+        assert_eq!("\\+ not a list", escape("+ not a list"));
+        assert_eq!("\\===", escape("==="));
+        assert_eq!("1984\\. was a year", escape("1984. was a year"));
+        assert_eq!("1\\) first", escape("1) first"));
+        assert_eq!("a # b - c 1. d", escape("a # b - c 1. d"));
+    }
+
+    #[test]
+    fn unescape_escaped_line() {
+        let line = "# 2 * 3 [six] a\\b 1984. AT&T &amp; c:\\path";
+
+        assert_eq!(line, unescape(&escape(line)));
+        assert_eq!("keep \\d", unescape("keep \\d"));
+    }
+
+    #[test]
+    fn escape_entities_only() {
+        assert_eq!("AT&T \\&amp; \\&#42;", escape("AT&T &amp; &#42;"));
+    }
+
+    #[test]
+    fn keep_paragraphs_and_line_breaks() {
+        assert_eq!(
+            "first\\\nline\n\nsecond",
+            paragraph("  first\nline  \n\n\n second\n")
+        );
+    }
+
+    #[test]
+    fn prefix_every_blockquote_line() {
+        assert_eq!(
+            "> first\\\n> line\n>\n> \\# second",
+            blockquote("first\nline\n\n# second")
+        );
+    }
+
+    #[test]
+    fn wrap_destination_with_spaces() {
+        assert_eq!("http://book.org/1", destination("http://book.org/1"));
+        assert_eq!(
+            "<http://book.org/a (1)>",
+            destination("http://book.org/a (1)")
+        );
+        assert_eq!("<a%3Cb%3E>", destination("a<b>"));
+    }
+
+    #[test]
+    fn format_single_word_tag() {
+        assert_eq!("#books/chess", tag("books/chess"));
+        assert_eq!("#to-read", tag("#to read"));
+        assert_eq!("#c--", tag("c++"));
+    }
+}
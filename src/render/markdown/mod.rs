@@ -2,12 +2,20 @@
 use std::io::Write;
 
 use crate::error::HighlightError;
-use crate::highlights::{Book, Highlight};
+use crate::highlights::{Book, Highlight, Location};
 use crate::render::markdown::writer::MarkdownWriter;
-use crate::render::Render;
+use crate::render::{visit_book, BookVisitor, Render};
 
+pub(crate) mod escape;
+pub mod obsidian;
+pub mod update;
 mod writer;
 
+/// Line closing the chapter before highlights without chapter.
+///
+/// Headings cannot be closed in Markdown, the comment is hidden in the rendered notes.
+pub(crate) const CHAPTER_END: &str = "<!-- end of chapter -->";
+
 /// Renders the book into markdown format using supplied writer.
 ///
 /// Use renderer abstraction where possible.
@@ -24,43 +32,95 @@ mod writer;
 /// ```
 /// Produces the markdown output of the example book into the standard output.
 pub fn render_book(book: &Book, w: impl Write) -> std::io::Result<()> {
-    let mut md = MarkdownWriter::new(w);
-    md.heading(book.title())?.end_block()?;
-    let authors = format_args!("by {}", book.authors()).to_string();
-    md.italic(&authors)?.end_block()?;
-
-    let highlights = book.highlights();
-    for highlight in highlights {
-        md.line()?.lf()?;
-        match &highlight {
-            Highlight::Quote {
-                quote: quote_text,
-                location: _,
-            } => {
-                md.blockquote(quote_text)?.end_block()?;
-            }
-            Highlight::Note {
-                note: note_text,
-                location: _,
-            } => {
-                md.text(note_text)?.end_block()?;
-            }
-            Highlight::Comment {
-                quote: quote_text,
-                note: note_text,
-                location: _,
-            } => {
-                md.blockquote(quote_text)?.end_block()?;
-                md.text(note_text)?.end_block()?;
-            }
-        }
+    visit_book(book, &mut MarkdownWriter::new(w))
+}
+
+impl<W: Write> BookVisitor for MarkdownWriter<W> {
+    fn book(&mut self, book: &Book) -> std::io::Result<()> {
+        self.heading(book.title())?.end_block()?;
+        let authors = format_args!("by {}", book.authors()).to_string();
+        self.italic(&authors)?.end_block()?;
+        Ok(())
+    }
+
+    fn chapter(&mut self, title: &str) -> std::io::Result<()> {
+        MarkdownWriter::chapter(self, title)?.end_block()?;
+        Ok(())
+    }
+
+    fn end_chapter(&mut self) -> std::io::Result<()> {
+        MarkdownWriter::end_chapter(self);
+        Ok(())
+    }
 
-        let location = highlight.location();
+    fn highlight(&mut self, _highlight: &Highlight) -> std::io::Result<()> {
+        self.chapter_end_mark()?.line()?.lf()?;
+        Ok(())
+    }
+
+    fn quote(&mut self, quote: &str) -> std::io::Result<()> {
+        self.blockquote(quote)?.end_block()?;
+        Ok(())
+    }
+
+    fn note(&mut self, note: &str) -> std::io::Result<()> {
+        self.paragraph(note)?.end_block()?;
+        Ok(())
+    }
+
+    fn location(&mut self, location: &Location) -> std::io::Result<()> {
         let name = format_args!("Location {}", location.value()).to_string();
-        md.link(&name, location.link())?.end_block()?;
+        if location.link().is_empty() {
+            self.text(&name)?.end_block()?;
+        } else {
+            self.link(&name, location.link())?.end_block()?;
+        }
+        Ok(())
     }
 
-    Ok(())
+    fn details(&mut self, highlight: &Highlight) -> std::io::Result<()> {
+        if let Some(details) = details_line(highlight) {
+            self.text(&details)?.end_block()?;
+        }
+        if let Some(tags) = tags_line(highlight) {
+            self.text(&tags)?.end_block()?;
+        }
+        Ok(())
+    }
+}
+
+/// Creation date, modification date and color of the highlight, e.g. `Added 2022-10-04 · Yellow`.
+///
+/// The modification date is shown only when it differs from the creation date.
+pub(crate) fn details_line(highlight: &Highlight) -> Option<String> {
+    let created = highlight.created().map(|created| created.date());
+    let modified = highlight
+        .modified()
+        .map(|modified| modified.date())
+        .filter(|modified| Some(modified) != created.as_ref());
+    let color = highlight.color().map(|color| {
+        let name = color.name();
+        name[..1].to_uppercase() + &name[1..]
+    });
+    let parts: Vec<String> = [
+        created.map(|date| format!("Added {}", date)),
+        modified.map(|date| format!("Updated {}", date)),
+        color,
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    (!parts.is_empty()).then(|| parts.join(" · "))
+}
+
+/// Tags of the highlight as `#tag` words.
+pub(crate) fn tags_line(highlight: &Highlight) -> Option<String> {
+    let tags: Vec<String> = highlight
+        .tags()
+        .iter()
+        .map(|tag| escape::tag(tag))
+        .collect();
+    (!tags.is_empty()).then(|| tags.join(" "))
 }
 
 /// Renders book highlights to markdown format.
@@ -78,7 +138,7 @@ impl Render for MarkdownRenderer {
     /// # use highlights::render::Render;
     /// let mut out = stdout();
     /// let mut book = examples::chess_book();
-    /// let mut renderer = MarkdownRenderer::default();
+    /// let mut renderer = MarkdownRenderer;
     /// renderer.render(&mut book, &mut out).unwrap();
     /// ```
     fn render(&mut self, book: &Book, out: impl Write) -> Result<(), HighlightError> {
@@ -95,7 +155,9 @@ impl Default for MarkdownRenderer {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::highlights::Location;
+    use crate::highlights::details::{Color, Timestamp};
+    use crate::input::markdown::MarkdownNotes;
+    use crate::input::HighlightsRead;
 
     #[test]
     fn render_title() {
@@ -164,8 +226,113 @@ mod tests {
         assert!(lines.contains(&"[Location 1](http://book.org/comments/1)"));
     }
 
+    #[test]
+    fn render_location_without_link() {
+        let book = Book::new(
+            "Title",
+            "Author",
+            [Highlight::quote("Quote", Location::new(1, ""))],
+        );
+
+        let markdown = render_markdown(&book);
+        let lines: Vec<&str> = markdown.lines().collect();
+
+        assert!(lines.contains(&"Location 1"));
+    }
+
+    #[test]
+    fn render_chapters() {
+        let book = Book::new(
+            "Title",
+            "Author",
+            [
+                Highlight::quote("First", Location::new(1, "").with_chapter("One")),
+                Highlight::quote("Second", Location::new(2, "").with_chapter("One")),
+                Highlight::quote("Third", Location::new(3, "").with_chapter("Two")),
+            ],
+        );
+
+        let markdown = render_markdown(&book);
+        let headings: Vec<&str> = markdown
+            .lines()
+            .filter(|line| line.starts_with("## "))
+            .collect();
+
+        assert_eq!(vec!["## One", "## Two"], headings);
+        assert!(markdown.contains("## One\n\n---\n> First"));
+    }
+
+    #[test]
+    fn skip_chapters_in_other_orders() {
+        let book = Book::new(
+            "Title",
+            "Author",
+            [
+                Highlight::quote("Second", Location::new(2, "").with_chapter("Two")),
+                Highlight::quote("First", Location::new(1, "").with_chapter("One")),
+                Highlight::quote("Third", Location::new(3, "").with_chapter("Two")),
+            ],
+        );
+
+        let markdown = render_markdown(&book);
+
+        assert!(!markdown.contains("## "), "{}", markdown);
+    }
+
+    #[test]
+    fn close_chapter_before_highlight_without_chapter() {
+        let book = Book::new(
+            "Title",
+            "Author",
+            [
+                Highlight::quote("First", Location::new(1, "").with_chapter("One")),
+                Highlight::quote("Second", Location::new(2, "")),
+                Highlight::quote("Third", Location::new(3, "").with_chapter("Two")),
+            ],
+        );
+
+        let markdown = render_markdown(&book);
+
+        assert_eq!(1, markdown.matches(CHAPTER_END).count());
+        assert!(markdown.contains("Location 1\n\n<!-- end of chapter -->\n\n---\n> Second"));
+        let read: Vec<Book> = MarkdownNotes::from_reader(markdown.as_bytes())
+            .unwrap()
+            .into();
+        let chapters: Vec<Option<String>> = read[0]
+            .highlights()
+            .iter()
+            .map(|highlight| highlight.location().chapter().map(str::to_owned))
+            .collect();
+        assert_eq!(
+            vec![Some("One".to_owned()), None, Some("Two".to_owned())],
+            chapters
+        );
+    }
+
+    #[test]
+    fn render_details_under_location() {
+        let highlight = Highlight::quote("Quote", Location::new(1, ""))
+            .with_created(Timestamp::parse("2022-10-04 10:12:01").unwrap())
+            .with_modified(Timestamp::parse("2022-10-05 08:00:00").unwrap())
+            .with_color(Color::Yellow)
+            .with_tags(["chess", "to read"]);
+        let book = Book::new("Title", "Author", [highlight]);
+
+        let markdown = render_markdown(&book);
+
+        assert!(
+            markdown.ends_with(
+                "Location 1\n\n\
+                 Added 2022-10-04 · Updated 2022-10-05 · Yellow\n\n\
+                 #chess #to-read\n\n"
+            ),
+            "{}",
+            markdown
+        );
+    }
+
     fn render_markdown(new_book: &Book) -> String {
-        let mut renderer = MarkdownRenderer::default();
+        let mut renderer = MarkdownRenderer;
         renderer.as_string(new_book)
     }
 }
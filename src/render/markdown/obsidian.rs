@@ -0,0 +1,437 @@
+//! Obsidian-flavored Markdown rendering for book highlights.
+//!
+//! Adds YAML frontmatter with the book details, renders highlights as callouts
+//! and marks every highlight with the block ID derived from its location, e.g. `^loc-157`,
+//! so other notes can link to a single highlight with `[[Book#^loc-157]]`.
+use std::collections::HashMap;
+use std::io::Write;
+
+use crate::error::HighlightError;
+use crate::highlights::{Book, Highlight, Location};
+use crate::render::markdown::writer::MarkdownWriter;
+use crate::render::markdown::{details_line, tags_line};
+use crate::render::{visit_book, BookVisitor, Render};
+
+/// Parts of the Obsidian output that can be switched off.
+#[derive(Clone, Debug)]
+pub struct ObsidianOptions {
+    /// Write YAML frontmatter with the title, authors, ASIN, highlight count and tags.
+    pub frontmatter: bool,
+    /// Render highlights as `> [!quote]` callouts instead of plain blockquotes.
+    pub callouts: bool,
+    /// Append the block ID to every highlight.
+    pub block_ids: bool,
+    /// Tags listed in the frontmatter.
+    pub tags: Vec<String>,
+}
+
+impl Default for ObsidianOptions {
+    fn default() -> Self {
+        ObsidianOptions {
+            frontmatter: true,
+            callouts: true,
+            block_ids: true,
+            tags: vec![],
+        }
+    }
+}
+
+/// Renders book highlights to Obsidian-flavored Markdown.
+#[derive(Default)]
+pub struct ObsidianRenderer {
+    options: ObsidianOptions,
+}
+
+impl ObsidianRenderer {
+    /// Creates the renderer with the options.
+    pub fn new(options: ObsidianOptions) -> Self {
+        ObsidianRenderer { options }
+    }
+}
+
+impl Render for ObsidianRenderer {
+    /// Renders highlights to Obsidian-flavored Markdown.
+    ///
+    /// ## Example:
+    ///
+    /// ```
+    /// # use std::io::stdout;
+    /// # use highlights::highlights::examples;
+    /// # use highlights::render::markdown::obsidian::{ObsidianOptions, ObsidianRenderer};
+    /// # use highlights::render::Render;
+    /// let book = examples::chess_book();
+    /// let options = ObsidianOptions {
+    ///     tags: vec!["books/chess".to_owned()],
+    ///     ..ObsidianOptions::default()
+    /// };
+    /// let mut renderer = ObsidianRenderer::new(options);
+    /// renderer.render(&book, stdout()).unwrap();
+    /// ```
+    fn render(&mut self, book: &Book, out: impl Write) -> Result<(), HighlightError> {
+        let mut writer = ObsidianWriter {
+            writer: MarkdownWriter::new(out),
+            options: &self.options,
+            block_ids: HashMap::new(),
+            callout_body: false,
+            block_id: None,
+        };
+        visit_book(book, &mut writer)
+            .map_err(|e| HighlightError::io("cannot write obsidian notes", e))
+    }
+}
+
+struct ObsidianWriter<'a, W> {
+    writer: MarkdownWriter<W>,
+    options: &'a ObsidianOptions,
+    block_ids: HashMap<usize, usize>,
+    callout_body: bool,
+    /// Block ID written after the callout of the current highlight.
+    block_id: Option<String>,
+}
+
+impl<W: Write> ObsidianWriter<'_, W> {
+    fn frontmatter(&mut self, book: &Book) -> std::io::Result<()> {
+        self.writer.text("---")?.lf()?;
+        self.writer
+            .text(&format!("title: {}", yaml_string(book.title())))?
+            .lf()?;
+        self.writer
+            .text(&format!("authors: {}", yaml_string(book.authors())))?
+            .lf()?;
+        if let Some(asin) = book.asin() {
+            self.writer
+                .text(&format!("asin: {}", yaml_string(asin)))?
+                .lf()?;
+        }
+        self.writer
+            .text(&format!("highlights: {}", book.highlights().len()))?
+            .lf()?;
+        if self.options.tags.is_empty() {
+            self.writer.text("tags: []")?.lf()?;
+        } else {
+            self.writer.text("tags:")?.lf()?;
+            for tag in &self.options.tags {
+                self.writer
+                    .text(&format!("  - {}", yaml_string(tag)))?
+                    .lf()?;
+            }
+        }
+        self.writer.text("---")?.end_block()?;
+        Ok(())
+    }
+
+    /// Separates the next part of the callout body from the previous one.
+    fn callout_part(&mut self) -> std::io::Result<()> {
+        if self.callout_body {
+            self.writer.text(">")?.lf()?;
+        }
+        self.callout_body = true;
+        Ok(())
+    }
+
+    fn callout_text(&mut self, text: &str) -> std::io::Result<()> {
+        self.callout_part()?;
+        self.writer.blockquote(text)?.lf()?;
+        Ok(())
+    }
+
+    fn location_link(&mut self, location: &Location) -> std::io::Result<()> {
+        let name = format!("Location {}", location.value());
+        if location.link().is_empty() {
+            self.writer.text(&name)?;
+        } else {
+            self.writer.link(&name, location.link())?;
+        }
+        Ok(())
+    }
+
+    /// Block ID of the location, with the ordinal suffix for repeated locations.
+    fn block_id(&mut self, location: &Location) -> String {
+        let repeats = self.block_ids.entry(location.value()).or_default();
+        *repeats += 1;
+        match *repeats {
+            1 => format!("^loc-{}", location.value()),
+            n => format!("^loc-{}-{}", location.value(), n),
+        }
+    }
+}
+
+impl<W: Write> BookVisitor for ObsidianWriter<'_, W> {
+    fn book(&mut self, book: &Book) -> std::io::Result<()> {
+        if self.options.frontmatter {
+            self.frontmatter(book)?;
+        }
+        self.writer.heading(book.title())?.end_block()?;
+        let authors = format!("by {}", book.authors());
+        self.writer.italic(&authors)?.end_block()?;
+        Ok(())
+    }
+
+    fn chapter(&mut self, title: &str) -> std::io::Result<()> {
+        self.writer.chapter(title)?.end_block()?;
+        Ok(())
+    }
+
+    fn end_chapter(&mut self) -> std::io::Result<()> {
+        self.writer.end_chapter();
+        Ok(())
+    }
+
+    fn highlight(&mut self, highlight: &Highlight) -> std::io::Result<()> {
+        self.writer.chapter_end_mark()?;
+        if self.options.callouts {
+            let kind = match highlight {
+                Highlight::Note { .. } => "note",
+                Highlight::Quote { .. } | Highlight::Comment { .. } => "quote",
+            };
+            self.writer.text(&format!("> [!{}]", kind))?.lf()?;
+            self.callout_body = false;
+        } else {
+            self.writer.line()?.lf()?;
+        }
+        Ok(())
+    }
+
+    fn quote(&mut self, quote: &str) -> std::io::Result<()> {
+        if self.options.callouts {
+            self.callout_text(quote)
+        } else {
+            self.writer.blockquote(quote)?.end_block()?;
+            Ok(())
+        }
+    }
+
+    fn note(&mut self, note: &str) -> std::io::Result<()> {
+        if self.options.callouts {
+            self.callout_text(note)
+        } else {
+            self.writer.paragraph(note)?.end_block()?;
+            Ok(())
+        }
+    }
+
+    fn location(&mut self, location: &Location) -> std::io::Result<()> {
+        let block_id = self.options.block_ids.then(|| self.block_id(location));
+        if self.options.callouts {
+            self.callout_part()?;
+            self.writer.text("> ")?;
+            self.location_link(location)?;
+            self.writer.lf()?;
+            self.block_id = block_id;
+        } else {
+            self.location_link(location)?;
+            if let Some(block_id) = block_id {
+                self.writer.text(" ")?.text(&block_id)?;
+            }
+            self.writer.end_block()?;
+        }
+        Ok(())
+    }
+
+    fn details(&mut self, highlight: &Highlight) -> std::io::Result<()> {
+        let lines = [details_line(highlight), tags_line(highlight)];
+        for line in lines.into_iter().flatten() {
+            if self.options.callouts {
+                self.callout_part()?;
+                self.writer.text("> ")?.text(&line)?.lf()?;
+            } else {
+                self.writer.text(&line)?.end_block()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn end_highlight(&mut self) -> std::io::Result<()> {
+        if self.options.callouts {
+            self.writer.lf()?;
+            // Block IDs of callouts go on a separate line after the callout.
+            if let Some(block_id) = self.block_id.take() {
+                self.writer.text(&block_id)?.end_block()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Double-quoted YAML scalar, JSON strings are valid YAML.
+fn yaml_string(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::highlights::details::Color;
+    use crate::highlights::examples;
+
+    #[test]
+    fn render_frontmatter() {
+        let options = ObsidianOptions {
+            tags: vec!["books/chess".to_owned(), "kindle".to_owned()],
+            ..ObsidianOptions::default()
+        };
+
+        let markdown = ObsidianRenderer::new(options).as_string(&examples::chess_book());
+
+        assert!(
+            markdown.starts_with(
+                "---\n\
+                 title: \"How Life Imitates Chess: Making the Right Moves, from the Board to the Boardroom\"\n\
+                 authors: \"Garry Kasparov\"\n\
+                 asin: \"B0049U443Q\"\n\
+                 highlights: 3\n\
+                 tags:\n  - \"books/chess\"\n  - \"kindle\"\n\
+                 ---\n\n# How Life"
+            ),
+            "{}",
+            markdown
+        );
+    }
+
+    #[test]
+    fn render_comment_callout() {
+        let book = Book::new(
+            "Title",
+            "Author",
+            [Highlight::comment(
+                "Quote\nsecond line\n\n*third*",
+                "Note",
+                Location::new(157, "kindle://book?action=open&location=157"),
+            )],
+        );
+
+        let markdown = ObsidianRenderer::default().as_string(&book);
+
+        assert!(
+            markdown.ends_with(
+                "*by Author*\n\n\
+                 > [!quote]\n\
+                 > Quote\\\n\
+                 > second line\n\
+                 >\n\
+                 > \\*third\\*\n\
+                 >\n\
+                 > Note\n\
+                 >\n\
+                 > [Location 157](kindle://book?action=open&location=157)\n\n\
+                 ^loc-157\n\n"
+            ),
+            "{}",
+            markdown
+        );
+    }
+
+    #[test]
+    fn render_note_callout() {
+        let book = Book::new(
+            "Title",
+            "Author",
+            [Highlight::note("Note", Location::new(3, ""))],
+        );
+
+        let markdown = ObsidianRenderer::default().as_string(&book);
+
+        assert!(
+            markdown.ends_with("> [!note]\n> Note\n>\n> Location 3\n\n^loc-3\n\n"),
+            "{}",
+            markdown
+        );
+    }
+
+    #[test]
+    fn render_details_in_callout() {
+        let book = Book::new(
+            "Title",
+            "Author",
+            [Highlight::note("Note", Location::new(3, ""))
+                .with_color(Color::Blue)
+                .with_tags(["idea"])],
+        );
+
+        let markdown = ObsidianRenderer::default().as_string(&book);
+
+        assert!(
+            markdown.ends_with(
+                "> [!note]\n> Note\n>\n> Location 3\n>\n> Blue\n>\n> #idea\n\n^loc-3\n\n"
+            ),
+            "{}",
+            markdown
+        );
+    }
+
+    #[test]
+    fn keep_block_ids_unique() {
+        let book = Book::new(
+            "Title",
+            "Author",
+            [
+                Highlight::quote("First", Location::new(1, "")),
+                Highlight::quote("Second", Location::new(1, "")),
+            ],
+        );
+
+        let markdown = ObsidianRenderer::default().as_string(&book);
+        let lines: Vec<&str> = markdown.lines().collect();
+
+        assert!(lines.contains(&"^loc-1"));
+        assert!(lines.contains(&"^loc-1-2"));
+    }
+
+    #[test]
+    fn render_without_callouts_and_frontmatter() {
+        let options = ObsidianOptions {
+            frontmatter: false,
+            callouts: false,
+            ..ObsidianOptions::default()
+        };
+        let book = Book::new(
+            "Title",
+            "Author",
+            [Highlight::quote(
+                "Quote",
+                Location::new(1, "http://book.org/1"),
+            )],
+        );
+
+        let markdown = ObsidianRenderer::new(options).as_string(&book);
+
+        assert_eq!(
+            "# Title\n\n*by Author*\n\n---\n> Quote\n\n[Location 1](http://book.org/1) ^loc-1\n\n",
+            markdown
+        );
+    }
+
+    #[test]
+    fn render_chapter_headings() {
+        let book = Book::new(
+            "Title",
+            "Author",
+            [Highlight::note(
+                "Note",
+                Location::new(3, "").with_chapter("Opening"),
+            )],
+        );
+
+        let markdown = ObsidianRenderer::default().as_string(&book);
+
+        assert!(
+            markdown.ends_with(
+                "*by Author*\n\n## Opening\n\n> [!note]\n> Note\n>\n> Location 3\n\n^loc-3\n\n"
+            ),
+            "{}",
+            markdown
+        );
+    }
+
+    #[test]
+    fn render_without_block_ids() {
+        let options = ObsidianOptions {
+            block_ids: false,
+            ..ObsidianOptions::default()
+        };
+
+        let markdown = ObsidianRenderer::new(options).as_string(&examples::chess_book());
+
+        assert!(!markdown.contains("^loc-"));
+    }
+}
@@ -0,0 +1,638 @@
+//! Incremental update of Markdown notes written earlier.
+//!
+//! Highlights already in the notes are recognised by their location lines, e.g. `[Location 157](…)`,
+//! with the quote telling apart highlights at the same location,
+//! or by Obsidian block IDs, e.g. `^loc-157`, when the location line was edited.
+//! Only new highlights are added to the notes,
+//! everything else in the file, including text written by hand, is kept as it is.
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+
+use regex::Regex;
+
+use crate::error::HighlightError;
+use crate::highlights::Book;
+
+/// Where the new highlight is added to the notes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Placement {
+    /// Before the highlight at the location already in the notes.
+    Before(usize),
+    /// After the highlight at the location already in the notes.
+    After(usize),
+    /// At the end of notes without known highlights.
+    End,
+}
+
+/// Highlight added to the notes.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Addition {
+    location: usize,
+    placement: Placement,
+    text: String,
+}
+
+/// Notes with the new highlights added.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Update {
+    content: String,
+    additions: Vec<Addition>,
+}
+
+impl Addition {
+    /// Location value of the added highlight.
+    pub fn location(&self) -> usize {
+        self.location
+    }
+
+    /// Where the highlight is added.
+    pub fn placement(&self) -> Placement {
+        self.placement
+    }
+
+    /// Rendered Markdown of the highlight.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+impl Display for Addition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Location {} ", self.location)?;
+        match self.placement {
+            Placement::Before(location) => write!(f, "before Location {}", location),
+            Placement::After(location) => write!(f, "after Location {}", location),
+            Placement::End => f.write_str("at the end"),
+        }
+    }
+}
+
+impl Update {
+    /// Content of the updated notes.
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    /// Highlights added to the notes in the order of the notes.
+    pub fn additions(&self) -> &[Addition] {
+        &self.additions
+    }
+
+    /// Checks if the notes already had all highlights.
+    pub fn is_empty(&self) -> bool {
+        self.additions.is_empty()
+    }
+}
+
+/// Highlight block of the rendered Markdown.
+struct Block<'a> {
+    /// First paragraph of the highlight text, the quote of quotes and comments.
+    quote: String,
+    /// Obsidian block ID of the highlight, if any.
+    block_id: Option<String>,
+    location: usize,
+    chapter: Option<String>,
+    /// Lines of the block starting with the chapter heading, if any.
+    lines: &'a [&'a str],
+    /// Index of the location line in the block lines.
+    key_line: usize,
+}
+
+/// Adds highlights of the book missing in the existing notes.
+///
+/// The `rendered` Markdown is the full rendering of the book, e.g. with the Markdown or Obsidian renderer.
+/// A new highlight goes before the next highlight of the book already in the notes,
+/// or after the last one when there is no next highlight, with its chapter heading when the notes miss it.
+/// Notes without known highlights get new highlights at the end.
+///
+/// Fails when the rendered output does not have a location line for every highlight,
+/// e.g. for the formats other than Markdown.
+///
+/// ## Example:
+///
+/// ```
+/// # use highlights::highlights::{Book, Highlight, Location};
+/// # use highlights::render::markdown::{render_book, update::update};
+/// let book = Book::new("Title", "Author", [Highlight::quote("Quote", Location::new(1, ""))]);
+/// let mut notes = vec![];
+/// render_book(&book, &mut notes).unwrap();
+/// let notes = String::from_utf8(notes).unwrap() + "My thoughts\n";
+///
+/// let book = Book::new(
+///     "Title",
+///     "Author",
+///     [
+///         Highlight::quote("Quote", Location::new(1, "")),
+///         Highlight::quote("New", Location::new(2, "")),
+///     ],
+/// );
+/// let mut rendered = vec![];
+/// render_book(&book, &mut rendered).unwrap();
+///
+/// let update = update(&notes, &book, &String::from_utf8(rendered).unwrap()).unwrap();
+/// assert_eq!(1, update.additions().len());
+/// assert!(update.content().ends_with("> New\n\nLocation 2\n\nMy thoughts\n"));
+/// ```
+pub fn update(existing: &str, book: &Book, rendered: &str) -> Result<Update, HighlightError> {
+    let rendered_lines: Vec<&str> = rendered.split_inclusive('\n').collect();
+    let blocks = blocks(book, &rendered_lines)?;
+    let mut lines: Vec<&str> = existing.split_inclusive('\n').collect();
+    if lines.last().is_some_and(|line| !line.ends_with('\n')) {
+        lines.push("\n");
+    }
+    let known = known_lines(&lines, &blocks);
+    let headings: HashSet<&str> = lines
+        .iter()
+        .filter(|line| line.starts_with("## "))
+        .map(|line| line.trim_end())
+        .collect();
+
+    let mut insertions: Vec<(usize, Addition)> = vec![];
+    for (index, block) in blocks.iter().enumerate() {
+        if known[index].is_some() {
+            continue;
+        }
+        let next = (index + 1..blocks.len())
+            .find_map(|next| known[next].map(|line| (&blocks[next], line)));
+        let previous = (0..index)
+            .rev()
+            .find_map(|previous| known[previous].map(|line| (&blocks[previous], line)));
+        let (position, placement) = match (next, previous) {
+            (Some((next, line)), _) => (
+                insert_before(&lines, line, next.chapter != block.chapter),
+                Placement::Before(next.location),
+            ),
+            (None, Some((previous, line))) => (
+                insert_after(&lines, line, previous),
+                Placement::After(previous.location),
+            ),
+            (None, None) => (lines.len(), Placement::End),
+        };
+        let text: String = block
+            .lines
+            .iter()
+            .enumerate()
+            .skip_while(|(line, text)| {
+                *line < block.key_line
+                    && (text.trim().is_empty() || headings.contains(text.trim_end()))
+            })
+            .map(|(_, text)| *text)
+            .collect();
+        insertions.push((
+            position,
+            Addition {
+                location: block.location,
+                placement,
+                text,
+            },
+        ));
+    }
+
+    if insertions.is_empty() {
+        return Ok(Update {
+            content: existing.to_owned(),
+            additions: vec![],
+        });
+    }
+    insertions.sort_by_key(|(position, _)| *position);
+    let mut content = String::with_capacity(existing.len());
+    let mut pending = insertions.iter().peekable();
+    for (index, line) in lines.iter().enumerate() {
+        while let Some((_, addition)) = pending.next_if(|(position, _)| *position <= index) {
+            push_block(&mut content, &addition.text);
+        }
+        content.push_str(line);
+    }
+    for (_, addition) in pending {
+        push_block(&mut content, &addition.text);
+    }
+
+    Ok(Update {
+        content,
+        additions: insertions
+            .into_iter()
+            .map(|(_, addition)| addition)
+            .collect(),
+    })
+}
+
+/// Appends the added highlight, separated by a blank line from the text before it.
+///
+/// Without the blank line the `---` separator would turn the text before it into a heading.
+fn push_block(content: &mut String, block: &str) {
+    if !content.is_empty() && !content.ends_with("\n\n") {
+        content.push('\n');
+    }
+    content.push_str(block);
+}
+
+/// Splits the rendered book into highlight blocks.
+fn blocks<'a>(book: &Book, lines: &'a [&'a str]) -> Result<Vec<Block<'a>>, HighlightError> {
+    let highlights = book.highlights();
+    let keys = highlight_keys(lines);
+    if keys.len() != highlights.len() {
+        return Err(HighlightError::General(format!(
+            "cannot update the notes, expected {} location lines in the rendered markdown, found {}",
+            highlights.len(),
+            keys.len()
+        )));
+    }
+    let block_id = block_id_regex();
+    let blocks = keys
+        .iter()
+        .zip(highlights)
+        .enumerate()
+        .map(|(index, (key, highlight))| {
+            let end = keys.get(index + 1).map_or(lines.len(), |next| next.start);
+            let lines = &lines[key.start..end];
+            Block {
+                quote: key.quote.clone(),
+                block_id: lines
+                    .iter()
+                    .find_map(|line| block_id.captures(line).map(|id| id[1].to_owned())),
+                location: highlight.location().value(),
+                chapter: highlight.location().chapter().map(str::to_owned),
+                lines,
+                key_line: key.line - key.start,
+            }
+        })
+        .collect();
+    Ok(blocks)
+}
+
+/// Lines of the location lines of the rendered highlights already in the notes.
+///
+/// Highlights are matched by location, and by the quote among highlights at the same location,
+/// so a quote that gained a note is recognised, and so is the quote with a fixed typo
+/// when it is the only unmatched highlight at its location.
+/// Obsidian block IDs stand in for edited location lines.
+fn known_lines(lines: &[&str], blocks: &[Block]) -> Vec<Option<usize>> {
+    let mut locations: HashMap<usize, Vec<HighlightKey>> = HashMap::new();
+    for key in highlight_keys(lines) {
+        if let Some(location) = key.location {
+            locations.entry(location).or_default().push(key);
+        }
+    }
+    let mut known = vec![None; blocks.len()];
+    for (index, block) in blocks.iter().enumerate() {
+        let Some(keys) = locations.get_mut(&block.location) else {
+            continue;
+        };
+        if let Some(position) = keys.iter().position(|key| key.quote == block.quote) {
+            known[index] = Some(keys.remove(position).line);
+        }
+    }
+    for (index, block) in blocks.iter().enumerate() {
+        let Some(keys) = locations.get_mut(&block.location) else {
+            continue;
+        };
+        if known[index].is_none() && !keys.is_empty() {
+            known[index] = Some(keys.remove(0).line);
+        }
+    }
+
+    let mut block_ids = block_ids(lines);
+    for (index, block) in blocks.iter().enumerate() {
+        if known[index].is_none() {
+            known[index] = block.block_id.as_ref().and_then(|id| block_ids.remove(id));
+        }
+    }
+    known
+}
+
+/// Obsidian block IDs of highlights without the location line, with the line of each.
+fn block_ids(lines: &[&str]) -> HashMap<String, usize> {
+    let location = location_regex();
+    let block_id = block_id_regex();
+    let mut block_ids = HashMap::new();
+    for (line, text) in lines.iter().enumerate() {
+        // Block IDs repeat for the repeated locations, so they only stand in for the edited location line.
+        let has_location = lines[..line]
+            .iter()
+            .rev()
+            .take_while(|line| !is_block_start(line))
+            .any(|line| location.is_match(line));
+        if has_location {
+            continue;
+        }
+        for capture in block_id.captures_iter(text) {
+            block_ids.entry(capture[1].to_owned()).or_insert(line);
+        }
+    }
+    block_ids
+}
+
+/// Location line of the highlight with the details identifying the highlight.
+struct HighlightKey {
+    /// First line of the highlight, including the chapter heading before it.
+    start: usize,
+    /// Location line.
+    line: usize,
+    /// Location value, if it fits `usize`.
+    location: Option<usize>,
+    /// First paragraph of the highlight text.
+    quote: String,
+}
+
+/// Location lines with the locations and quotes of the highlights.
+fn highlight_keys(lines: &[&str]) -> Vec<HighlightKey> {
+    let location = location_regex();
+    let mut lower = body_start(lines);
+    let mut keys = vec![];
+    for line in location_lines(lines) {
+        let start = block_start(lines, lower, line);
+        keys.push(HighlightKey {
+            start,
+            line,
+            location: location
+                .captures(lines[line])
+                .and_then(|capture| capture[1].parse().ok()),
+            quote: first_paragraph(&lines[start..line]),
+        });
+        lower = line + 1;
+    }
+    keys
+}
+
+/// First paragraph of the highlight lines without the blockquote and callout markup.
+fn first_paragraph(lines: &[&str]) -> String {
+    lines
+        .iter()
+        .filter(|line| !line.starts_with("## ") && !is_block_start(line))
+        .map(|line| line.trim().trim_start_matches('>').trim())
+        .skip_while(|text| text.is_empty())
+        .take_while(|text| !text.is_empty())
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+/// Lines with the location of the highlight, e.g. `[Location 157](…)`.
+fn location_lines(lines: &[&str]) -> Vec<usize> {
+    let location = location_regex();
+    lines
+        .iter()
+        .enumerate()
+        .filter(|(_, text)| location.is_match(text))
+        .map(|(line, _)| line)
+        .collect()
+}
+
+fn location_regex() -> Regex {
+    Regex::new(r"^(?:> )?\[?Location (\d+)(?:\]|\s|$)").unwrap()
+}
+
+fn block_id_regex() -> Regex {
+    Regex::new(r"\^(loc-\d+(?:-\d+)?)\b").unwrap()
+}
+
+/// First line after the frontmatter.
+fn body_start(lines: &[&str]) -> usize {
+    match lines.first() {
+        Some(line) if line.trim_end() == "---" => lines[1..]
+            .iter()
+            .position(|line| line.trim_end() == "---")
+            .map_or(0, |end| end + 2),
+        _ => 0,
+    }
+}
+
+/// First line of the highlight with the location line, including the chapter heading before it.
+fn block_start(lines: &[&str], lower: usize, key_line: usize) -> usize {
+    let Some(start) = (lower..key_line)
+        .rev()
+        .find(|line| is_block_start(lines[*line]))
+    else {
+        return lower.min(key_line);
+    };
+    let mut heading = start;
+    let mut line = start;
+    while line > lower && (lines[line - 1].trim().is_empty() || lines[line - 1].starts_with("## "))
+    {
+        line -= 1;
+        if lines[line].starts_with("## ") {
+            heading = line;
+        }
+    }
+    heading
+}
+
+/// Separator of Markdown highlights or the start of Obsidian callout.
+fn is_block_start(line: &str) -> bool {
+    line.trim_end() == "---" || line.starts_with("> [!")
+}
+
+/// Position before the highlight in the notes, before its chapter heading for the highlight of other chapter.
+fn insert_before(lines: &[&str], key_line: usize, other_chapter: bool) -> usize {
+    let lower = location_lines(&lines[..key_line])
+        .last()
+        .map_or(body_start(lines), |line| line + 1);
+    let Some(start) = (lower..key_line)
+        .rev()
+        .find(|line| is_block_start(lines[*line]))
+    else {
+        return key_line;
+    };
+    if !other_chapter {
+        return start;
+    }
+    let mut line = start;
+    while line > lower && lines[line - 1].trim().is_empty() {
+        line -= 1;
+    }
+    if line > lower && lines[line - 1].starts_with("## ") {
+        line - 1
+    } else {
+        start
+    }
+}
+
+/// Position after the generated lines of the highlight in the notes.
+fn insert_after(lines: &[&str], key_line: usize, block: &Block) -> usize {
+    let generated = &block.lines[block.key_line..];
+    let matching = lines[key_line..]
+        .iter()
+        .zip(generated)
+        .take_while(|(line, generated)| line == generated)
+        .count();
+    key_line + matching.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::highlights::{Highlight, Location};
+    use crate::render::markdown::obsidian::ObsidianRenderer;
+    use crate::render::markdown::MarkdownRenderer;
+    use crate::render::Render;
+
+    fn book(locations: &[usize]) -> Book {
+        let highlights: Vec<Highlight> = locations
+            .iter()
+            .map(|location| {
+                Highlight::quote(
+                    format!("Quote {}", location),
+                    Location::new(*location, format!("https://book.org/{}", location)),
+                )
+            })
+            .collect();
+        Book::new("Title", "Author", highlights)
+    }
+
+    fn markdown(book: &Book) -> String {
+        MarkdownRenderer.as_string(book)
+    }
+
+    #[test]
+    fn keep_notes_without_new_highlights() {
+        let book = book(&[1, 2]);
+        let notes = markdown(&book) + "## My thoughts\n\nChess is life\n";
+
+        let update = update(&notes, &book, &markdown(&book)).unwrap();
+
+        assert!(update.is_empty());
+        assert_eq!(notes, update.content());
+    }
+
+    #[test]
+    fn add_highlights_in_book_order() {
+        let notes = markdown(&book(&[2, 4])).replace(
+            "Location 2](https://book.org/2)\n",
+            "Location 2](https://book.org/2)\n\nMy note\n",
+        );
+        let book = book(&[1, 2, 3, 4, 5]);
+
+        let update = update(&notes, &book, &markdown(&book)).unwrap();
+
+        let placements: Vec<Placement> =
+            update.additions().iter().map(Addition::placement).collect();
+        assert_eq!(
+            vec![
+                Placement::Before(2),
+                Placement::Before(4),
+                Placement::After(4)
+            ],
+            placements
+        );
+        let content = update.content();
+        let position = |text: &str| content.find(text).unwrap();
+        assert!(position("> Quote 1") < position("> Quote 2"));
+        assert!(position("My note") < position("> Quote 3"));
+        assert!(position("> Quote 3") < position("> Quote 4"));
+        assert!(position("> Quote 4") < position("> Quote 5"));
+        assert_eq!(1, content.matches("# Title").count());
+    }
+
+    #[test]
+    fn recognise_quote_with_new_note() {
+        let old = Book::new(
+            "Title",
+            "Author",
+            [Highlight::quote("Quote", Location::new(10, ""))],
+        );
+        let new = Book::new(
+            "Title",
+            "Author",
+            [Highlight::comment("Quote", "Note", Location::new(10, ""))],
+        );
+
+        let update = update(&markdown(&old), &new, &markdown(&new)).unwrap();
+
+        assert!(update.is_empty());
+        assert_eq!(markdown(&old), update.content());
+    }
+
+    #[test]
+    fn recognise_quote_with_fixed_typo() {
+        let quote = |text: &str| Highlight::quote(text, Location::new(10, ""));
+        let old = Book::new("Title", "Author", [quote("Teh quote")]);
+        let new = Book::new("Title", "Author", [quote("The quote")]);
+
+        let update = update(&markdown(&old), &new, &markdown(&new)).unwrap();
+
+        assert!(update.is_empty());
+    }
+
+    #[test]
+    fn recognise_highlights_at_repeated_location() {
+        let quote = |text: &str| Highlight::quote(text, Location::new(5, ""));
+        let old = Book::new("Title", "Author", [quote("Later")]);
+        let new = Book::new("Title", "Author", [quote("Earlier"), quote("Later")]);
+
+        let update = update(&markdown(&old), &new, &markdown(&new)).unwrap();
+
+        assert_eq!(1, update.additions().len());
+        assert!(update.additions()[0].text().contains("> Earlier"));
+        assert_eq!(markdown(&new), update.content());
+    }
+
+    #[test]
+    fn append_before_user_sections() {
+        let notes = markdown(&book(&[1])) + "## My thoughts\n";
+        let book = book(&[1, 2]);
+
+        let update = update(&notes, &book, &markdown(&book)).unwrap();
+
+        assert!(update
+            .content()
+            .ends_with("[Location 2](https://book.org/2)\n\n## My thoughts\n"));
+    }
+
+    #[test]
+    fn add_chapter_headings() {
+        let chapter = |location: usize, chapter: &str| {
+            Highlight::quote(
+                format!("Quote {}", location),
+                Location::new(location, "").with_chapter(chapter),
+            )
+        };
+        let old = Book::new("Title", "Author", [chapter(1, "One"), chapter(3, "Two")]);
+        let new = Book::new(
+            "Title",
+            "Author",
+            [
+                chapter(1, "One"),
+                chapter(2, "One"),
+                chapter(3, "Two"),
+                chapter(4, "Three"),
+            ],
+        );
+
+        let update = update(&markdown(&old), &new, &markdown(&new)).unwrap();
+
+        assert_eq!(markdown(&new), update.content());
+    }
+
+    #[test]
+    fn recognise_obsidian_block_ids() {
+        let mut renderer = ObsidianRenderer::default();
+        let notes = renderer
+            .as_string(&book(&[1, 2]))
+            .replace("> [Location 1](https://book.org/1)\n", "> Moved link\n");
+        let book = book(&[1, 2, 3]);
+
+        let update = update(&notes, &book, &renderer.as_string(&book)).unwrap();
+
+        assert_eq!(1, update.additions().len());
+        assert_eq!(3, update.additions()[0].location());
+        assert!(update.content().ends_with(
+            "^loc-2\n\n> [!quote]\n> Quote 3\n>\n> [Location 3](https://book.org/3)\n\n^loc-3\n\n"
+        ));
+    }
+
+    #[test]
+    fn append_to_notes_without_highlights() {
+        let book = book(&[1]);
+
+        let update = update("My notes", &book, &markdown(&book)).unwrap();
+
+        assert_eq!(Placement::End, update.additions()[0].placement());
+        assert!(update.content().starts_with("My notes\n\n---\n> Quote 1\n"));
+    }
+
+    #[test]
+    fn reject_output_without_locations() {
+        let book = book(&[1]);
+
+        assert!(update("", &book, "title,highlight\n").is_err());
+    }
+}
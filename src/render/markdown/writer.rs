@@ -1,8 +1,11 @@
 use std::fmt::Arguments;
 use std::io::Write;
 
+use crate::render::markdown::{escape, CHAPTER_END};
+
 pub struct MarkdownWriter<W> {
     writer: W,
+    chapter_ended: bool,
 }
 
 impl<W> MarkdownWriter<W>
@@ -10,15 +13,45 @@ where
     W: Write,
 {
     pub fn new(writer: W) -> Self {
-        MarkdownWriter { writer }
+        MarkdownWriter {
+            writer,
+            chapter_ended: false,
+        }
     }
 
     pub fn heading(&mut self, title: &str) -> std::io::Result<&mut Self> {
-        self.write_fmt(format_args!("# {}", title))
+        self.write_fmt(format_args!("# {}", escape::escape_inline(title)))
+    }
+
+    pub fn subheading(&mut self, title: &str) -> std::io::Result<&mut Self> {
+        self.write_fmt(format_args!("## {}", escape::escape_inline(title)))
+    }
+
+    /// Writes the chapter heading, the previous chapter needs no end mark before it.
+    pub fn chapter(&mut self, title: &str) -> std::io::Result<&mut Self> {
+        self.chapter_ended = false;
+        self.subheading(title)
+    }
+
+    /// Ends the chapter, the end mark is written by [`MarkdownWriter::chapter_end_mark`].
+    pub fn end_chapter(&mut self) {
+        self.chapter_ended = true;
+    }
+
+    /// Writes the end mark of the chapter ended before the highlight outside of chapters.
+    pub fn chapter_end_mark(&mut self) -> std::io::Result<&mut Self> {
+        if std::mem::take(&mut self.chapter_ended) {
+            self.write_all(CHAPTER_END)?.end_block()?;
+        }
+        Ok(self)
     }
 
     pub fn blockquote(&mut self, quote: &str) -> std::io::Result<&mut Self> {
-        self.write_fmt(format_args!("> {}", quote))
+        self.write_all(&escape::blockquote(quote))
+    }
+
+    pub fn paragraph(&mut self, text: &str) -> std::io::Result<&mut Self> {
+        self.write_all(&escape::paragraph(text))
     }
 
     pub fn text(&mut self, text: &str) -> std::io::Result<&mut Self> {
@@ -26,18 +59,21 @@ where
     }
 
     pub fn italic(&mut self, text: &str) -> std::io::Result<&mut Self> {
-        self.write_fmt(format_args!("*{}*", text))
+        self.write_fmt(format_args!("*{}*", escape::escape_inline(text)))
     }
 
     pub fn link(&mut self, title: &str, url: &str) -> std::io::Result<&mut Self> {
-        self.write_fmt(format_args!("[{}]({})", title, url))
+        self.write_fmt(format_args!(
+            "[{}]({})",
+            escape::escape_inline(title),
+            escape::destination(url)
+        ))
     }
 
     pub fn line(&mut self) -> std::io::Result<&mut Self> {
         self.write_all("---")
     }
 
-    #[allow(dead_code)]
     pub fn lf(&mut self) -> std::io::Result<&mut Self> {
         self.write_all("\n")
     }
@@ -85,6 +121,40 @@ mod tests {
         assert_eq!(markdown, "> This is rather nice quote I want to highlight");
     }
 
+    #[test]
+    fn render_multiline_blockquote() {
+        let mut buf = BufWriter::new(Vec::new());
+        let mut md = MarkdownWriter::new(&mut buf);
+
+        md.blockquote("First paragraph\n\n*Second* paragraph")
+            .unwrap();
+
+        let markdown = stringify(buf);
+        assert_eq!(markdown, "> First paragraph\n>\n> \\*Second\\* paragraph");
+    }
+
+    #[test]
+    fn render_escaped_paragraph() {
+        let mut buf = BufWriter::new(Vec::new());
+        let mut md = MarkdownWriter::new(&mut buf);
+
+        md.paragraph("# Not a heading\nbut [a] note").unwrap();
+
+        let markdown = stringify(buf);
+        assert_eq!(markdown, "\\# Not a heading\\\nbut \\[a\\] note");
+    }
+
+    #[test]
+    fn render_escaped_link() {
+        let mut buf = BufWriter::new(Vec::new());
+        let mut md = MarkdownWriter::new(&mut buf);
+
+        md.link("[1]", "http://book.org/a (1)").unwrap();
+
+        let markdown = stringify(buf);
+        assert_eq!(markdown, "[\\[1\\]](<http://book.org/a (1)>)");
+    }
+
     #[test]
     fn render_text() {
         let mut buf = BufWriter::new(Vec::new());
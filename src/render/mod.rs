@@ -2,15 +2,35 @@
 use std::io::{BufWriter, Write};
 
 use crate::error::HighlightError;
-use crate::highlights::Book;
+use crate::highlights::{Book, Highlight, Location};
 
+pub mod anki;
+pub mod html;
+mod id;
+pub mod json;
 pub mod markdown;
+pub mod org;
+pub mod readwise;
+pub mod registry;
+pub mod template;
 
 /// Render format to export book highlights.
 pub trait Render {
     /// Render book into specified output.
     fn render(&mut self, book: &Book, out: impl Write) -> Result<(), HighlightError>;
 
+    /// Render several books into one output.
+    ///
+    /// Books are rendered one after another by default.
+    /// Formats that cannot be concatenated, like CSV with the header row or whole documents,
+    /// render all books into one document instead.
+    fn render_all(&mut self, books: &[Book], mut out: impl Write) -> Result<(), HighlightError> {
+        for book in books {
+            self.render(book, &mut out)?;
+        }
+        Ok(())
+    }
+
     /// Renders book highlights into the string.
     ///
     /// This is a convenience method to simplify testing new renderers.
@@ -21,3 +41,143 @@ pub trait Render {
         String::from_utf8(bytes).unwrap()
     }
 }
+
+/// Receives parts of the book in the rendering order.
+///
+/// Renderers implement the visitor and let [`visit_book`] walk the book,
+/// so every highlight type renders consistently across formats.
+pub trait BookVisitor {
+    /// Book title and authors before any highlight.
+    fn book(&mut self, book: &Book) -> std::io::Result<()>;
+
+    /// Start of the chapter, before its first highlight.
+    ///
+    /// Books without chapter data never start a chapter, so their highlights stay flat.
+    fn chapter(&mut self, _title: &str) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    /// End of the chapter, after its last highlight.
+    fn end_chapter(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    /// Start of the highlight, before its quote or note.
+    fn highlight(&mut self, highlight: &Highlight) -> std::io::Result<()>;
+
+    /// Quote from the book text.
+    fn quote(&mut self, quote: &str) -> std::io::Result<()>;
+
+    /// Reader note.
+    fn note(&mut self, note: &str) -> std::io::Result<()>;
+
+    /// Location of the highlight, after its quote and note.
+    fn location(&mut self, location: &Location) -> std::io::Result<()>;
+
+    /// Creation time, color and tags of the highlight, after its location.
+    fn details(&mut self, _highlight: &Highlight) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    /// End of the highlight.
+    fn end_highlight(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    /// End of the book after all highlights.
+    fn end_book(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Walks the book passing its parts to the visitor.
+///
+/// Comments are visited as the quote followed by the note.
+/// Highlights ordered by location are grouped by chapter: a chapter starts whenever the chapter
+/// of the highlight differs from the previous one and ends before the next chapter,
+/// before the highlight without chapter or at the end of the book.
+/// Highlights in other orders are visited without chapters, the chapters would repeat.
+pub fn visit_book(book: &Book, visitor: &mut impl BookVisitor) -> std::io::Result<()> {
+    visitor.book(book)?;
+    let highlights = book.highlights();
+    let grouped = is_location_order(&highlights);
+    let mut chapter: Option<String> = None;
+    for highlight in &highlights {
+        let location = highlight.location();
+        let title = location.chapter().filter(|_| grouped);
+        if chapter.as_deref() != title {
+            if chapter.is_some() {
+                visitor.end_chapter()?;
+            }
+            if let Some(title) = title {
+                visitor.chapter(title)?;
+            }
+            chapter = title.map(str::to_owned);
+        }
+        visitor.highlight(highlight)?;
+        match highlight {
+            Highlight::Quote { quote, .. } => visitor.quote(quote)?,
+            Highlight::Note { note, .. } => visitor.note(note)?,
+            Highlight::Comment { quote, note, .. } => {
+                visitor.quote(quote)?;
+                visitor.note(note)?;
+            }
+        }
+        visitor.location(&location)?;
+        visitor.details(highlight)?;
+        visitor.end_highlight()?;
+    }
+    if chapter.is_some() {
+        visitor.end_chapter()?;
+    }
+    visitor.end_book()
+}
+
+/// Checks if the highlights go by location, forwards or backwards.
+pub(crate) fn is_location_order(highlights: &[Highlight]) -> bool {
+    let values: Vec<usize> = highlights
+        .iter()
+        .map(|highlight| highlight.location().value())
+        .collect();
+    values.windows(2).all(|pair| pair[0] <= pair[1])
+        || values.windows(2).all(|pair| pair[0] >= pair[1])
+}
+
+/// Object-safe rendering interface.
+///
+/// Every [`Render`] implementation is also a `DynRender`,
+/// so renderers can be selected at runtime and kept as `Box<dyn DynRender>`.
+pub trait DynRender {
+    /// Render book into the output trait object.
+    fn render_dyn(&mut self, book: &Book, out: &mut dyn Write) -> Result<(), HighlightError>;
+
+    /// Render several books into the output trait object.
+    fn render_all_dyn(&mut self, books: &[Book], out: &mut dyn Write)
+        -> Result<(), HighlightError>;
+}
+
+impl<R: Render> DynRender for R {
+    fn render_dyn(&mut self, book: &Book, out: &mut dyn Write) -> Result<(), HighlightError> {
+        self.render(book, out)
+    }
+
+    fn render_all_dyn(
+        &mut self,
+        books: &[Book],
+        out: &mut dyn Write,
+    ) -> Result<(), HighlightError> {
+        self.render_all(books, out)
+    }
+}
+
+impl Render for Box<dyn DynRender> {
+    /// Renders the book with the boxed renderer.
+    fn render(&mut self, book: &Book, mut out: impl Write) -> Result<(), HighlightError> {
+        self.as_mut().render_dyn(book, &mut out)
+    }
+
+    /// Renders the books with the boxed renderer.
+    fn render_all(&mut self, books: &[Book], mut out: impl Write) -> Result<(), HighlightError> {
+        self.as_mut().render_all_dyn(books, &mut out)
+    }
+}
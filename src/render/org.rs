@@ -0,0 +1,368 @@
+//! Emacs Org-mode rendering for book highlights.
+//!
+//! Every highlight becomes a heading with the `:ID:` property,
+//! so org-roam can reference individual highlights.
+//! Highlights of books with chapters are nested under the chapter headings.
+//! Quotes are kept in `#+BEGIN_QUOTE` blocks, where lines looking like Org syntax
+//! are escaped with a leading comma, and notes are plain paragraphs,
+//! where such lines start with a zero width space.
+use std::io::Write;
+
+use crate::error::HighlightError;
+use crate::highlights::{Book, Highlight, Location};
+use crate::render::id::highlight_id;
+use crate::render::{visit_book, BookVisitor, Render};
+
+const HEADING_WORDS: usize = 8;
+
+/// Renders book highlights to Org-mode format.
+pub struct OrgRenderer;
+
+impl Render for OrgRenderer {
+    /// Renders highlights to Org-mode format.
+    ///
+    /// ## Example:
+    ///
+    /// ```
+    /// # use std::io::stdout;
+    /// # use highlights::highlights::examples;
+    /// # use highlights::render::org::OrgRenderer;
+    /// # use highlights::render::Render;
+    /// let book = examples::chess_book();
+    /// let mut renderer = OrgRenderer;
+    /// renderer.render(&book, stdout()).unwrap();
+    /// ```
+    fn render(&mut self, book: &Book, out: impl Write) -> Result<(), HighlightError> {
+        self.render_all(std::slice::from_ref(book), out)
+    }
+
+    /// Renders several books into one document with a top-level heading per book.
+    fn render_all(&mut self, books: &[Book], mut out: impl Write) -> Result<(), HighlightError> {
+        let nested = books.len() > 1;
+        let mut write = || -> std::io::Result<()> {
+            if nested {
+                writeln!(out, "#+TITLE: Highlights")?;
+            }
+            for book in books {
+                let mut writer = OrgWriter {
+                    writer: &mut out,
+                    book,
+                    nested,
+                    level: 1,
+                };
+                visit_book(book, &mut writer)?;
+            }
+            Ok(())
+        };
+        write().map_err(|e| HighlightError::io("cannot write org notes", e))
+    }
+}
+
+impl Default for OrgRenderer {
+    fn default() -> Self {
+        OrgRenderer
+    }
+}
+
+struct OrgWriter<'a, W> {
+    writer: W,
+    book: &'a Book,
+    /// Book is a heading of the document with several books.
+    nested: bool,
+    /// Heading level of the highlights.
+    level: usize,
+}
+
+impl<W: Write> OrgWriter<'_, W> {
+    /// Heading level of the chapters.
+    fn chapter_level(&self) -> usize {
+        if self.nested {
+            2
+        } else {
+            1
+        }
+    }
+}
+
+impl<W: Write> BookVisitor for OrgWriter<'_, W> {
+    fn book(&mut self, book: &Book) -> std::io::Result<()> {
+        self.level = self.chapter_level();
+        if self.nested {
+            writeln!(self.writer)?;
+            writeln!(self.writer, "* {}", single_line(book.title()))?;
+            writeln!(self.writer, ":PROPERTIES:")?;
+            writeln!(self.writer, ":AUTHOR: {}", single_line(book.authors()))?;
+            return writeln!(self.writer, ":END:");
+        }
+        writeln!(self.writer, "#+TITLE: {}", single_line(book.title()))?;
+        writeln!(self.writer, "#+AUTHOR: {}", single_line(book.authors()))
+    }
+
+    fn chapter(&mut self, title: &str) -> std::io::Result<()> {
+        self.level = self.chapter_level() + 1;
+        writeln!(self.writer)?;
+        writeln!(
+            self.writer,
+            "{} {}",
+            "*".repeat(self.chapter_level()),
+            single_line(title)
+        )
+    }
+
+    fn end_chapter(&mut self) -> std::io::Result<()> {
+        self.level = self.chapter_level();
+        Ok(())
+    }
+
+    fn highlight(&mut self, highlight: &Highlight) -> std::io::Result<()> {
+        writeln!(self.writer)?;
+        writeln!(
+            self.writer,
+            "{} {}",
+            "*".repeat(self.level),
+            heading(highlight)
+        )?;
+        writeln!(self.writer, ":PROPERTIES:")?;
+        writeln!(self.writer, ":ID: {}", highlight_id(self.book, highlight))?;
+        writeln!(self.writer, ":END:")
+    }
+
+    fn quote(&mut self, quote: &str) -> std::io::Result<()> {
+        writeln!(self.writer, "#+BEGIN_QUOTE")?;
+        for line in quote.trim().lines() {
+            writeln!(self.writer, "{}", escape_block_line(line))?;
+        }
+        writeln!(self.writer, "#+END_QUOTE")
+    }
+
+    fn note(&mut self, note: &str) -> std::io::Result<()> {
+        writeln!(self.writer)?;
+        for line in note.trim().lines() {
+            writeln!(self.writer, "{}", escape_paragraph_line(line))?;
+        }
+        Ok(())
+    }
+
+    fn location(&mut self, location: &Location) -> std::io::Result<()> {
+        writeln!(self.writer)?;
+        let name = format!("Location {}", location.value());
+        if location.link().is_empty() {
+            writeln!(self.writer, "{}", name)
+        } else {
+            writeln!(
+                self.writer,
+                "[[{}][{}]]",
+                escape_link(location.link()),
+                name
+            )
+        }
+    }
+}
+
+/// Heading with the first words of the highlight text.
+fn heading(highlight: &Highlight) -> String {
+    let text = match highlight {
+        Highlight::Quote { quote, .. } | Highlight::Comment { quote, .. } => quote,
+        Highlight::Note { note, .. } => note,
+    };
+    let words: Vec<&str> = text.split_whitespace().collect();
+    if words.is_empty() {
+        format!("Location {}", highlight.location().value())
+    } else if words.len() > HEADING_WORDS {
+        format!("{}…", words[..HEADING_WORDS].join(" "))
+    } else {
+        words.join(" ")
+    }
+}
+
+fn single_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// Lines inside blocks starting with `*` or `#+` are escaped with a comma.
+fn escape_block_line(line: &str) -> String {
+    let unescaped = line.trim_start_matches(',');
+    if unescaped.starts_with('*') || unescaped.starts_with("#+") {
+        format!(",{}", line)
+    } else {
+        line.to_owned()
+    }
+}
+
+/// Lines of paragraphs starting with `*` or `#+` are escaped with a zero width space,
+/// since a comma is shown as is outside blocks.
+fn escape_paragraph_line(line: &str) -> String {
+    let trimmed = line.trim_start();
+    if trimmed.starts_with('*') || trimmed.starts_with("#+") {
+        format!("\u{200B}{}", line)
+    } else {
+        line.to_owned()
+    }
+}
+
+fn escape_link(link: &str) -> String {
+    link.replace('[', "%5B").replace(']', "%5D")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_keywords() {
+        let org = OrgRenderer.as_string(&Book::new("Title", "Author", []));
+
+        assert_eq!("#+TITLE: Title\n#+AUTHOR: Author\n", org);
+    }
+
+    #[test]
+    fn render_comment() {
+        let book = Book::new(
+            "Title",
+            "Author",
+            [Highlight::comment(
+                "Quote",
+                "Note",
+                Location::new(157, "kindle://book?action=open&location=157"),
+            )],
+        );
+        let id = highlight_id(&book, &book.highlights()[0]);
+
+        let org = OrgRenderer.as_string(&book);
+
+        assert_eq!(
+            format!(
+                "#+TITLE: Title\n#+AUTHOR: Author\n\n\
+                 * Quote\n:PROPERTIES:\n:ID: {}\n:END:\n\
+                 #+BEGIN_QUOTE\nQuote\n#+END_QUOTE\n\n\
+                 Note\n\n\
+                 [[kindle://book?action=open&location=157][Location 157]]\n",
+                id
+            ),
+            org
+        );
+    }
+
+    #[test]
+    fn render_note_without_link() {
+        let book = Book::new(
+            "Title",
+            "Author",
+            [Highlight::note("Note", Location::new(3, ""))],
+        );
+
+        let org = OrgRenderer.as_string(&book);
+        let lines: Vec<&str> = org.lines().collect();
+
+        assert!(lines.contains(&"* Note"));
+        assert!(!lines.contains(&"#+BEGIN_QUOTE"));
+        assert!(lines.contains(&"Location 3"));
+    }
+
+    #[test]
+    fn nest_highlights_under_chapters() {
+        let book = Book::new(
+            "Title",
+            "Author",
+            [
+                Highlight::note("First", Location::new(1, "").with_chapter("One")),
+                Highlight::note("Second", Location::new(2, "").with_chapter("Two")),
+            ],
+        );
+
+        let org = OrgRenderer.as_string(&book);
+        let headings: Vec<&str> = org.lines().filter(|line| line.starts_with('*')).collect();
+
+        assert_eq!(vec!["* One", "** First", "* Two", "** Second"], headings);
+    }
+
+    #[test]
+    fn close_chapter_before_highlight_without_chapter() {
+        let book = Book::new(
+            "Title",
+            "Author",
+            [
+                Highlight::note("First", Location::new(1, "").with_chapter("One")),
+                Highlight::note("Second", Location::new(2, "")),
+            ],
+        );
+
+        let org = OrgRenderer.as_string(&book);
+        let headings: Vec<&str> = org.lines().filter(|line| line.starts_with('*')).collect();
+
+        assert_eq!(vec!["* One", "** First", "* Second"], headings);
+    }
+
+    #[test]
+    fn nest_several_books_under_headings() {
+        let books = [
+            Book::new(
+                "First book",
+                "Author",
+                [Highlight::note(
+                    "First",
+                    Location::new(1, "").with_chapter("One"),
+                )],
+            ),
+            Book::new(
+                "Second book",
+                "Author",
+                [Highlight::note("Second", Location::new(2, ""))],
+            ),
+        ];
+        let mut org = vec![];
+
+        OrgRenderer.render_all(&books, &mut org).unwrap();
+
+        let org = String::from_utf8(org).unwrap();
+        let headings: Vec<&str> = org.lines().filter(|line| line.starts_with('*')).collect();
+        assert_eq!(1, org.matches("#+TITLE:").count());
+        assert_eq!(
+            vec![
+                "* First book",
+                "** One",
+                "*** First",
+                "* Second book",
+                "** Second"
+            ],
+            headings
+        );
+    }
+
+    #[test]
+    fn shorten_heading() {
+        let highlight = Highlight::quote(
+            "the reality is that we discard our decisions almost as soon as we make them",
+            Location::new(1, ""),
+        );
+
+        assert_eq!(
+            "the reality is that we discard our decisions…",
+            heading(&highlight)
+        );
+    }
+
+    #[test]
+    fn escape_org_syntax() {
+        let book = Book::new(
+            "Title",
+            "Author",
+            [Highlight::comment(
+                "* not a heading\n#+END_QUOTE",
+                "* not a heading either\n#+TITLE: nor a title\n\nsecond paragraph",
+                Location::new(1, "http://book.org/[1]"),
+            )],
+        );
+
+        let org = OrgRenderer.as_string(&book);
+        let lines: Vec<&str> = org.lines().collect();
+
+        assert!(lines.contains(&",* not a heading"));
+        assert!(lines.contains(&",#+END_QUOTE"));
+        assert!(lines.contains(&"\u{200B}* not a heading either"));
+        assert!(lines.contains(&"\u{200B}#+TITLE: nor a title"));
+        assert!(lines.contains(&"second paragraph"));
+        assert!(!org.contains("#+BEGIN_NOTE"));
+        assert!(lines.contains(&"[[http://book.org/%5B1%5D][Location 1]]"));
+    }
+}
@@ -0,0 +1,208 @@
+//! Readwise bulk-import CSV rendering for book highlights.
+use std::io::Write;
+
+use serde::Serialize;
+
+use crate::error::HighlightError;
+use crate::highlights::{Book, Highlight};
+use crate::render::Render;
+
+#[derive(Serialize)]
+struct ImportRow<'a> {
+    #[serde(rename = "Highlight")]
+    highlight: &'a str,
+    #[serde(rename = "Title")]
+    title: &'a str,
+    #[serde(rename = "Author")]
+    author: &'a str,
+    #[serde(rename = "URL")]
+    url: &'a str,
+    #[serde(rename = "Note")]
+    note: String,
+    #[serde(rename = "Location")]
+    location: usize,
+    #[serde(rename = "Date")]
+    date: String,
+}
+
+/// Renders book highlights to the Readwise bulk-import CSV format.
+///
+/// Readwise requires the highlight text on every row,
+/// so notes without a quote are exported as highlights.
+/// Tags are written as `.tag` words at the start of the note, the way Readwise reads them.
+pub struct ReadwiseRenderer;
+
+impl Render for ReadwiseRenderer {
+    /// Renders highlights as CSV rows.
+    ///
+    /// ## Example:
+    ///
+    /// ```
+    /// # use std::io::stdout;
+    /// # use highlights::highlights::examples;
+    /// # use highlights::render::readwise::ReadwiseRenderer;
+    /// # use highlights::render::Render;
+    /// let book = examples::chess_book();
+    /// let mut renderer = ReadwiseRenderer;
+    /// renderer.render(&book, stdout()).unwrap();
+    /// ```
+    fn render(&mut self, book: &Book, out: impl Write) -> Result<(), HighlightError> {
+        self.render_all(std::slice::from_ref(book), out)
+    }
+
+    /// Renders highlights of all books under one header row.
+    fn render_all(&mut self, books: &[Book], out: impl Write) -> Result<(), HighlightError> {
+        let mut writer = csv::Writer::from_writer(out);
+        for book in books {
+            write_rows(&mut writer, book).map_err(|e| {
+                HighlightError::io("cannot write readwise csv", std::io::Error::from(e))
+            })?;
+        }
+        writer
+            .flush()
+            .map_err(|e| HighlightError::io("cannot write readwise csv", e))
+    }
+}
+
+fn write_rows(writer: &mut csv::Writer<impl Write>, book: &Book) -> csv::Result<()> {
+    for highlight in book.highlights() {
+        let location = highlight.location();
+        let (quote, note) = match &highlight {
+            Highlight::Quote { quote, .. } => (quote.as_str(), ""),
+            Highlight::Note { note, .. } => (note.as_str(), ""),
+            Highlight::Comment { quote, note, .. } => (quote.as_str(), note.as_str()),
+        };
+        let tags = highlight
+            .tags()
+            .iter()
+            .map(|tag| format!(".{}", tag_word(tag)));
+        let note = tags
+            .chain((!note.is_empty()).then(|| note.to_owned()))
+            .collect::<Vec<String>>()
+            .join(" ");
+        let date = highlight
+            .created()
+            .map(|created| created.to_string().replace('T', " "))
+            .unwrap_or_default();
+        writer.serialize(ImportRow {
+            highlight: quote,
+            title: book.title(),
+            author: book.authors(),
+            url: location.link(),
+            note,
+            location: location.value(),
+            date,
+        })?;
+    }
+    Ok(())
+}
+
+/// Tags with spaces would end at the first space, so spaces become dashes.
+fn tag_word(tag: &str) -> String {
+    tag.split_whitespace().collect::<Vec<&str>>().join("-")
+}
+
+impl Default for ReadwiseRenderer {
+    fn default() -> Self {
+        ReadwiseRenderer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::highlights::details::Timestamp;
+    use crate::highlights::{examples, Location};
+    use crate::input::readwise::ReadwiseCsv;
+    use crate::input::HighlightsRead;
+
+    #[test]
+    fn render_header() {
+        let csv = ReadwiseRenderer.as_string(&examples::chess_book());
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!("Highlight,Title,Author,URL,Note,Location,Date", lines[0]);
+        assert_eq!(4, lines.len());
+    }
+
+    #[test]
+    fn render_comment() {
+        let book = Book::new(
+            "Title, with comma",
+            "Author",
+            [Highlight::comment(
+                "Quote",
+                "Note",
+                Location::new(1, "http://book.org/comments/1"),
+            )],
+        );
+
+        let csv = ReadwiseRenderer.as_string(&book);
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(
+            "Quote,\"Title, with comma\",Author,http://book.org/comments/1,Note,1,",
+            lines[1]
+        );
+    }
+
+    #[test]
+    fn render_date_and_tags() {
+        let book = Book::new(
+            "Title",
+            "Author",
+            [Highlight::comment("Quote", "Note", Location::new(1, ""))
+                .with_created(Timestamp::parse("2022-10-04 10:12:01").unwrap())
+                .with_tags(["chess", "to read"])],
+        );
+
+        let csv = ReadwiseRenderer.as_string(&book);
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(
+            "Quote,Title,Author,,.chess .to-read Note,1,2022-10-04 10:12:01",
+            lines[1]
+        );
+        let books: Vec<Book> = ReadwiseCsv::from_reader(csv.as_bytes()).unwrap().into();
+        assert_eq!(["chess", "to-read"], books[0].highlights()[0].tags());
+    }
+
+    #[test]
+    fn render_header_once_for_several_books() {
+        let books = [
+            examples::chess_book(),
+            Book::new(
+                "Other",
+                "Author",
+                [Highlight::quote("Quote", Location::new(1, ""))],
+            ),
+        ];
+        let mut csv = vec![];
+
+        ReadwiseRenderer.render_all(&books, &mut csv).unwrap();
+
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(1, csv.matches("Highlight,Title").count());
+        let books: Vec<Book> = ReadwiseCsv::from_reader(csv.as_bytes()).unwrap().into();
+        assert_eq!(2, books.len());
+    }
+
+    #[test]
+    fn round_trip_through_reader() {
+        let book = examples::chess_book();
+        let csv = ReadwiseRenderer.as_string(&book);
+
+        let books: Vec<Book> = ReadwiseCsv::from_reader(csv.as_bytes()).unwrap().into();
+
+        assert_eq!(1, books.len());
+        assert_eq!(book.title(), books[0].title());
+        assert_eq!(book.authors(), books[0].authors());
+        let highlights = books[0].highlights();
+        assert_eq!(3, highlights.len());
+        assert_eq!(294, highlights[1].location().value());
+        assert_eq!(
+            "kindle://book?action=open&asin=B0049U443Q&location=295",
+            highlights[2].location().link()
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! Registry of output formats.
+use std::path::Path;
+
+use crate::error::HighlightError;
+use crate::render::anki::AnkiRenderer;
+use crate::render::html::HtmlRenderer;
+use crate::render::json::JsonRenderer;
+use crate::render::markdown::obsidian::ObsidianRenderer;
+use crate::render::markdown::MarkdownRenderer;
+use crate::render::org::OrgRenderer;
+use crate::render::readwise::ReadwiseRenderer;
+use crate::render::DynRender;
+
+/// Format rendered when neither the format name nor the output extension selects one.
+pub const DEFAULT_FORMAT: &str = "markdown";
+
+type Factory = Box<dyn Fn() -> Box<dyn DynRender>>;
+
+struct Entry {
+    name: String,
+    extensions: Vec<String>,
+    factory: Factory,
+}
+
+/// Maps output format names and file extensions to renderers.
+///
+/// ## Example
+///
+/// ```
+/// # use highlights::highlights::examples;
+/// # use highlights::render::registry::Registry;
+/// # use highlights::render::Render;
+/// let registry = Registry::default();
+/// let mut renderer = registry.renderer("markdown").unwrap();
+/// let markdown = renderer.as_string(&examples::chess_book());
+/// ```
+pub struct Registry {
+    entries: Vec<Entry>,
+}
+
+impl Registry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Registry { entries: vec![] }
+    }
+
+    /// Registers the renderer factory under the format name and output file extensions.
+    ///
+    /// Registering the same name again replaces the previous renderer.
+    pub fn register<F>(&mut self, name: &str, extensions: &[&str], factory: F)
+    where
+        F: Fn() -> Box<dyn DynRender> + 'static,
+    {
+        self.entries.retain(|entry| entry.name != name);
+        self.entries.push(Entry {
+            name: name.to_owned(),
+            extensions: extensions.iter().map(|e| e.to_lowercase()).collect(),
+            factory: Box::new(factory),
+        });
+    }
+
+    /// Names of the registered formats.
+    pub fn names(&self) -> Vec<&str> {
+        self.entries
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect()
+    }
+
+    /// Main output file extension of the format, if it has one.
+    pub fn extension(&self, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|entry| entry.name == name)
+            .and_then(|entry| entry.extensions.first())
+            .map(String::as_str)
+    }
+
+    /// Creates the renderer for the format name.
+    pub fn renderer(&self, name: &str) -> Option<Box<dyn DynRender>> {
+        self.entries
+            .iter()
+            .find(|entry| entry.name == name)
+            .map(|entry| (entry.factory)())
+    }
+
+    /// Creates the renderer matching the extension of the output file.
+    pub fn renderer_for_path(&self, path: &Path) -> Option<Box<dyn DynRender>> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        self.entries
+            .iter()
+            .find(|entry| entry.extensions.contains(&extension))
+            .map(|entry| (entry.factory)())
+    }
+
+    /// Selects the renderer by the format name, the output file extension or the default format in that order.
+    ///
+    /// Fails when the format name is not registered.
+    pub fn select(
+        &self,
+        name: Option<&str>,
+        output: Option<&Path>,
+    ) -> Result<Box<dyn DynRender>, HighlightError> {
+        if let Some(name) = name {
+            return self.renderer(name).ok_or_else(|| {
+                HighlightError::General(format!(
+                    "unknown output format '{}', supported formats: {}",
+                    name,
+                    self.names().join(", ")
+                ))
+            });
+        }
+        output
+            .and_then(|path| self.renderer_for_path(path))
+            .or_else(|| self.renderer(DEFAULT_FORMAT))
+            .ok_or_else(|| HighlightError::General("no output format registered".to_owned()))
+    }
+}
+
+impl Default for Registry {
+    /// Registry with all built-in formats.
+    fn default() -> Self {
+        let mut registry = Registry::new();
+        registry.register("markdown", &["md", "markdown"], || {
+            Box::new(MarkdownRenderer)
+        });
+        registry.register("readwise", &["csv"], || Box::new(ReadwiseRenderer));
+        registry.register("html", &["html", "htm"], || {
+            Box::new(HtmlRenderer::default())
+        });
+        registry.register("json", &["json"], || Box::new(JsonRenderer));
+        registry.register("org", &["org"], || Box::new(OrgRenderer));
+        registry.register("anki", &["apkg"], || Box::new(AnkiRenderer::default()));
+        registry.register("obsidian", &[], || Box::new(ObsidianRenderer::default()));
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::highlights::examples;
+    use crate::render::Render;
+
+    #[test]
+    fn main_extension() {
+        let registry = Registry::default();
+
+        assert_eq!(Some("md"), registry.extension("markdown"));
+        assert_eq!(None, registry.extension("obsidian"));
+        assert_eq!(None, registry.extension("pdf"));
+    }
+
+    #[test]
+    fn select_by_name() {
+        let registry = Registry::default();
+
+        let mut renderer = registry.select(Some("readwise"), None).unwrap();
+
+        let csv = renderer.as_string(&examples::chess_book());
+        assert!(csv.starts_with("Highlight,Title,Author"));
+    }
+
+    #[test]
+    fn select_by_extension() {
+        let registry = Registry::default();
+
+        let mut renderer = registry.select(None, Some(Path::new("notes.CSV"))).unwrap();
+
+        let csv = renderer.as_string(&examples::chess_book());
+        assert!(csv.starts_with("Highlight,Title,Author"));
+    }
+
+    #[test]
+    fn prefer_name_over_extension() {
+        let registry = Registry::default();
+
+        let mut renderer = registry
+            .select(Some("markdown"), Some(Path::new("notes.csv")))
+            .unwrap();
+
+        let markdown = renderer.as_string(&examples::chess_book());
+        assert!(markdown.starts_with("# How Life Imitates Chess"));
+    }
+
+    #[test]
+    fn fall_back_to_default_format() {
+        let registry = Registry::default();
+
+        let mut renderer = registry.select(None, Some(Path::new("notes.txt"))).unwrap();
+
+        let markdown = renderer.as_string(&examples::chess_book());
+        assert!(markdown.starts_with("# How Life Imitates Chess"));
+    }
+
+    #[test]
+    fn reject_unknown_format() {
+        let registry = Registry::default();
+
+        let error = registry.select(Some("pdf"), None).err().unwrap();
+
+        assert!(
+            error
+                .to_string()
+                .contains("markdown, readwise, html, json, org, anki, obsidian"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn register_custom_format() {
+        let mut registry = Registry::new();
+        registry.register("plain", &["txt"], || Box::new(MarkdownRenderer));
+
+        assert_eq!(vec!["plain"], registry.names());
+        assert!(registry.renderer_for_path(Path::new("notes.txt")).is_some());
+    }
+}
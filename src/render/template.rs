@@ -0,0 +1,377 @@
+//! Rendering of book highlights with user-defined templates.
+//!
+//! Templates use the [minijinja](https://docs.rs/minijinja) syntax and receive the book as the context:
+//!
+//! - `title`, `authors` and optional `asin` of the book;
+//! - `highlights` list where every highlight has `kind` (`quote`, `note` or `comment`),
+//!   optional `quote` and `note`, and the `location` with `value`, `link` and optional `chapter`;
+//!   the first highlight of every chapter also has the `chapter` title to start the chapter with,
+//!   and the first highlight without a chapter after one has the `end_of_chapter` flag;
+//! - optional `created` and `modified` timestamps formatted as `YYYY-MM-DDTHH:MM:SS`,
+//!   optional `color` name and the `tags` list of every highlight.
+//!
+//! Markdown templates escape user text with the filters:
+//!
+//! - `markdown` escapes the single line of text;
+//! - `paragraph` escapes the text keeping paragraphs and line breaks;
+//! - `blockquote` renders the escaped text as the blockquote;
+//! - `destination` formats the link destination;
+//! - `tag` formats the tag as the `#tag` word.
+//!
+//! Block tags remove the newline following them, so templates can keep one tag per line.
+//! Templates with the `.html` extension escape the values.
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+
+use minijinja::syntax::SyntaxConfig;
+use minijinja::value::Serde;
+use minijinja::Environment;
+use serde::Serialize;
+
+use crate::error::HighlightError;
+use crate::highlights::details::{Color, Timestamp};
+use crate::highlights::{Book, Highlight, Location};
+use crate::render::markdown::escape;
+use crate::render::{is_location_order, Render};
+
+/// Template reproducing the output of the [`crate::render::markdown::MarkdownRenderer`].
+pub const DEFAULT_TEMPLATE: &str = "\
+# {{ title | markdown }}
+
+*by {{ authors | markdown }}*
+
+{% for highlight in highlights %}
+{% if highlight.end_of_chapter %}
+<!-- end of chapter -->
+
+{% endif %}
+{% if highlight.chapter %}
+## {{ highlight.chapter | markdown }}
+
+{% endif %}
+---
+{% if highlight.quote %}
+{{ highlight.quote | blockquote }}
+
+{% endif %}
+{% if highlight.note %}
+{{ highlight.note | paragraph }}
+
+{% endif %}
+{% if highlight.location.link %}
+[Location {{ highlight.location.value }}]({{ highlight.location.link | destination }})
+{% else %}
+Location {{ highlight.location.value }}
+{% endif %}
+
+{% set details = [
+  'Added ' ~ highlight.created[:10] if highlight.created,
+  'Updated ' ~ highlight.modified[:10]
+    if highlight.modified and highlight.modified[:10] != (highlight.created or '')[:10],
+  highlight.color | capitalize if highlight.color,
+] | select | list %}
+{% if details %}
+{{ details | join(' · ') }}
+
+{% endif %}
+{% if highlight.tags %}
+{{ highlight.tags | map('tag') | join(' ') }}
+
+{% endif %}
+{% endfor %}
+";
+
+const DEFAULT_TEMPLATE_NAME: &str = "default.md";
+
+#[derive(Serialize)]
+struct BookContext<'a> {
+    title: &'a str,
+    authors: &'a str,
+    asin: Option<&'a str>,
+    highlights: Vec<HighlightContext>,
+}
+
+#[derive(Serialize)]
+struct HighlightContext {
+    chapter: Option<String>,
+    end_of_chapter: bool,
+    kind: &'static str,
+    quote: Option<String>,
+    note: Option<String>,
+    location: Location,
+    created: Option<Timestamp>,
+    modified: Option<Timestamp>,
+    color: Option<Color>,
+    tags: Vec<String>,
+}
+
+impl From<Highlight> for HighlightContext {
+    fn from(highlight: Highlight) -> Self {
+        let location = highlight.location();
+        let created = highlight.created();
+        let modified = highlight.modified();
+        let color = highlight.color();
+        let tags = highlight.tags().to_vec();
+        let (kind, quote, note) = match highlight {
+            Highlight::Quote { quote, .. } => ("quote", Some(quote), None),
+            Highlight::Note { note, .. } => ("note", None, Some(note)),
+            Highlight::Comment { quote, note, .. } => ("comment", Some(quote), Some(note)),
+        };
+        HighlightContext {
+            chapter: None,
+            end_of_chapter: false,
+            kind,
+            quote,
+            note,
+            location,
+            created,
+            modified,
+            color,
+            tags,
+        }
+    }
+}
+
+/// Renders book highlights with the template.
+pub struct TemplateRenderer {
+    environment: Environment<'static>,
+    name: String,
+}
+
+impl TemplateRenderer {
+    /// Creates the renderer from the template source.
+    ///
+    /// The name selects the escaping of the values by the extension.
+    /// Fails when the template has a syntax error.
+    pub fn new(name: impl Into<String>, source: impl Into<String>) -> Result<Self, HighlightError> {
+        let name = name.into();
+        let mut environment = Environment::new();
+        let syntax = SyntaxConfig::builder()
+            .trim_blocks(true)
+            .lstrip_blocks(true)
+            .build()
+            .map_err(invalid_template)?;
+        environment.set_syntax(syntax);
+        environment.add_filter("markdown", |text: &str| escape::escape_inline(text));
+        environment.add_filter("paragraph", |text: &str| escape::paragraph(text));
+        environment.add_filter("blockquote", |text: &str| escape::blockquote(text));
+        environment.add_filter("destination", |url: &str| escape::destination(url));
+        environment.add_filter("tag", |tag: &str| escape::tag(tag));
+        environment
+            .add_template_owned(name.clone(), source.into())
+            .map_err(invalid_template)?;
+        Ok(TemplateRenderer { environment, name })
+    }
+
+    /// Reads the template from the file.
+    pub fn from_file(path: &Path) -> Result<Self, HighlightError> {
+        let source = fs::read_to_string(path).map_err(|e| {
+            HighlightError::io(format!("cannot read template: {}", path.display()), e)
+        })?;
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(DEFAULT_TEMPLATE_NAME);
+        TemplateRenderer::new(name, source)
+    }
+}
+
+impl Render for TemplateRenderer {
+    /// Renders highlights with the template.
+    ///
+    /// ## Example:
+    ///
+    /// ```
+    /// # use std::io::stdout;
+    /// # use highlights::highlights::examples;
+    /// # use highlights::render::template::TemplateRenderer;
+    /// # use highlights::render::Render;
+    /// let book = examples::chess_book();
+    /// let mut renderer = TemplateRenderer::new(
+    ///     "quotes.md",
+    ///     "{% for h in highlights %}{{ h.quote }}\n{% endfor %}",
+    /// )
+    /// .unwrap();
+    /// renderer.render(&book, stdout()).unwrap();
+    /// ```
+    fn render(&mut self, book: &Book, mut out: impl Write) -> Result<(), HighlightError> {
+        let context = BookContext {
+            title: book.title(),
+            authors: book.authors(),
+            asin: book.asin(),
+            highlights: highlights(book),
+        };
+        let output = self
+            .environment
+            .get_template(&self.name)
+            .and_then(|template| template.render(Serde(&context)))
+            .map_err(invalid_template)?;
+        out.write_all(output.as_bytes())
+            .map_err(|e| HighlightError::io("cannot write template output", e))
+    }
+}
+
+impl Default for TemplateRenderer {
+    fn default() -> Self {
+        TemplateRenderer::new(DEFAULT_TEMPLATE_NAME, DEFAULT_TEMPLATE)
+            .expect("default template is valid")
+    }
+}
+
+/// Highlights with the chapter title set on the first highlight of every chapter
+/// and the end of chapter flag set on the first highlight without a chapter after one.
+///
+/// Chapters follow the rules of [`visit_book`](crate::render::visit_book):
+/// they are only kept when the highlights go by location,
+/// and a highlight without a chapter closes the previous one.
+fn highlights(book: &Book) -> Vec<HighlightContext> {
+    let grouped = is_location_order(&book.highlights());
+    let mut chapter: Option<String> = None;
+    let mut highlights = vec![];
+    for highlight in book.highlights() {
+        let mut context = HighlightContext::from(highlight);
+        let title = context.location.chapter().filter(|_| grouped);
+        if chapter.as_deref() != title {
+            context.end_of_chapter = chapter.is_some() && title.is_none();
+            chapter = title.map(str::to_owned);
+            context.chapter = chapter.clone();
+        }
+        highlights.push(context);
+    }
+    highlights
+}
+
+fn invalid_template(error: minijinja::Error) -> HighlightError {
+    HighlightError::format(
+        "invalid template",
+        std::io::Error::new(ErrorKind::InvalidData, error.to_string()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::highlights::examples;
+    use crate::render::markdown::MarkdownRenderer;
+
+    #[test]
+    fn default_template_matches_markdown() {
+        let book = Book::new(
+            "Title",
+            "Author",
+            [
+                Highlight::quote("Quote", Location::new(1, "http://book.org/quotes/1")),
+                Highlight::note("Note", Location::new(2, "")),
+                Highlight::comment("Quote", "Note", Location::new(3, "http://book.org/3")),
+                Highlight::comment(
+                    "# Heading\n*bold*\n\n1984. [link](x)",
+                    "- item\n\n_note_ & <b>",
+                    Location::new(4, "http://book.org/a (4)"),
+                ),
+            ],
+        );
+        let chapters = Book::new(
+            "Title",
+            "Author",
+            [
+                Highlight::quote("Quote", Location::new(1, "").with_chapter("*One*")),
+                Highlight::note("Note", Location::new(2, "").with_chapter("*One*")),
+                Highlight::quote("Quote", Location::new(3, "").with_chapter("Two")),
+            ],
+        );
+
+        let out_of_order = Book::new(
+            "Title",
+            "Author",
+            [
+                Highlight::quote("Quote", Location::new(3, "").with_chapter("Two")),
+                Highlight::note("Note", Location::new(1, "").with_chapter("One")),
+                Highlight::quote("Quote", Location::new(2, "").with_chapter("One")),
+            ],
+        );
+
+        let without_chapter = Book::new(
+            "Title",
+            "Author",
+            [
+                Highlight::quote("Quote", Location::new(1, "").with_chapter("One")),
+                Highlight::note("Note", Location::new(2, "")),
+                Highlight::quote("Quote", Location::new(3, "").with_chapter("One")),
+            ],
+        );
+
+        let details = Book::new(
+            "Title",
+            "Author",
+            [
+                Highlight::quote("Quote", Location::new(1, ""))
+                    .with_created(Timestamp::parse("2022-10-04 10:12:01").unwrap())
+                    .with_modified(Timestamp::parse("2022-10-05 08:00:00").unwrap())
+                    .with_color(Color::Yellow)
+                    .with_tags(["chess", "to read"]),
+                Highlight::note("Note", Location::new(2, ""))
+                    .with_created(Timestamp::parse("2022-10-04 10:12:01").unwrap())
+                    .with_modified(Timestamp::parse("2022-10-04 11:00:00").unwrap()),
+                Highlight::note("Note", Location::new(3, ""))
+                    .with_modified(Timestamp::parse("2022-10-04 11:00:00").unwrap())
+                    .with_tags(["idea"]),
+            ],
+        );
+
+        for book in [
+            book,
+            chapters,
+            out_of_order,
+            without_chapter,
+            details,
+            examples::chess_book(),
+            Book::new("Title", "Author", []),
+        ] {
+            assert_eq!(
+                MarkdownRenderer.as_string(&book),
+                TemplateRenderer::default().as_string(&book)
+            );
+        }
+    }
+
+    #[test]
+    fn expose_highlight_kind() {
+        let book = Book::new(
+            "Title",
+            "Author",
+            [
+                Highlight::quote("Quote", Location::new(1, "")),
+                Highlight::note("Note", Location::new(2, "")),
+                Highlight::comment("Quote", "Note", Location::new(3, "")),
+            ],
+        );
+        let mut renderer = TemplateRenderer::new(
+            "kinds.txt",
+            "{% for h in highlights %}{{ h.kind }}@{{ h.location.value }} {% endfor %}",
+        )
+        .unwrap();
+
+        assert_eq!("quote@1 note@2 comment@3 ", renderer.as_string(&book));
+    }
+
+    #[test]
+    fn escape_html_templates() {
+        let book = Book::new("<Title>", "Author", []);
+        let mut renderer = TemplateRenderer::new("notes.html", "<h1>{{ title }}</h1>").unwrap();
+
+        assert_eq!("<h1>&lt;Title&gt;</h1>", renderer.as_string(&book));
+    }
+
+    #[test]
+    fn reject_invalid_template() {
+        let result = TemplateRenderer::new("broken.md", "{% for h in highlights %}");
+
+        match result {
+            Err(HighlightError::InvalidFormat(message, _)) => {
+                assert_eq!("invalid template", message)
+            }
+            _ => panic!("expected invalid template error"),
+        }
+    }
+}
@@ -1,8 +1,12 @@
 use assert_cmd::Command;
+use assert_fs::prelude::*;
+use predicates::prelude::*;
 
 const VALID_INPUT_PATH: &str = "tests/rustonomicon.json";
 const MISSING_INPUT_PATH: &str = "tests/file-does-not-exist.json";
 const INVALID_INPUT_PATH: &str = "tests/invalid_bookcision.json";
+const CLIPPINGS_INPUT_PATH: &str = "tests/my_clippings.txt";
+const TWO_BOOKS_INPUT_PATH: &str = "tests/two_books.txt";
 
 #[test]
 fn default_conversion() -> Result<(), Box<dyn std::error::Error>> {
@@ -63,6 +67,43 @@ fn invalid_input() -> Result<(), Box<dyn std::error::Error>> {
     let output_file = assert_fs::NamedTempFile::new("rustonomicon.md")?;
 
     cmd.arg(INVALID_INPUT_PATH).arg(output_file.path());
+    cmd.assert()
+        .failure()
+        .code(65)
+        .stderr(predicates::str::contains("invalid bookcision json file"))
+        .stderr(predicates::str::contains(
+            " --> tests/invalid_bookcision.json:6:5\n",
+        ))
+        .stderr(predicates::str::contains(
+            "6 |     {\n  |     ^ empty highlight",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn lenient_input() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("highlights")?;
+
+    cmd.arg("--lenient").arg(INVALID_INPUT_PATH);
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::contains("Note to self"))
+        .stderr(predicates::str::contains(
+            "warning: entry 1: empty highlight, skipped",
+        ))
+        .stderr(predicates::str::contains(
+            "1 broken entry skipped or repaired",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn strict_overrides_lenient() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("highlights")?;
+
+    cmd.args(["--lenient", "--strict"]).arg(INVALID_INPUT_PATH);
     cmd.assert()
         .failure()
         .code(65)
@@ -70,3 +111,446 @@ fn invalid_input() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn detect_input_format() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("highlights")?;
+
+    cmd.arg(CLIPPINGS_INPUT_PATH);
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::contains("# Rustonomicon"))
+        .stdout(predicates::str::contains("Helpful indeed"));
+
+    Ok(())
+}
+
+#[test]
+fn explicit_input_format() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("highlights")?;
+
+    cmd.arg("--from").arg("clippings").arg(VALID_INPUT_PATH);
+    cmd.assert()
+        .failure()
+        .code(65)
+        .stderr(predicates::str::contains("invalid kindle clippings file"));
+
+    Ok(())
+}
+
+#[test]
+fn unknown_input_format() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("highlights")?;
+
+    cmd.arg("--from").arg("pdf").arg(VALID_INPUT_PATH);
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("supported formats: bookcision"));
+
+    Ok(())
+}
+
+#[test]
+fn explicit_output_format() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("highlights")?;
+
+    cmd.arg("--to").arg("readwise").arg(VALID_INPUT_PATH);
+    cmd.assert().success().stdout(predicates::str::starts_with(
+        "Highlight,Title,Author,URL,Note,Location,Date",
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn readwise_output_with_several_books() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("highlights")?;
+
+    cmd.args(["--to", "readwise", TWO_BOOKS_INPUT_PATH]);
+    let output = cmd.assert().success().get_output().stdout.clone();
+
+    let csv = String::from_utf8(output)?;
+    assert_eq!(1, csv.matches("Highlight,Title").count());
+    assert!(csv.contains("How Life Imitates Chess"));
+    assert!(csv.contains("Rustonomicon"));
+
+    Ok(())
+}
+
+#[test]
+fn output_format_from_extension() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("highlights")?;
+    let output_file = assert_fs::NamedTempFile::new("rustonomicon.csv")?;
+
+    cmd.arg(VALID_INPUT_PATH).arg(output_file.path());
+    cmd.assert().success();
+    output_file.assert(predicates::str::starts_with("Highlight,Title,Author"));
+
+    Ok(())
+}
+
+#[test]
+fn unknown_output_format() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("highlights")?;
+
+    cmd.arg("--to").arg("pdf").arg(VALID_INPUT_PATH);
+    cmd.assert()
+        .failure()
+        .code(70)
+        .stderr(predicates::str::contains("unknown output format 'pdf'"));
+
+    Ok(())
+}
+
+#[test]
+fn html_output_with_stylesheet() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new()?;
+    let stylesheet = temp.child("notes.css");
+    stylesheet.write_str("body { color: teal; }")?;
+    let output = temp.child("notes.html");
+    let mut cmd = Command::cargo_bin("highlights")?;
+
+    cmd.arg("--css")
+        .arg(stylesheet.path())
+        .arg(VALID_INPUT_PATH)
+        .arg(output.path());
+    cmd.assert().success();
+
+    output.assert(predicates::str::starts_with("<!DOCTYPE html>"));
+    output.assert(predicates::str::contains("body { color: teal; }"));
+    temp.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn html_output_with_several_books() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("highlights")?;
+
+    cmd.args(["--to", "html", TWO_BOOKS_INPUT_PATH]);
+    let output = cmd.assert().success().get_output().stdout.clone();
+
+    let html = String::from_utf8(output)?;
+    assert_eq!(1, html.matches("<!DOCTYPE html>").count());
+    assert_eq!(2, html.matches("<article class=\"book\">").count());
+
+    Ok(())
+}
+
+#[test]
+fn org_output_with_several_books() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("highlights")?;
+
+    cmd.args(["--to", "org", TWO_BOOKS_INPUT_PATH]);
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::starts_with(
+            "#+TITLE: Highlights
+",
+        ))
+        .stdout(predicates::str::contains("\n* Rustonomicon\n"))
+        .stdout(predicates::str::contains("\n* How Life Imitates Chess\n"));
+
+    Ok(())
+}
+
+#[test]
+fn json_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new()?;
+    let json = temp.child("notes.json");
+    Command::cargo_bin("highlights")?
+        .arg(VALID_INPUT_PATH)
+        .arg(json.path())
+        .assert()
+        .success();
+    json.assert(predicates::str::contains("\"version\": 1"));
+
+    let mut cmd = Command::cargo_bin("highlights")?;
+    cmd.arg(json.path());
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::starts_with("# Rustonomicon"));
+    temp.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn markdown_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("highlights")?;
+
+    cmd.args(["--to", "json", "samples/default_markdown.md"]);
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::contains("\"type\": \"comment\""))
+        .stdout(predicates::str::contains("\"value\": 693"));
+
+    Ok(())
+}
+
+#[test]
+fn anki_package_output() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new()?;
+    let package = temp.child("deck.apkg");
+    let mut cmd = Command::cargo_bin("highlights")?;
+
+    cmd.arg("--cloze").arg(VALID_INPUT_PATH).arg(package.path());
+    cmd.assert().success();
+
+    let content = std::fs::read(package.path())?;
+    assert!(content.starts_with(b"PK"));
+    temp.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn anki_package_with_several_books() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new()?;
+    let package = temp.child("decks.apkg");
+    let mut cmd = Command::cargo_bin("highlights")?;
+
+    cmd.arg(TWO_BOOKS_INPUT_PATH).arg(package.path());
+    cmd.assert().success();
+
+    let content = std::fs::read(package.path())?;
+    let end_of_archive = content.windows(4).filter(|w| w == b"PK\x05\x06").count();
+    assert_eq!(1, end_of_archive);
+    temp.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn render_with_template() -> Result<(), Box<dyn std::error::Error>> {
+    let template = assert_fs::NamedTempFile::new("quotes.txt")?;
+    template.write_str(
+        "{{ title }}\n{% for h in highlights %}{{ h.kind }}: {{ h.location.value }}\n{% endfor %}",
+    )?;
+    let mut cmd = Command::cargo_bin("highlights")?;
+
+    cmd.arg("--template")
+        .arg(template.path())
+        .arg(VALID_INPUT_PATH);
+    cmd.assert()
+        .success()
+        .stdout("Rustonomicon\nquote: 157\ncomment: 305\nnote: 693\n");
+    template.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn invalid_template() -> Result<(), Box<dyn std::error::Error>> {
+    let template = assert_fs::NamedTempFile::new("broken.md")?;
+    template.write_str("{% for h in highlights %}")?;
+    let mut cmd = Command::cargo_bin("highlights")?;
+
+    cmd.arg("--template")
+        .arg(template.path())
+        .arg(VALID_INPUT_PATH);
+    cmd.assert()
+        .failure()
+        .code(65)
+        .stderr(predicates::str::contains("invalid template"));
+    template.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn obsidian_output_with_tags() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("highlights")?;
+
+    cmd.args(["--to", "obsidian", "--tag", "rust", "--no-callouts"])
+        .arg(VALID_INPUT_PATH);
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::starts_with(
+            "---\ntitle: \"Rustonomicon\"\n",
+        ))
+        .stdout(predicates::str::contains("tags:\n  - \"rust\"\n"))
+        .stdout(predicates::str::contains("asin: \"c3dd2fe1-"))
+        .stdout(predicates::str::contains(" ^loc-157\n"));
+
+    Ok(())
+}
+
+#[test]
+fn batch_conversion() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new()?;
+    let mut cmd = Command::cargo_bin("highlights")?;
+
+    cmd.arg("--out-dir")
+        .arg(temp.path())
+        .args([VALID_INPUT_PATH, CLIPPINGS_INPUT_PATH]);
+    cmd.assert().success();
+
+    let book = temp.child("The Rust Community - Rustonomicon.md");
+    book.assert(predicates::str::starts_with("# Rustonomicon"));
+    book.assert(predicates::str::contains("Helpful indeed"));
+    temp.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn batch_conversion_with_name_pattern() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new()?;
+    let mut cmd = Command::cargo_bin("highlights")?;
+
+    cmd.args(["--to", "org", "--name", "{title}/notes.{ext}", "--out-dir"])
+        .arg(temp.path())
+        .arg(VALID_INPUT_PATH);
+    cmd.assert().success();
+
+    temp.child("Rustonomicon/notes.org")
+        .assert(predicates::str::starts_with("#+TITLE: Rustonomicon"));
+    temp.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn too_many_paths() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("highlights")?;
+
+    cmd.args([VALID_INPUT_PATH, CLIPPINGS_INPUT_PATH, "notes.md"]);
+    cmd.assert()
+        .failure()
+        .code(70)
+        .stderr(predicates::str::contains("use --out-dir"));
+
+    Ok(())
+}
+
+#[test]
+fn filter_highlights() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("highlights")?;
+
+    cmd.args(["--only", "quotes,comments", "--location", "200.."])
+        .args(["--grep", "(?i)interesting", "--min-length", "10"])
+        .arg(VALID_INPUT_PATH);
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::contains("interesting quote"))
+        .stdout(predicates::str::contains("helpful quote").not())
+        .stdout(predicates::str::contains("lint your project").not());
+
+    Ok(())
+}
+
+#[test]
+fn invalid_filter() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("highlights")?;
+
+    cmd.args(["--location", "400..100", VALID_INPUT_PATH]);
+    cmd.assert()
+        .failure()
+        .stderr(predicates::str::contains("starts after it ends"));
+
+    Ok(())
+}
+
+#[test]
+fn sort_highlights() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("highlights")?;
+
+    cmd.args([
+        "--sort",
+        "length",
+        "--reverse",
+        "--to",
+        "json",
+        VALID_INPUT_PATH,
+    ]);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let output = String::from_utf8(output)?;
+
+    let comment = output.find("interesting quote").unwrap();
+    let note = output.find("lint your project").unwrap();
+    let quote = output.find("helpful quote").unwrap();
+    assert!(comment < note && note < quote);
+
+    Ok(())
+}
+
+#[test]
+fn merge_duplicate_highlights() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new()?;
+    let clippings = temp.child("My Clippings.txt");
+    clippings.write_str(
+        "Rustonomicon (The Rust Community)\n\
+         - Your Highlight on page 3 | Location 157-158 | Added on Tuesday, 4 October 2022 10:02:44\n\n\
+         This is a helpful quote\n\
+         ==========\n\
+         Rustonomicon (The Rust Community)\n\
+         - Your Highlight on page 3 | Location 157-159 | Added on Tuesday, 4 October 2022 10:04:12\n\n\
+         This is a helpful quote from the Rustonomicon\n\
+         ==========\n",
+    )?;
+    let mut cmd = Command::cargo_bin("highlights")?;
+
+    cmd.arg("--dedup").arg(clippings.path());
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::contains("from the Rustonomicon"))
+        .stdout(predicates::str::contains("quote\n").not())
+        .stderr(predicates::str::contains(
+            "merged: Rustonomicon: location 157: merged overlapping quote \"This is a helpful quote\"",
+        ))
+        .stderr(predicates::str::contains("1 highlight merged in Rustonomicon"));
+    temp.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn update_existing_notes() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new()?;
+    let notes = temp.child("notes.md");
+    Command::cargo_bin("highlights")?
+        .args(["--only", "quotes", VALID_INPUT_PATH])
+        .arg(notes.path())
+        .assert()
+        .success();
+    let written = std::fs::read_to_string(notes.path())? + "## My thoughts\n\nGreat book\n";
+    notes.write_str(&written)?;
+
+    Command::cargo_bin("highlights")?
+        .args(["--update", "--dry-run", VALID_INPUT_PATH])
+        .arg(notes.path())
+        .assert()
+        .success()
+        .stdout(predicates::str::contains(
+            "would add Location 305 after Location 157",
+        ));
+    notes.assert(written.as_str());
+
+    Command::cargo_bin("highlights")?
+        .args(["--update", VALID_INPUT_PATH])
+        .arg(notes.path())
+        .assert()
+        .success()
+        .stderr(predicates::str::contains("2 new highlights added"));
+    notes.assert(predicates::str::contains("interesting quote"));
+    notes.assert(predicates::str::ends_with(
+        "location=693)\n\n## My thoughts\n\nGreat book\n",
+    ));
+    temp.close()?;
+
+    Ok(())
+}
+
+#[test]
+fn update_needs_output_file() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("highlights")?;
+
+    cmd.args(["--update", VALID_INPUT_PATH]);
+    cmd.assert()
+        .failure()
+        .code(70)
+        .stderr(predicates::str::contains("--update needs the file"));
+
+    Ok(())
+}
@@ -9,7 +9,7 @@ fn convert_bookcision_json_to_markdown() {
     let input_file = File::open("tests/rustonomicon.json").unwrap();
     let book = JsonBook::from_reader(input_file).unwrap().into();
     let mut output: Vec<u8> = vec![];
-    let mut renderer = MarkdownRenderer::default();
+    let mut renderer = MarkdownRenderer;
     renderer.render(&book, &mut output).unwrap();
 
     let markdown = String::from_utf8(output).unwrap();
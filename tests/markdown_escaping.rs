@@ -0,0 +1,144 @@
+use std::fs::File;
+
+use highlights::highlights::{Book, Highlight};
+use highlights::input::json::JsonDocuments;
+use highlights::input::HighlightsRead;
+use highlights::render::markdown::MarkdownRenderer;
+use highlights::render::template::TemplateRenderer;
+use highlights::render::Render;
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+
+#[derive(Debug, PartialEq)]
+enum Block {
+    Heading(String),
+    Paragraph(String),
+    Quote(String),
+    Rule,
+}
+
+#[test]
+fn markdown_keeps_tricky_highlights() {
+    let book = tricky_book();
+
+    let markdown = MarkdownRenderer.as_string(&book);
+
+    assert_round_trip(&book, &markdown);
+}
+
+#[test]
+fn default_template_keeps_tricky_highlights() {
+    let book = tricky_book();
+
+    let markdown = TemplateRenderer::default().as_string(&book);
+
+    assert_round_trip(&book, &markdown);
+}
+
+#[test]
+fn markdown_escapes_table_pipes() {
+    let book = tricky_book();
+
+    let markdown = MarkdownRenderer.as_string(&book);
+
+    assert!(markdown.contains("> Name \\| Moves\\\n> \\|---\\|---\\|\n"));
+    assert!(!markdown.contains("|---|"), "{}", markdown);
+}
+
+fn assert_round_trip(book: &Book, markdown: &str) {
+    let blocks = parse(markdown);
+    assert_eq!(Block::Heading(book.title().to_owned()), blocks[0]);
+    assert_eq!(
+        Block::Paragraph(format!("<em>by {}</em>", book.authors())),
+        blocks[1]
+    );
+
+    let rendered: Vec<&[Block]> = blocks[2..].split(|block| *block == Block::Rule).collect();
+    let highlights = book.highlights();
+    assert_eq!(highlights.len() + 1, rendered.len(), "{}", markdown);
+    for (highlight, blocks) in highlights.iter().zip(&rendered[1..]) {
+        let (quote, note) = match highlight {
+            Highlight::Quote { quote, .. } => (Some(quote), None),
+            Highlight::Note { note, .. } => (None, Some(note)),
+            Highlight::Comment { quote, note, .. } => (Some(quote), Some(note)),
+        };
+        let mut blocks = blocks.iter().peekable();
+        if let Some(quote) = quote {
+            assert_eq!(Some(&Block::Quote(normalize(quote))), blocks.next());
+        }
+        let mut paragraphs: Vec<String> = blocks
+            .map(|block| match block {
+                Block::Paragraph(text) => text.clone(),
+                other => panic!("expected paragraph, got {:?}", other),
+            })
+            .collect();
+        let location = paragraphs.pop().unwrap();
+        if let Some(note) = note {
+            assert_eq!(normalize(note), paragraphs.join("\n\n"));
+        } else {
+            assert!(paragraphs.is_empty(), "{:?}", paragraphs);
+        }
+        let location_value = highlight.location().value();
+        let expected = match highlight.location().link() {
+            "" => format!("Location {}", location_value),
+            link => format!("<a href=\"{}\">Location {}</a>", link, location_value),
+        };
+        assert_eq!(expected, location);
+    }
+}
+
+/// Text of the blocks with the inline markup spelled out, so the markup produced
+/// by unescaped text does not pass for the original characters.
+fn parse(markdown: &str) -> Vec<Block> {
+    let options = Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES;
+    let mut blocks = vec![];
+    let mut text = String::new();
+    let mut quote: Option<Vec<String>> = None;
+    for event in Parser::new_ext(markdown, options) {
+        match event {
+            Event::Start(Tag::BlockQuote(_)) => quote = Some(vec![]),
+            Event::End(TagEnd::BlockQuote(_)) => {
+                blocks.push(Block::Quote(quote.take().unwrap().join("\n\n")))
+            }
+            Event::End(TagEnd::Paragraph) => {
+                let paragraph = std::mem::take(&mut text);
+                match quote.as_mut() {
+                    Some(paragraphs) => paragraphs.push(paragraph),
+                    None => blocks.push(Block::Paragraph(paragraph)),
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                blocks.push(Block::Heading(std::mem::take(&mut text)))
+            }
+            Event::Rule => blocks.push(Block::Rule),
+            Event::Text(value) => text.push_str(&value),
+            Event::HardBreak => text.push('\n'),
+            Event::SoftBreak => text.push(' '),
+            Event::Start(Tag::Emphasis) => text.push_str("<em>"),
+            Event::End(TagEnd::Emphasis) => text.push_str("</em>"),
+            Event::Start(Tag::Link { dest_url, .. }) => {
+                text.push_str(&format!("<a href=\"{}\">", dest_url))
+            }
+            Event::End(TagEnd::Link) => text.push_str("</a>"),
+            Event::Start(Tag::Paragraph) | Event::Start(Tag::Heading { .. }) => {}
+            other => text.push_str(&format!("<{:?}>", other)),
+        }
+    }
+    blocks
+}
+
+/// Expected text after rendering, with trimmed lines and single blank lines between paragraphs.
+fn normalize(text: &str) -> String {
+    let lines: Vec<&str> = text.lines().map(str::trim).collect();
+    lines
+        .split(|line| line.is_empty())
+        .filter(|paragraph| !paragraph.is_empty())
+        .map(|paragraph| paragraph.join("\n"))
+        .collect::<Vec<String>>()
+        .join("\n\n")
+}
+
+fn tricky_book() -> Book {
+    let input = File::open("tests/tricky_highlights.json").unwrap();
+    let books: Vec<Book> = JsonDocuments::from_reader(input).unwrap().into();
+    books.into_iter().next().unwrap()
+}